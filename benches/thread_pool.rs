@@ -0,0 +1,102 @@
+//! 比较共享队列和工作窃取两种调度方式，负载仿照 src/thread/atomic.rs：大量很小的任务争抢同一个原子变量。
+//! 运行：cargo bench --bench thread_pool
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rust_web::pool::{Scheduler, ThreadPool};
+
+const N_JOBS: u64 = 1_000_000;
+const N_THREADS: usize = 10;
+const N_PARENTS: u64 = 1_000;
+
+static R: AtomicU64 = AtomicU64::new(0);
+
+type Workload = fn(Scheduler) -> Duration;
+
+fn build(scheduler: Scheduler) -> ThreadPool {
+    ThreadPool::builder()
+        .size(N_THREADS)
+        .scheduler(scheduler)
+        .build()
+        .unwrap()
+}
+
+// 主线程提交所有任务
+fn single_submitter(scheduler: Scheduler) -> Duration {
+    R.store(0, Ordering::SeqCst);
+    let s = Instant::now();
+    let pool = build(scheduler);
+    for _ in 0..N_JOBS {
+        pool.execute(|| {
+            R.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+    pool.shutdown();
+    assert_eq!(N_JOBS, R.load(Ordering::SeqCst));
+    s.elapsed()
+}
+
+// N_THREADS 个线程同时提交任务
+fn many_submitters(scheduler: Scheduler) -> Duration {
+    R.store(0, Ordering::SeqCst);
+    let s = Instant::now();
+    let pool = Arc::new(build(scheduler));
+    let submitters: Vec<_> = (0..N_THREADS)
+        .map(|_| {
+            let pool = Arc::clone(&pool);
+            thread::spawn(move || {
+                for _ in 0..N_JOBS / N_THREADS as u64 {
+                    pool.execute(|| {
+                        R.fetch_add(1, Ordering::Relaxed);
+                    });
+                }
+            })
+        })
+        .collect();
+    for submitter in submitters {
+        submitter.join().unwrap();
+    }
+    pool.shutdown();
+    assert_eq!(N_JOBS, R.load(Ordering::SeqCst));
+    s.elapsed()
+}
+
+// 任务在工作线程中再提交子任务
+fn nested(scheduler: Scheduler) -> Duration {
+    R.store(0, Ordering::SeqCst);
+    let s = Instant::now();
+    let pool = Arc::new(build(scheduler));
+    for _ in 0..N_PARENTS {
+        let inner = Arc::clone(&pool);
+        pool.execute(move || {
+            for _ in 0..N_JOBS / N_PARENTS {
+                inner.execute(|| {
+                    R.fetch_add(1, Ordering::Relaxed);
+                });
+            }
+        });
+    }
+    // 子任务是在工作线程中提交的，全部执行完才能停机
+    while R.load(Ordering::SeqCst) < N_JOBS {
+        thread::sleep(Duration::from_millis(1));
+    }
+    pool.shutdown();
+    s.elapsed()
+}
+
+fn main() {
+    let workloads: [(&str, Workload); 3] = [
+        ("single submitter", single_submitter),
+        ("many submitters", many_submitters),
+        ("nested", nested),
+    ];
+    println!("{} jobs, {} workers", N_JOBS, N_THREADS);
+    for (name, workload) in workloads {
+        for scheduler in [Scheduler::Shared, Scheduler::WorkStealing] {
+            println!("{:<18} {:<14} {:?}", name, format!("{:?}", scheduler), workload(scheduler));
+        }
+    }
+}
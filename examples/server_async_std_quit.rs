@@ -1,11 +1,12 @@
 #![allow(dead_code, unused, unused_imports)]
 
-use std::time::Duration;
+use std::sync::Arc;
 use async_std::task::spawn;
-use async_std::task::sleep;
-use async_std::io::{Result, Error, ErrorKind, BufReader, copy, prelude::*};
+use async_std::io::Result;
 use async_std::channel::unbounded as channel;
-use async_std::fs::File;
+use rust_web::http::KeepAlive;
+use rust_web::web::adapter::async_std::serve_connection;
+use rust_web::web::{FileService, Signal, StaticFiles};
 use async_std::net::{TcpListener, TcpStream};
 use async_std::prelude::{Future, FutureExt};
 
@@ -25,7 +26,7 @@ async fn main() -> Result<()> {
     //         while let Ok((stream, addr)) = listener.accept().await {
     //             let kill_switch = kill_switch.clone();
     //             spawn(async move {
-    //                 if let Ok(RequestResult::Quit) = handle_connection(stream).await {
+    //                 if let Ok(Some(Signal::Quit)) = handle_connection(stream).await {
     //                     kill_switch.send(()).await;
     //                 }
     //             });
@@ -41,54 +42,10 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-enum RequestResult {
-    Ok,
-    Quit,
-}
-
-async fn handle_connection(mut stream: TcpStream) -> Result<RequestResult> {
-    let mut str = String::new();
-    BufReader::new(&mut stream).read_line(&mut str).await?;
-    println!("request: {}", str.trim());
-    let strsubs: Vec<_> = str.split(" ").collect();
-    if strsubs.len() < 3 {
-        return Err(Error::from(ErrorKind::InvalidInput));
-    }
-    let method = strsubs[0];
-    let path = strsubs[1];
-    println!("method: {method} , path: {path}");
-
-    let (path, query) = match path.find("?") {
-        Some(pos) => (&path[..pos], &path[(pos+1)..]),
-        None => (path, ""),
-    };
-
-    if query == "sleep" {
-        sleep(Duration::new(4, 0)).await;
-    }
-
-    if path == "/" {
-        stream.write("HTTP/1.1 200 OK\r\n\r\n<html><body>Welcome async_std Server</body></html>".as_bytes()).await?;
-    } else {
-        let relative_path = match path.strip_prefix("/") {
-            Some(p) => p,
-            None => path,
-        };
-        match File::open(relative_path).await {
-            Ok(mut f) => {
-                stream.write("HTTP/1.1 200 OK\r\n\r\n".as_bytes()).await?;
-                copy(&mut f, &mut stream).await?;
-            }
-            Err(err) => {
-                eprintln!("{}", err);
-                stream.write(format!("HTTP/1.1 404 NOT FOUND\r\n\r\n<html><body>Not Found {}</body></html>", path).as_bytes()).await?;
-            }
-        }
-    }
-    stream.flush().await?;
-
-    if query == "quit" {
-        return Ok(RequestResult::Quit);
-    }
-    return Ok(RequestResult::Ok);
+async fn handle_connection(stream: TcpStream) -> Result<Option<Signal>> {
+    // 拒绝 `..`、点文件和指向当前目录之外的符号链接
+    let service = FileService::new(StaticFiles::new("."))
+        .welcome("<html><body>Welcome async_std Server</body></html>")
+        .log_requests(true);
+    serve_connection(stream, Arc::new(service), KeepAlive::default()).await
 }
@@ -0,0 +1,35 @@
+use std::env;
+use std::process;
+
+use rust_web::web::{run_web_server, ServerConfig};
+
+/*
+使用库中的线程池 web 服务（rust_web::web）：
+1.配置从命令行参数指定的 TOML 文件读取，没有参数时读取环境变量 RUST_WEB_CONFIG 指定的文件，都没有时使用默认配置
+2.RUST_WEB_PORT、RUST_WEB_WORKERS、RUST_WEB_DOC_ROOT 等环境变量优先于配置文件，见 ServerConfig 的文档
+
+cargo run --example server_pool -- server.toml
+RUST_WEB_PORT=8080 cargo run --example server_pool
+*/
+
+fn main() {
+    let config = match env::args_os().nth(1) {
+        Some(path) => ServerConfig::from_file(path).and_then(|mut config| {
+            config.apply_env()?;
+            Ok(config)
+        }),
+        None => ServerConfig::load(),
+    };
+    let config = match config {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(2);
+        }
+    };
+
+    if let Err(err) = run_web_server(config) {
+        eprintln!("server error: {}", err);
+        process::exit(1);
+    }
+}
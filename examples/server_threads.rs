@@ -1,10 +1,11 @@
 // #![allow(dead_code, unused)]
-use std::thread::{sleep, spawn};
-use std::io::{Result, Error, ErrorKind, BufReader, Write, BufRead, copy};
-use std::time::Duration;
+use std::thread::spawn;
+use std::io::Result;
 use std::sync::mpsc::channel;
-use std::fs::File;
 use std::net::{TcpListener, TcpStream};
+use rust_web::http::KeepAlive;
+use rust_web::web::adapter::blocking::{serve_connection, spawn_admin};
+use rust_web::web::{AdminConfig, AdminError, AdminService, FileService, StaticFiles};
 
 /*
 《Rust 程序设计语言》最后实现了一个多线程 web server， 说是实现了优雅停机与清理，其实只是线程池的 drop ，
@@ -13,7 +14,9 @@ use std::net::{TcpListener, TcpStream};
 那么我们来实现一个更好的 http server ，有这些功能：
 1.文件请求 http://127.0.0.1:20083/abc.html 发送当前目录下 abc.html 的内容
 2.简单 query string 处理 /?sleep /abc.html?sleep 暂停4秒再发送响应
-3.正确的退出 处理 /?quit /abc.html?quit 会退出程序
+3.正确的退出 向管理端口发送 POST /quit 会退出程序，管理端口和令牌见 rust_web::web::AdminConfig：
+  curl -X POST -H "Authorization: Bearer $TOKEN" http://127.0.0.1:20084/quit
+4.持久连接 一个连接上可以连续发送多个请求（包括流水线请求），空闲 5 秒或处理 100 个请求后关闭，客户端发送 Connection: close 时回复完立即关闭
 
 技术细节：
 1.标准库的 TcpListener 是没有什么正常的手段停止的。根据 Graceful exit TcpListener.incoming() 只有两种手段
@@ -31,6 +34,14 @@ fn main() -> Result<()> {
     let port = 20083;
     let listener = TcpListener::bind((local_host, port))?;
 
+    let admin = AdminConfig::from_env();
+    // 管理端口只支持 POST /quit，回复发完后退出
+    let admin_service = AdminService::new(&admin.token, |_| Err(AdminError::Unsupported));
+    let quit_switch = dispatch_sender.clone();
+    let _admin_loop = spawn_admin(&admin, admin_service, move || {
+        quit_switch.send(DispatchMessage::Quit).unwrap();
+    })?;
+
     let dispatch_sender1 = dispatch_sender.clone();
 
     let _accept_loop = spawn(move || {
@@ -41,14 +52,14 @@ fn main() -> Result<()> {
         }
     });
     println!("server started at http://{}:{}/ serving files in {:?}", local_host, port, std::env::current_dir().unwrap_or_default());
+    println!("{}", admin.banner());
 
     while let Ok(dispatch_message) = dispatch_receiver.recv() {
         match dispatch_message {
             DispatchMessage::Connected(stream) => { // 接受并处理来自 channel 的连接消息
-                let dispatch_sender = dispatch_sender.clone();
                 spawn(move || {
-                    if let Ok(RequestResult::Quit) = handle_connection(stream) { // 如果连接是退出命令（即：/quit），则发送退出消息，退出循环
-                        dispatch_sender.send(DispatchMessage::Quit).unwrap();
+                    if let Err(err) = handle_connection(stream) {
+                        eprintln!("handle connection failed: {}", err);
                     }
                 });
             }
@@ -66,54 +77,12 @@ enum DispatchMessage {
     Quit,
 }
 
-enum RequestResult {
-    Ok,
-    Quit,
+fn handle_connection(stream: TcpStream) -> Result<()> {
+    // 持久连接：一个连接上按顺序处理多个请求（包括流水线请求），直到客户端发送 Connection: close、空闲超时或达到最大请求数
+    // 拒绝 `..`、点文件和指向当前目录之外的符号链接，客户端接受时换成预压缩的 .br/.gz 文件
+    let service = FileService::new(StaticFiles::new(".").compression(true).precompressed(true))
+        .welcome("<html><body>Welcome Threads Server</body></html>")
+        .log_requests(true);
+    serve_connection(stream, &service, KeepAlive::default())?;
+    Ok(())
 }
-
-fn handle_connection(mut stream: TcpStream) -> Result<RequestResult> {
-    let mut str = String::new();
-    BufReader::new(&stream).read_line(&mut str)?;
-    println!("request: {}", str.trim());
-    let strsubs: Vec<_> = str.split(" ").collect();
-    if strsubs.len() < 3 {
-        return Err(Error::from(ErrorKind::InvalidInput));
-    }
-    let method = strsubs[0];
-    let path = strsubs[1];
-    println!("method: {method} , path: {path}");
-
-    let (path, query) = match path.find("?") {
-        Some(pos) => (&path[..pos], &path[(pos+1)..]),
-        None => (path, ""),
-    };
-
-    if query == "sleep" { // http://127.0.0.1:57486?sleep
-        sleep(Duration::new(4, 0));
-    }
-
-    if path == "/" { // http://127.0.0.1:57486 
-        write!(stream, "HTTP/1.1 200 OK\r\n\r\n<html><body>Welcome Threads Server</body></html>")?;
-    } else {
-        let relative_path = match path.strip_prefix("/") {
-            Some(p) => p,
-            None => path,
-        };
-        match File::open(relative_path) {
-            Ok(mut f) => {
-                write!(stream, "HTTP/1.1 200 OK\r\n\r\n")?;
-                copy(&mut f, &mut stream)?;
-            }
-            Err(err) => {
-                eprintln!("{}", err);
-                write!(stream, "HTTP/1.1 404 NOT FOUND\r\n\r\n<html><body>Not Found {}</body></html>", path)?;
-            }
-        }
-    }
-    stream.flush()?;
-
-    if query == "quit" { // http://127.0.0.1:57486?quit 
-        return Ok(RequestResult::Quit);
-    }
-    return Ok(RequestResult::Ok);
-}
\ No newline at end of file
@@ -1,9 +1,10 @@
+use std::sync::Arc;
 use tokio::task::spawn;
-use tokio::time::sleep;
-use tokio::io::{Result, Error, ErrorKind, AsyncWriteExt, BufReader, AsyncBufReadExt, copy};
-use std::time::Duration;
+use tokio::io::Result;
 use tokio::sync::mpsc::unbounded_channel as channel;
-use tokio::fs::File;
+use rust_web::http::KeepAlive;
+use rust_web::web::adapter::tokio::{serve_connection, spawn_admin};
+use rust_web::web::{AdminConfig, AdminError, AdminService, FileService, StaticFiles};
 use tokio::net::{TcpListener, TcpStream};
 
 #[tokio::main]
@@ -13,23 +14,30 @@ async fn main() -> Result<()> {
     let local_host = "127.0.0.1";
     let port = 20083;
     let listener = TcpListener::bind((local_host, port)).await?;
+    let admin = AdminConfig::from_env();
+    // 管理端口只支持 POST /quit，回复发完后退出
+    let admin_service = AdminService::new(&admin.token, |_| Err(AdminError::Unsupported));
+    let quit_switch = kill_switch.clone();
+    let _admin_loop = spawn_admin(&admin, admin_service, move || {
+        println!("kill_switch send 'quit' msg");
+        quit_switch.send("quit".to_string()).unwrap();
+    }).await?;
     let accept_loop = spawn(async move {
         while let Ok((stream, addr)) = listener.accept().await {
             println!("TcpListener accept: {} ", addr);
             let kill_switch = kill_switch.clone();
             spawn(async move {
                 println!("spawn async handle_connection");
-                if let Ok(RequestResult::Quit) = handle_connection(stream).await {
-                    println!("kill_switch send 'quit' msg");
-                    kill_switch.send("quit".to_string()).unwrap();
-                } else {
-                    println!("kill_switch send 'ok' msg");
-                    kill_switch.send("ok".to_string()).unwrap(); 
+                if let Err(err) = handle_connection(stream).await {
+                    eprintln!("handle connection failed: {}", err);
                 }
+                println!("kill_switch send 'ok' msg");
+                kill_switch.send("ok".to_string()).unwrap();
             });
         }
     });
     println!("server started at http://{}:{}/ serving files in {:?}", local_host, port, std::env::current_dir().unwrap_or_default());
+    println!("{}", admin.banner());
 
   
     // kill_switch_receiver.recv().await;
@@ -50,54 +58,11 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-enum RequestResult {
-    Ok,
-    Quit,
+async fn handle_connection(stream: TcpStream) -> Result<()> {
+    // 拒绝 `..`、点文件和指向当前目录之外的符号链接
+    let service = FileService::new(StaticFiles::new("."))
+        .welcome("<html><body>Welcome Tokio Server</body></html>")
+        .log_requests(true);
+    serve_connection(stream, Arc::new(service), KeepAlive::default()).await?;
+    Ok(())
 }
-
-async fn handle_connection(mut stream: TcpStream) -> Result<RequestResult> {
-    let mut str = String::new();
-    BufReader::new(&mut stream).read_line(&mut str).await?;
-    println!("request: {}", str.trim());
-    let strsubs: Vec<_> = str.split(" ").collect();
-    if strsubs.len() < 3 {
-        return Err(Error::from(ErrorKind::InvalidInput));
-    }
-    let method = strsubs[0];
-    let path = strsubs[1];
-    println!("method: {method} , path: {path}");
-
-    let (path, query) = match path.find("?") {
-        Some(pos) => (&path[..pos], &path[(pos+1)..]),
-        None => (path, ""),
-    };
-
-    if query == "sleep" {
-        sleep(Duration::new(4, 0)).await;
-    }
-
-    if path == "/" {
-        stream.write("HTTP/1.1 200 OK\r\n\r\n<html><body>Welcome Tokio Server</body></html>".as_bytes()).await?;
-    } else {
-        let relative_path = match path.strip_prefix("/") {
-            Some(p) => p,
-            None => path,
-        };
-        match File::open(relative_path).await {
-            Ok(mut f) => {
-                stream.write("HTTP/1.1 200 OK\r\n\r\n".as_bytes()).await?;
-                copy(&mut f, &mut stream).await?;
-            }
-            Err(err) => {
-                eprintln!("{}", err);
-                stream.write(format!("HTTP/1.1 404 NOT FOUND\r\n\r\n<html><body>Not Found {}</body></html>", path).as_bytes()).await?;
-            }
-        }
-    }
-    stream.flush().await?;
-
-    if query == "quit" {
-        return Ok(RequestResult::Quit);
-    }
-    return Ok(RequestResult::Ok);
-}
\ No newline at end of file
@@ -0,0 +1,118 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// 解析 HTTP 请求时的错误。
+/// 每种错误都对应一个响应状态码，服务端据此回复客户端，而不是 panic。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    /// 请求行格式错误
+    InvalidRequestLine,
+    /// 方法名不是合法的 token
+    InvalidMethod,
+    /// 请求目标既不是 origin-form，也不是 absolute-form 或 `*`
+    InvalidTarget,
+    /// 不支持的 HTTP 版本（只支持 HTTP/1.0 和 HTTP/1.1）
+    UnsupportedVersion,
+    /// 请求行超过长度限制
+    UriTooLong,
+    /// 请求头总长度或数量超过限制
+    HeadersTooLarge,
+    /// 请求头格式错误
+    InvalidHeader,
+    /// Content-Length 非法或多个值互相冲突
+    InvalidContentLength,
+    /// 请求体超过长度限制
+    BodyTooLarge,
+    /// chunked 编码格式错误
+    InvalidChunk,
+    /// 不支持的 Transfer-Encoding
+    UnsupportedTransferEncoding,
+    /// 路径或查询串的百分号编码非法，或解码后不是 UTF-8
+    InvalidEncoding,
+    /// 请求尚未读完连接就关闭了
+    UnexpectedEof,
+    /// 没有在 `KeepAlive::request_timeout` 内收到完整的请求
+    RequestTimeout,
+}
+
+impl ParseError {
+    /// 该错误对应的响应状态码
+    pub fn status_code(&self) -> u16 {
+        match self {
+            ParseError::UriTooLong => 414,
+            ParseError::HeadersTooLarge => 431,
+            ParseError::BodyTooLarge => 413,
+            ParseError::UnsupportedVersion => 505,
+            ParseError::UnsupportedTransferEncoding => 501,
+            ParseError::RequestTimeout => 408,
+            _ => 400,
+        }
+    }
+
+    /// 状态码对应的原因短语
+    pub fn reason(&self) -> &'static str {
+        super::reason_phrase(self.status_code())
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match self {
+            ParseError::InvalidRequestLine => "invalid request line",
+            ParseError::InvalidMethod => "invalid method",
+            ParseError::InvalidTarget => "invalid request target",
+            ParseError::UnsupportedVersion => "unsupported http version",
+            ParseError::UriTooLong => "request line too long",
+            ParseError::HeadersTooLarge => "request headers too large",
+            ParseError::InvalidHeader => "invalid header",
+            ParseError::InvalidContentLength => "invalid content-length",
+            ParseError::BodyTooLarge => "request body too large",
+            ParseError::InvalidChunk => "invalid chunked body",
+            ParseError::UnsupportedTransferEncoding => "unsupported transfer-encoding",
+            ParseError::InvalidEncoding => "invalid percent-encoding",
+            ParseError::UnexpectedEof => "connection closed before request was complete",
+            ParseError::RequestTimeout => "request not received in time",
+        };
+        write!(f, "{}", msg)
+    }
+}
+
+impl Error for ParseError {}
+
+/// 从连接中读取请求时的错误：IO 错误或解析错误。
+#[derive(Debug)]
+pub enum ReadError {
+    Io(io::Error),
+    Parse(ParseError),
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReadError::Io(err) => write!(f, "io error: {}", err),
+            ReadError::Parse(err) => write!(f, "parse error: {}", err),
+        }
+    }
+}
+
+impl Error for ReadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ReadError::Io(err) => Some(err),
+            ReadError::Parse(err) => Some(err),
+        }
+    }
+}
+
+impl From<io::Error> for ReadError {
+    fn from(err: io::Error) -> Self {
+        ReadError::Io(err)
+    }
+}
+
+impl From<ParseError> for ReadError {
+    fn from(err: ParseError) -> Self {
+        ReadError::Parse(err)
+    }
+}
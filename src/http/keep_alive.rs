@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+use super::{Request, Response};
+
+/// 持久连接（keep-alive）的配置。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeepAlive {
+    /// 两个请求之间连接最长空闲时间，超时后服务端关闭连接
+    pub idle_timeout: Duration,
+    /// 一个连接上最多处理的请求数，达到后服务端关闭连接
+    pub max_requests: usize,
+    /// 从收到请求的第一个字节起，收完整个请求的最长时间，超时后回复 408 并关闭连接。
+    /// 读超时只管每次读，一次只发一个字节的慢速客户端可以一直占着连接
+    pub request_timeout: Duration,
+}
+
+impl Default for KeepAlive {
+    fn default() -> Self {
+        KeepAlive {
+            idle_timeout: Duration::from_secs(5),
+            max_requests: 100,
+            request_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl KeepAlive {
+    /// 决定回复第 `served` 个请求后是否保持连接，并设置 `Connection` / `Keep-Alive` 响应头。
+    /// 客户端要求关闭、或已达到最大请求数时返回 false，此时发送完响应就应关闭连接。
+    pub fn apply(&self, request: &Request, served: usize, response: &mut Response) -> bool {
+        let keep = request.keep_alive()
+            && served < self.max_requests
+            && !response.headers().has_token("Connection", "close");
+        if keep {
+            response.set_header("Connection", "keep-alive");
+            response.set_header(
+                "Keep-Alive",
+                format!(
+                    "timeout={}, max={}",
+                    self.idle_timeout.as_secs(),
+                    self.max_requests - served
+                ),
+            );
+        } else {
+            response.set_header("Connection", "close");
+        }
+        keep
+    }
+}
@@ -0,0 +1,86 @@
+//! 最小的 HTTP/1.1 协议实现：请求解析、响应序列化、持久连接和相关的错误类型。
+//! 不依赖任何 IO，`read_request` 只是为阻塞式的 `Read` 提供的便捷函数。
+
+mod error;
+mod headers;
+mod keep_alive;
+mod parser;
+mod request;
+mod response;
+mod uri;
+
+use std::io::{self, ErrorKind, Read};
+
+pub use error::{ParseError, ReadError};
+pub use headers::Headers;
+pub use keep_alive::KeepAlive;
+pub use parser::{Limits, RequestParser};
+pub use request::{Method, Request, Version};
+pub use response::Response;
+pub(crate) use uri::percent_decode;
+
+/// 状态码对应的原因短语
+pub fn reason_phrase(code: u16) -> &'static str {
+    match code {
+        100 => "Continue",
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        206 => "Partial Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        413 => "Payload Too Large",
+        414 => "URI Too Long",
+        416 => "Range Not Satisfiable",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        503 => "Service Unavailable",
+        505 => "HTTP Version Not Supported",
+        _ => "Unknown",
+    }
+}
+
+/// 读超时产生的错误（`set_read_timeout` 超时在不同平台上分别是 `WouldBlock` 或 `TimedOut`）
+pub fn is_timeout(err: &io::Error) -> bool {
+    matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+}
+
+/// 从阻塞的 `reader` 中读取一个完整请求。
+///
+/// `buf` 中保存已读到但还未解析的字节，在同一连接上重复调用时要传同一个 `buf` 和 `parser`，
+/// 这样流水线中的下一个请求不会丢失。
+/// 连接在请求开始之前就关闭时返回 `Ok(None)`。
+pub fn read_request<R: Read>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+    parser: &mut RequestParser,
+) -> Result<Option<Request>, ReadError> {
+    let mut chunk = [0; 4096];
+    loop {
+        if let Some((request, consumed)) = parser.parse(buf)? {
+            buf.drain(..consumed);
+            return Ok(Some(request));
+        }
+        let n = match reader.read(&mut chunk) {
+            Ok(n) => n,
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.into()),
+        };
+        if n == 0 {
+            return if buf.iter().all(|b| matches!(b, b'\r' | b'\n')) {
+                Ok(None)
+            } else {
+                Err(ParseError::UnexpectedEof.into())
+            };
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+}
@@ -0,0 +1,682 @@
+use std::mem;
+
+use super::request::is_token_char;
+use super::uri::{parse_query, percent_decode};
+use super::{Headers, Method, ParseError, Request, Version};
+
+/// 解析请求时的各项长度限制，超过限制时返回对应的错误（414/431/413）。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// 请求行最大长度（字节）
+    pub max_request_line: usize,
+    /// 请求头（含 chunked 尾部字段）最大总长度（字节）
+    pub max_header_bytes: usize,
+    /// 请求头最大个数
+    pub max_headers: usize,
+    /// 请求体最大长度（字节）
+    pub max_body: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_request_line: 8 * 1024,
+            max_header_bytes: 16 * 1024,
+            max_headers: 100,
+            max_body: 1024 * 1024,
+        }
+    }
+}
+
+/// chunked 编码中单个 chunk-size 行的最大长度（含扩展）
+const MAX_CHUNK_LINE: usize = 1024;
+
+/// 增量式请求解析器。
+///
+/// 调用者把从连接中读到的字节追加到同一个缓冲区，每次追加后调用 `parse`：
+/// 返回 `Ok(None)` 表示还需要更多数据；返回 `Ok(Some((request, consumed)))`
+/// 表示解析出一个完整请求，调用者应丢弃缓冲区开头的 `consumed` 个字节，
+/// 剩余的字节属于下一个（流水线）请求。解析器会记住已经扫描过的位置，不会重复解析。
+/// 完成一个请求或返回错误后，解析器自动复位，可以继续解析下一个请求。
+#[derive(Debug)]
+pub struct RequestParser {
+    limits: Limits,
+    state: State,
+}
+
+#[derive(Debug)]
+enum State {
+    Head {
+        // 请求行起始位置（跳过请求行前的空行）
+        start: usize,
+        // 下一个待扫描行的起始位置
+        line_start: usize,
+        // 请求行结束位置（含换行符）
+        request_line_end: Option<usize>,
+    },
+    Body {
+        request: Box<Request>,
+        pos: usize,
+        body: Vec<u8>,
+        kind: BodyKind,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+enum BodyKind {
+    Length(usize),
+    Chunked(Chunk),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Chunk {
+    Size,
+    Data(usize),
+    DataEnd,
+    Trailer { start: usize },
+}
+
+impl State {
+    fn initial() -> State {
+        State::Head {
+            start: 0,
+            line_start: 0,
+            request_line_end: None,
+        }
+    }
+}
+
+impl Default for RequestParser {
+    fn default() -> Self {
+        RequestParser::new()
+    }
+}
+
+impl RequestParser {
+    pub fn new() -> RequestParser {
+        RequestParser::with_limits(Limits::default())
+    }
+
+    pub fn with_limits(limits: Limits) -> RequestParser {
+        RequestParser {
+            limits,
+            state: State::initial(),
+        }
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// 解析缓冲区中的请求，见类型文档。
+    pub fn parse(&mut self, buf: &[u8]) -> Result<Option<(Request, usize)>, ParseError> {
+        let result = self.advance(buf);
+        if !matches!(result, Ok(None)) {
+            self.state = State::initial();
+        }
+        result
+    }
+
+    fn advance(&mut self, buf: &[u8]) -> Result<Option<(Request, usize)>, ParseError> {
+        if let State::Head { .. } = self.state {
+            let (start, end) = match self.scan_head(buf)? {
+                Some(range) => range,
+                None => return Ok(None),
+            };
+            let (request, kind) = parse_head(&buf[start..end], &self.limits)?;
+            self.state = State::Body {
+                request: Box::new(request),
+                pos: end,
+                body: Vec::new(),
+                kind,
+            };
+        }
+
+        let limits = self.limits;
+        let done = match &mut self.state {
+            State::Body { pos, body, kind, .. } => match kind {
+                BodyKind::Length(len) => {
+                    if buf.len() < *pos + *len {
+                        None
+                    } else {
+                        body.extend_from_slice(&buf[*pos..*pos + *len]);
+                        Some(*pos + *len)
+                    }
+                }
+                BodyKind::Chunked(chunk) => parse_chunked(buf, pos, body, chunk, &limits)?,
+            },
+            State::Head { .. } => unreachable!(),
+        };
+
+        match done {
+            Some(consumed) => match mem::replace(&mut self.state, State::initial()) {
+                State::Body { request, body, .. } => {
+                    let mut request = *request;
+                    request.set_body(body);
+                    Ok(Some((request, consumed)))
+                }
+                State::Head { .. } => unreachable!(),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// 逐行扫描，找到请求头结束的空行，返回请求头（含请求行）的范围
+    fn scan_head(&mut self, buf: &[u8]) -> Result<Option<(usize, usize)>, ParseError> {
+        let limits = self.limits;
+        let (start, line_start, request_line_end) = match &mut self.state {
+            State::Head {
+                start,
+                line_start,
+                request_line_end,
+            } => (start, line_start, request_line_end),
+            State::Body { .. } => unreachable!(),
+        };
+
+        loop {
+            let rest = &buf[*line_start..];
+            let newline = match rest.iter().position(|&b| b == b'\n') {
+                Some(i) => i,
+                None => {
+                    // 当前行还没读完，检查是否已经超过限制
+                    match request_line_end {
+                        None if rest.len() > limits.max_request_line => {
+                            return Err(ParseError::UriTooLong)
+                        }
+                        Some(end) if buf.len() - *end > limits.max_header_bytes => {
+                            return Err(ParseError::HeadersTooLarge)
+                        }
+                        _ => return Ok(None),
+                    }
+                }
+            };
+            let line = trim_cr(&rest[..newline]);
+            let next = *line_start + newline + 1;
+
+            match request_line_end {
+                // 请求行之前的空行忽略掉，但不能无限多
+                None if line.is_empty() => {
+                    if next > limits.max_request_line {
+                        return Err(ParseError::InvalidRequestLine);
+                    }
+                    *start = next;
+                }
+                None => {
+                    if line.len() > limits.max_request_line {
+                        return Err(ParseError::UriTooLong);
+                    }
+                    *request_line_end = Some(next);
+                }
+                Some(end) => {
+                    if next - *end > limits.max_header_bytes {
+                        return Err(ParseError::HeadersTooLarge);
+                    }
+                    if line.is_empty() {
+                        return Ok(Some((*start, next)));
+                    }
+                }
+            }
+            *line_start = next;
+        }
+    }
+}
+
+/// 解析请求行和请求头，确定请求体的长度
+fn parse_head(head: &[u8], limits: &Limits) -> Result<(Request, BodyKind), ParseError> {
+    let mut lines = head.split(|&b| b == b'\n').map(trim_cr);
+
+    let request_line = lines.next().ok_or(ParseError::InvalidRequestLine)?;
+    let request_line = std::str::from_utf8(request_line).map_err(|_| ParseError::InvalidRequestLine)?;
+    let parts: Vec<_> = request_line.split(' ').collect();
+    if parts.len() != 3 || parts.iter().any(|p| p.is_empty()) {
+        return Err(ParseError::InvalidRequestLine);
+    }
+    let method = Method::parse(parts[0])?;
+    let target = parts[1];
+    let version = Version::parse(parts[2])?;
+
+    if !target.bytes().all(|b| b.is_ascii_graphic()) {
+        return Err(ParseError::InvalidTarget);
+    }
+    let origin = if target == "*" {
+        if method != Method::Options {
+            return Err(ParseError::InvalidTarget);
+        }
+        "*"
+    } else if target.starts_with('/') {
+        target
+    } else if let Some(rest) = target
+        .strip_prefix("http://")
+        .or_else(|| target.strip_prefix("https://"))
+    {
+        // absolute-form，去掉 authority 部分
+        match rest.find(['/', '?']) {
+            Some(pos) if rest.as_bytes()[pos] == b'/' => &rest[pos..],
+            _ => "/",
+        }
+    } else {
+        return Err(ParseError::InvalidTarget);
+    };
+    let origin = origin.split('#').next().unwrap_or_default();
+    let (raw_path, query) = match origin.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (origin, None),
+    };
+    let path = percent_decode(raw_path, false)?;
+    if path.contains('\0') {
+        return Err(ParseError::InvalidEncoding);
+    }
+    let query_pairs = match query {
+        Some(query) => parse_query(query)?,
+        None => Vec::new(),
+    };
+
+    let mut headers = Headers::new();
+    for line in lines.filter(|line| !line.is_empty()) {
+        // 不支持已废弃的折行写法
+        if line[0] == b' ' || line[0] == b'\t' {
+            return Err(ParseError::InvalidHeader);
+        }
+        let colon = line
+            .iter()
+            .position(|&b| b == b':')
+            .ok_or(ParseError::InvalidHeader)?;
+        let name = &line[..colon];
+        if name.is_empty() || !name.iter().all(|&b| is_token_char(b)) {
+            return Err(ParseError::InvalidHeader);
+        }
+        let value = trim_ows(&line[colon + 1..]);
+        if value.iter().any(|&b| b.is_ascii_control() && b != b'\t') {
+            return Err(ParseError::InvalidHeader);
+        }
+        if headers.len() >= limits.max_headers {
+            return Err(ParseError::HeadersTooLarge);
+        }
+        headers.append(
+            std::str::from_utf8(name).map_err(|_| ParseError::InvalidHeader)?,
+            &String::from_utf8_lossy(value),
+        );
+    }
+
+    let kind = body_kind(&headers, limits)?;
+    let request = Request::new(
+        method,
+        target.to_string(),
+        path,
+        query.map(str::to_string),
+        query_pairs,
+        version,
+        headers,
+    );
+    Ok((request, kind))
+}
+
+fn body_kind(headers: &Headers, limits: &Limits) -> Result<BodyKind, ParseError> {
+    if headers.contains("Transfer-Encoding") {
+        // 同时带 Content-Length 和 Transfer-Encoding 的请求可能是请求走私，直接拒绝
+        if headers.contains("Content-Length") {
+            return Err(ParseError::InvalidHeader);
+        }
+        let codings: Vec<_> = headers
+            .get_all("Transfer-Encoding")
+            .flat_map(|v| v.split(','))
+            .map(str::trim)
+            .filter(|c| !c.is_empty())
+            .collect();
+        return match codings.as_slice() {
+            [coding] if coding.eq_ignore_ascii_case("chunked") => Ok(BodyKind::Chunked(Chunk::Size)),
+            _ => Err(ParseError::UnsupportedTransferEncoding),
+        };
+    }
+
+    let mut length = None;
+    for value in headers.get_all("Content-Length").flat_map(|v| v.split(',')) {
+        let value = value.trim();
+        if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(ParseError::InvalidContentLength);
+        }
+        let value: u64 = value.parse().map_err(|_| ParseError::InvalidContentLength)?;
+        match length {
+            Some(prev) if prev != value => return Err(ParseError::InvalidContentLength),
+            _ => length = Some(value),
+        }
+    }
+    match length {
+        Some(len) if len > limits.max_body as u64 => Err(ParseError::BodyTooLarge),
+        Some(len) => Ok(BodyKind::Length(len as usize)),
+        None => Ok(BodyKind::Length(0)),
+    }
+}
+
+/// 解码 chunked 请求体，完成时返回请求的结束位置
+fn parse_chunked(
+    buf: &[u8],
+    pos: &mut usize,
+    body: &mut Vec<u8>,
+    chunk: &mut Chunk,
+    limits: &Limits,
+) -> Result<Option<usize>, ParseError> {
+    loop {
+        match *chunk {
+            Chunk::Size => {
+                let rest = &buf[*pos..];
+                let newline = match rest.iter().position(|&b| b == b'\n') {
+                    Some(i) if i <= MAX_CHUNK_LINE => i,
+                    Some(_) => return Err(ParseError::InvalidChunk),
+                    None if rest.len() > MAX_CHUNK_LINE => return Err(ParseError::InvalidChunk),
+                    None => return Ok(None),
+                };
+                let line = trim_cr(&rest[..newline]);
+                // 忽略 chunk 扩展
+                let size = line.split(|&b| b == b';').next().unwrap_or_default();
+                let size = trim_ows(size);
+                if size.is_empty() || size.len() > 16 || !size.iter().all(u8::is_ascii_hexdigit) {
+                    return Err(ParseError::InvalidChunk);
+                }
+                let size = std::str::from_utf8(size)
+                    .ok()
+                    .and_then(|s| u64::from_str_radix(s, 16).ok())
+                    .ok_or(ParseError::InvalidChunk)?;
+                *pos += newline + 1;
+                *chunk = if size == 0 {
+                    Chunk::Trailer { start: *pos }
+                } else if body.len() as u64 + size > limits.max_body as u64 {
+                    return Err(ParseError::BodyTooLarge);
+                } else {
+                    Chunk::Data(size as usize)
+                };
+            }
+            Chunk::Data(remaining) => {
+                let available = remaining.min(buf.len() - *pos);
+                body.extend_from_slice(&buf[*pos..*pos + available]);
+                *pos += available;
+                if available < remaining {
+                    *chunk = Chunk::Data(remaining - available);
+                    return Ok(None);
+                }
+                *chunk = Chunk::DataEnd;
+            }
+            Chunk::DataEnd => match &buf[*pos..] {
+                [b'\r', b'\n', ..] => {
+                    *pos += 2;
+                    *chunk = Chunk::Size;
+                }
+                [b'\n', ..] => {
+                    *pos += 1;
+                    *chunk = Chunk::Size;
+                }
+                [] | [b'\r'] => return Ok(None),
+                _ => return Err(ParseError::InvalidChunk),
+            },
+            Chunk::Trailer { start } => {
+                // 尾部字段按请求头的限制计算长度，内容忽略
+                let rest = &buf[*pos..];
+                let newline = match rest.iter().position(|&b| b == b'\n') {
+                    Some(i) => i,
+                    None if buf.len() - start > limits.max_header_bytes => {
+                        return Err(ParseError::HeadersTooLarge)
+                    }
+                    None => return Ok(None),
+                };
+                let next = *pos + newline + 1;
+                if next - start > limits.max_header_bytes {
+                    return Err(ParseError::HeadersTooLarge);
+                }
+                if trim_cr(&rest[..newline]).is_empty() {
+                    return Ok(Some(next));
+                }
+                *pos = next;
+            }
+        }
+    }
+}
+
+fn trim_cr(line: &[u8]) -> &[u8] {
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+fn trim_ows(mut value: &[u8]) -> &[u8] {
+    while let [b' ' | b'\t', rest @ ..] = value {
+        value = rest;
+    }
+    while let [rest @ .., b' ' | b'\t'] = value {
+        value = rest;
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &[u8]) -> Result<Option<(Request, usize)>, ParseError> {
+        RequestParser::new().parse(input)
+    }
+
+    fn parse_err(input: &str) -> ParseError {
+        parse(input.as_bytes()).expect_err(input)
+    }
+
+    // 模拟一次只读到一个字节，返回请求和它结束的位置
+    fn parse_bytewise(parser: &mut RequestParser, input: &[u8]) -> Result<(Request, usize), ParseError> {
+        for end in 1..=input.len() {
+            if let Some(done) = parser.parse(&input[..end])? {
+                return Ok(done);
+            }
+        }
+        panic!("request not complete");
+    }
+
+    fn small_limits() -> Limits {
+        Limits {
+            max_request_line: 32,
+            max_header_bytes: 64,
+            max_headers: 3,
+            max_body: 16,
+        }
+    }
+
+    #[test]
+    fn simple_request() {
+        let input = b"GET /a%20b?x=1&y=%E4%B8%AD HTTP/1.1\r\nHost: example.com\r\n\r\n";
+        let (request, consumed) = parse(input).unwrap().unwrap();
+        assert_eq!(consumed, input.len());
+        assert_eq!(request.method(), &Method::Get);
+        assert_eq!(request.target(), "/a%20b?x=1&y=%E4%B8%AD");
+        assert_eq!(request.path(), "/a b");
+        assert_eq!(request.query(), Some("x=1&y=%E4%B8%AD"));
+        assert_eq!(request.query_param("y"), Some("中"));
+        assert_eq!(request.version(), Version::Http11);
+        assert_eq!(request.header("host"), Some("example.com"));
+        assert!(request.body().is_empty());
+    }
+
+    #[test]
+    fn incomplete_request() {
+        assert!(parse(b"GET / HTTP/1.1\r\nHost: a\r\n").unwrap().is_none());
+        assert!(parse(b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nabc").unwrap().is_none());
+    }
+
+    #[test]
+    fn absolute_form_and_asterisk() {
+        let (request, _) = parse(b"GET http://example.com/x?y HTTP/1.1\r\n\r\n").unwrap().unwrap();
+        assert_eq!(request.path(), "/x");
+        assert_eq!(request.query(), Some("y"));
+        let (request, _) = parse(b"GET https://example.com HTTP/1.1\r\n\r\n").unwrap().unwrap();
+        assert_eq!(request.path(), "/");
+        let (request, _) = parse(b"OPTIONS * HTTP/1.1\r\n\r\n").unwrap().unwrap();
+        assert_eq!(request.path(), "*");
+    }
+
+    #[test]
+    fn errors_and_status_codes() {
+        let cases = [
+            ("GET /\r\n\r\n", ParseError::InvalidRequestLine, 400),
+            ("GET  / HTTP/1.1\r\n\r\n", ParseError::InvalidRequestLine, 400),
+            ("GET / FTP/1.0\r\n\r\n", ParseError::InvalidRequestLine, 400),
+            ("G(T / HTTP/1.1\r\n\r\n", ParseError::InvalidMethod, 400),
+            ("GET a/b HTTP/1.1\r\n\r\n", ParseError::InvalidTarget, 400),
+            ("GET * HTTP/1.1\r\n\r\n", ParseError::InvalidTarget, 400),
+            ("GET / HTTP/2.0\r\n\r\n", ParseError::UnsupportedVersion, 505),
+            ("GET / HTTP/1.1\r\nNo-Colon\r\n\r\n", ParseError::InvalidHeader, 400),
+            ("GET / HTTP/1.1\r\nBad Name: x\r\n\r\n", ParseError::InvalidHeader, 400),
+            ("GET / HTTP/1.1\r\nA: b\r\n folded\r\n\r\n", ParseError::InvalidHeader, 400),
+            ("GET / HTTP/1.1\r\nA: b\x01c\r\n\r\n", ParseError::InvalidHeader, 400),
+            ("POST / HTTP/1.1\r\nContent-Length: -1\r\n\r\n", ParseError::InvalidContentLength, 400),
+            ("POST / HTTP/1.1\r\nContent-Length: 1x\r\n\r\n", ParseError::InvalidContentLength, 400),
+            ("POST / HTTP/1.1\r\nContent-Length: 99999999999999999999\r\n\r\n", ParseError::InvalidContentLength, 400),
+            ("POST / HTTP/1.1\r\nContent-Length: 2000000\r\n\r\n", ParseError::BodyTooLarge, 413),
+            ("POST / HTTP/1.1\r\nTransfer-Encoding: gzip\r\n\r\n", ParseError::UnsupportedTransferEncoding, 501),
+            ("POST / HTTP/1.1\r\nTransfer-Encoding: gzip, chunked\r\n\r\n", ParseError::UnsupportedTransferEncoding, 501),
+            ("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n", ParseError::InvalidChunk, 400),
+            ("GET /%zz HTTP/1.1\r\n\r\n", ParseError::InvalidEncoding, 400),
+            ("GET /%00 HTTP/1.1\r\n\r\n", ParseError::InvalidEncoding, 400),
+            ("GET /?a=%ff HTTP/1.1\r\n\r\n", ParseError::InvalidEncoding, 400),
+        ];
+        for (input, err, status) in cases {
+            assert_eq!(parse_err(input), err, "{:?}", input);
+            assert_eq!(err.status_code(), status, "{:?}", input);
+        }
+        assert_eq!(ParseError::UriTooLong.status_code(), 414);
+        assert_eq!(ParseError::HeadersTooLarge.status_code(), 431);
+        assert_eq!(ParseError::UnexpectedEof.status_code(), 400);
+        assert_eq!(ParseError::RequestTimeout.status_code(), 408);
+    }
+
+    #[test]
+    fn content_length_with_transfer_encoding_rejected() {
+        let input = "POST / HTTP/1.1\r\nContent-Length: 3\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n";
+        assert_eq!(parse_err(input), ParseError::InvalidHeader);
+        let input = "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 3\r\n\r\n0\r\n\r\n";
+        assert_eq!(parse_err(input), ParseError::InvalidHeader);
+    }
+
+    #[test]
+    fn conflicting_content_length() {
+        let input = "POST / HTTP/1.1\r\nContent-Length: 3\r\nContent-Length: 4\r\n\r\nabcd";
+        assert_eq!(parse_err(input), ParseError::InvalidContentLength);
+        assert_eq!(parse_err("POST / HTTP/1.1\r\nContent-Length: 3, 4\r\n\r\nabcd"), ParseError::InvalidContentLength);
+        // 相同的值可以重复
+        let input = b"POST / HTTP/1.1\r\nContent-Length: 3\r\nContent-Length: 3, 3\r\n\r\nabc";
+        let (request, _) = parse(input).unwrap().unwrap();
+        assert_eq!(request.body(), b"abc");
+    }
+
+    #[test]
+    fn request_line_and_header_limits() {
+        let mut parser = RequestParser::with_limits(small_limits());
+        let long_target = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(40));
+        assert_eq!(parser.parse(long_target.as_bytes()).unwrap_err(), ParseError::UriTooLong);
+        // 请求行还没读完时也要检查
+        assert_eq!(parser.parse(&long_target.as_bytes()[..36]).unwrap_err(), ParseError::UriTooLong);
+
+        let big_header = format!("GET / HTTP/1.1\r\nA: {}\r\n\r\n", "b".repeat(70));
+        assert_eq!(parser.parse(big_header.as_bytes()).unwrap_err(), ParseError::HeadersTooLarge);
+        assert_eq!(parser.parse(&big_header.as_bytes()[..90]).unwrap_err(), ParseError::HeadersTooLarge);
+
+        let many = "GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\nD: 4\r\n\r\n";
+        assert_eq!(parser.parse(many.as_bytes()).unwrap_err(), ParseError::HeadersTooLarge);
+
+        let body = "POST / HTTP/1.1\r\nContent-Length: 17\r\n\r\n";
+        assert_eq!(parser.parse(body.as_bytes()).unwrap_err(), ParseError::BodyTooLarge);
+
+        // 请求行前的空行不能无限多
+        let blank = "\r\n".repeat(20);
+        assert_eq!(parser.parse(blank.as_bytes()).unwrap_err(), ParseError::InvalidRequestLine);
+    }
+
+    #[test]
+    fn chunked_body() {
+        let input = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5;ext=1\r\nhello\r\n6\r\n world\r\n0\r\nX-Trailer: t\r\n\r\n";
+        let (request, consumed) = parse(input).unwrap().unwrap();
+        assert_eq!(consumed, input.len());
+        assert_eq!(request.body(), b"hello world");
+        // 尾部字段忽略
+        assert_eq!(request.header("X-Trailer"), None);
+    }
+
+    #[test]
+    fn chunk_limits() {
+        let mut parser = RequestParser::with_limits(small_limits());
+        let head = "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n";
+
+        let too_big = format!("{}10\r\n{}\r\n1\r\nx\r\n0\r\n\r\n", head, "a".repeat(16));
+        assert_eq!(parser.parse(too_big.as_bytes()).unwrap_err(), ParseError::BodyTooLarge);
+
+        let long_size = format!("{}{}1\r\n", head, "0".repeat(17));
+        assert_eq!(parser.parse(long_size.as_bytes()).unwrap_err(), ParseError::InvalidChunk);
+
+        let long_ext = format!("{}1;{}", head, "e".repeat(MAX_CHUNK_LINE + 1));
+        assert_eq!(parser.parse(long_ext.as_bytes()).unwrap_err(), ParseError::InvalidChunk);
+
+        let missing_crlf = format!("{}1\r\nxy\r\n0\r\n\r\n", head);
+        assert_eq!(parser.parse(missing_crlf.as_bytes()).unwrap_err(), ParseError::InvalidChunk);
+    }
+
+    #[test]
+    fn trailer_limits() {
+        let mut parser = RequestParser::with_limits(small_limits());
+        let head = "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n";
+
+        let big_trailer = format!("{}T: {}\r\n\r\n", head, "t".repeat(70));
+        assert_eq!(parser.parse(big_trailer.as_bytes()).unwrap_err(), ParseError::HeadersTooLarge);
+        // 尾部字段还没读完时也要检查
+        let unfinished = format!("{}T: {}", head, "t".repeat(70));
+        assert_eq!(parser.parse(unfinished.as_bytes()).unwrap_err(), ParseError::HeadersTooLarge);
+
+        let many_lines = format!("{}{}\r\n", head, "T: 1\r\n".repeat(12));
+        assert_eq!(parser.parse(many_lines.as_bytes()).unwrap_err(), ParseError::HeadersTooLarge);
+    }
+
+    #[test]
+    fn input_split_across_reads() {
+        let inputs: [&[u8]; 3] = [
+            b"\r\nGET /split?q=1 HTTP/1.1\r\nHost: a\r\n\r\n",
+            b"POST /form HTTP/1.1\r\nContent-Length: 11\r\n\r\nhello world",
+            b"POST /chunked HTTP/1.1\nTransfer-Encoding: chunked\n\n5\r\nhello\r\n6\n world\n0\nT: 1\n\n",
+        ];
+        for input in inputs {
+            let mut parser = RequestParser::new();
+            let (request, consumed) = parse_bytewise(&mut parser, input).unwrap();
+            assert_eq!(consumed, input.len());
+            let (whole, _) = parse(input).unwrap().unwrap();
+            assert_eq!(request.path(), whole.path());
+            assert_eq!(request.body(), whole.body());
+        }
+    }
+
+    #[test]
+    fn pipelined_requests() {
+        let first = b"GET /one HTTP/1.1\r\nHost: a\r\n\r\n".as_slice();
+        let second = b"POST /two HTTP/1.1\r\nContent-Length: 3\r\n\r\nabc".as_slice();
+        let third = b"POST /three HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nhi\r\n0\r\n\r\n".as_slice();
+        let mut buf = [first, second, third, b"GET /fo".as_slice()].concat();
+
+        let mut parser = RequestParser::new();
+        let mut seen = Vec::new();
+        while let Some((request, consumed)) = parser.parse(&buf).unwrap() {
+            seen.push((request.path().to_string(), request.body().to_vec(), consumed));
+            buf.drain(..consumed);
+        }
+        assert_eq!(
+            seen,
+            [
+                ("/one".to_string(), Vec::new(), first.len()),
+                ("/two".to_string(), b"abc".to_vec(), second.len()),
+                ("/three".to_string(), b"hi".to_vec(), third.len()),
+            ]
+        );
+        // 剩下的半个请求留在缓冲区里，补齐后接着解析
+        assert_eq!(buf, b"GET /fo");
+        buf.extend_from_slice(b"ur HTTP/1.1\r\n\r\n");
+        let (request, consumed) = parser.parse(&buf).unwrap().unwrap();
+        assert_eq!((request.path(), consumed), ("/four", buf.len()));
+    }
+
+    #[test]
+    fn parser_resets_after_error() {
+        let mut parser = RequestParser::new();
+        assert_eq!(parser.parse(b"BAD\r\n\r\n").unwrap_err(), ParseError::InvalidRequestLine);
+        assert!(parser.parse(b"GET / HTTP/1.1\r\n\r\n").unwrap().is_some());
+    }
+}
@@ -0,0 +1,196 @@
+use std::fmt;
+
+use super::{Headers, ParseError};
+
+/// 请求方法
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+    Connect,
+    Options,
+    Trace,
+    Patch,
+    /// 其他扩展方法，保存原始名称
+    Other(String),
+}
+
+impl Method {
+    /// 解析方法名，方法名区分大小写，必须是合法的 token
+    pub fn parse(src: &str) -> Result<Method, ParseError> {
+        if src.is_empty() || !src.bytes().all(is_token_char) {
+            return Err(ParseError::InvalidMethod);
+        }
+        let method = match src {
+            "GET" => Method::Get,
+            "HEAD" => Method::Head,
+            "POST" => Method::Post,
+            "PUT" => Method::Put,
+            "DELETE" => Method::Delete,
+            "CONNECT" => Method::Connect,
+            "OPTIONS" => Method::Options,
+            "TRACE" => Method::Trace,
+            "PATCH" => Method::Patch,
+            other => Method::Other(other.to_string()),
+        };
+        Ok(method)
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+            Method::Connect => "CONNECT",
+            Method::Options => "OPTIONS",
+            Method::Trace => "TRACE",
+            Method::Patch => "PATCH",
+            Method::Other(name) => name,
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// HTTP 版本
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    Http10,
+    Http11,
+}
+
+impl Version {
+    pub fn parse(src: &str) -> Result<Version, ParseError> {
+        match src {
+            "HTTP/1.1" => Ok(Version::Http11),
+            "HTTP/1.0" => Ok(Version::Http10),
+            _ if src.starts_with("HTTP/") => Err(ParseError::UnsupportedVersion),
+            _ => Err(ParseError::InvalidRequestLine),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Version::Http10 => "HTTP/1.0",
+            Version::Http11 => "HTTP/1.1",
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// 解析完成的 HTTP 请求。
+/// 路径和查询参数已经做过百分号解码，请求体已经去掉了 chunked 编码。
+#[derive(Debug, Clone)]
+pub struct Request {
+    method: Method,
+    target: String,
+    path: String,
+    query: Option<String>,
+    query_pairs: Vec<(String, String)>,
+    version: Version,
+    headers: Headers,
+    body: Vec<u8>,
+}
+
+impl Request {
+    pub(crate) fn new(
+        method: Method,
+        target: String,
+        path: String,
+        query: Option<String>,
+        query_pairs: Vec<(String, String)>,
+        version: Version,
+        headers: Headers,
+    ) -> Request {
+        Request {
+            method,
+            target,
+            path,
+            query,
+            query_pairs,
+            version,
+            headers,
+            body: Vec::new(),
+        }
+    }
+
+    pub(crate) fn set_body(&mut self, body: Vec<u8>) {
+        self.body = body;
+    }
+
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// 请求行中原始的请求目标，未解码
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// 解码后的路径，总是以 `/` 开头（`OPTIONS *` 除外）
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// 原始查询串（不含 `?`），未解码
+    pub fn query(&self) -> Option<&str> {
+        self.query.as_deref()
+    }
+
+    /// 解码后的查询参数，保留原始顺序
+    pub fn query_pairs(&self) -> &[(String, String)] {
+        &self.query_pairs
+    }
+
+    /// 第一个同名查询参数的值；`?sleep` 这种没有值的参数返回空字符串
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query_pairs
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// 客户端是否希望保持连接：HTTP/1.1 默认保持，除非带 `Connection: close`；
+    /// HTTP/1.0 默认关闭，除非带 `Connection: keep-alive`。
+    pub fn keep_alive(&self) -> bool {
+        match self.version {
+            Version::Http11 => !self.headers.has_token("Connection", "close"),
+            Version::Http10 => self.headers.has_token("Connection", "keep-alive"),
+        }
+    }
+}
+
+/// RFC 9110 中 token 允许的字符
+pub(crate) fn is_token_char(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
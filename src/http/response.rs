@@ -0,0 +1,107 @@
+use super::{reason_phrase, Headers, ParseError};
+
+/// HTTP 响应。
+/// 序列化时如果没有设置 `Content-Length` 和 `Transfer-Encoding`，会按 body 长度自动补上 `Content-Length`
+/// （1xx、204、304 除外：它们没有 body，304 的 `Content-Length` 只能由调用方给出）；
+/// 需要流式发送 body（比如拷贝文件）时，自己设置 `Content-Length`，再只发送 `head_bytes`。
+#[derive(Debug, Clone)]
+pub struct Response {
+    status: u16,
+    headers: Headers,
+    body: Vec<u8>,
+}
+
+impl Response {
+    pub fn new(status: u16) -> Response {
+        Response {
+            status,
+            headers: Headers::new(),
+            body: Vec::new(),
+        }
+    }
+
+    /// 设置响应头，替换同名的已有值
+    pub fn header(mut self, name: &str, value: impl ToString) -> Response {
+        self.set_header(name, value);
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Response {
+        self.body = body.into();
+        self
+    }
+
+    pub fn set_header(&mut self, name: &str, value: impl ToString) {
+        self.headers.set(name, &value.to_string());
+    }
+
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    pub fn body_bytes(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// 状态行和响应头，以空行结尾
+    pub fn head_bytes(&self) -> Vec<u8> {
+        let mut head = format!("HTTP/1.1 {} {}\r\n", self.status, reason_phrase(self.status));
+        for (name, value) in self.headers.iter() {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        let bodyless = matches!(self.status, 100..=199 | 204 | 304);
+        if !bodyless
+            && !self.headers.contains("Content-Length")
+            && !self.headers.contains("Transfer-Encoding")
+        {
+            head.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+        }
+        head.push_str("\r\n");
+        head.into_bytes()
+    }
+
+    /// 完整的响应报文
+    pub fn into_bytes(self) -> Vec<u8> {
+        let mut bytes = self.head_bytes();
+        bytes.extend_from_slice(&self.body);
+        bytes
+    }
+}
+
+impl From<ParseError> for Response {
+    /// 请求格式错误时的响应，之后连接必须关闭
+    fn from(err: ParseError) -> Self {
+        Response::new(err.status_code())
+            .header("Content-Type", "text/plain; charset=utf-8")
+            .header("Connection", "close")
+            .body(format!("{} {}: {}", err.status_code(), err.reason(), err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn head(response: &Response) -> String {
+        String::from_utf8(response.head_bytes()).unwrap()
+    }
+
+    #[test]
+    fn content_length_added_from_body() {
+        let response = Response::new(200).body("hello");
+        assert!(head(&response).contains("Content-Length: 5\r\n"));
+    }
+
+    #[test]
+    fn no_content_length_for_bodyless_status() {
+        for status in [101, 204, 304] {
+            assert!(!head(&Response::new(status)).contains("Content-Length"), "{}", status);
+        }
+        let response = Response::new(304).header("Content-Length", "42");
+        assert!(head(&response).contains("Content-Length: 42\r\n"));
+    }
+}
@@ -0,0 +1,84 @@
+use super::ParseError;
+
+/// 百分号解码。`plus_as_space` 为 true 时把 `+` 解码为空格（用于查询串）。
+/// 非法的 `%XX` 序列或解码结果不是 UTF-8 时返回 `ParseError::InvalidEncoding`。
+pub fn percent_decode(src: &str, plus_as_space: bool) -> Result<String, ParseError> {
+    let bytes = src.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hi = bytes.get(i + 1).and_then(|b| hex_value(*b));
+                let lo = bytes.get(i + 2).and_then(|b| hex_value(*b));
+                match (hi, lo) {
+                    (Some(hi), Some(lo)) => decoded.push(hi << 4 | lo),
+                    _ => return Err(ParseError::InvalidEncoding),
+                }
+                i += 3;
+            }
+            b'+' if plus_as_space => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            b => {
+                decoded.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).map_err(|_| ParseError::InvalidEncoding)
+}
+
+/// 解析 `a=1&b=2` 形式的查询串，键和值都做百分号解码。
+/// 没有 `=` 的项（如 `?sleep`）值为空字符串。
+pub fn parse_query(query: &str) -> Result<Vec<(String, String)>, ParseError> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            Ok((percent_decode(key, true)?, percent_decode(value, true)?))
+        })
+        .collect()
+}
+
+fn hex_value(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'a'..=b'f' => Some(b - b'a' + 10),
+        b'A'..=b'F' => Some(b - b'A' + 10),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode() {
+        assert_eq!(percent_decode("/a%20b%2Fc", false).unwrap(), "/a b/c");
+        assert_eq!(percent_decode("%e4%B8%AD", false).unwrap(), "中");
+        assert_eq!(percent_decode("a+b", false).unwrap(), "a+b");
+        assert_eq!(percent_decode("a+b", true).unwrap(), "a b");
+    }
+
+    #[test]
+    fn decode_errors() {
+        for src in ["%", "%2", "%zz", "%g0", "a%2", "%ff", "%C3%28"] {
+            assert_eq!(percent_decode(src, false), Err(ParseError::InvalidEncoding), "{}", src);
+        }
+    }
+
+    #[test]
+    fn query() {
+        assert_eq!(
+            parse_query("a=1&b=x+y&&sleep&c=%3D&a=2").unwrap(),
+            [("a", "1"), ("b", "x y"), ("sleep", ""), ("c", "="), ("a", "2")]
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+        );
+        assert_eq!(parse_query("k=v=w").unwrap(), [("k".to_string(), "v=w".to_string())]);
+        assert_eq!(parse_query("a=%zz"), Err(ParseError::InvalidEncoding));
+    }
+}
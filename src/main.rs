@@ -1,57 +1,137 @@
-use async_std::task::spawn;
-use async_std::task::sleep;
+use async_std::task::{self, spawn};
 use async_std::task::JoinHandle;
-use async_std::io::{Result, Error, ErrorKind, BufReader, copy, stdin};
-use std::time::Duration;
-use async_std::channel::{unbounded as channel, Sender};
-use async_std::fs::File;
-use async_std::net::{TcpListener, TcpStream};
-use async_std::path::PathBuf;
-
+use async_std::future::timeout;
+use async_std::io::{Result, Error, ErrorKind, BufReader, stdin};
 use async_std::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::net::{Shutdown, SocketAddr};
+use std::os::unix::fs::PermissionsExt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use async_std::channel::{unbounded as channel, Receiver, Sender};
+use rust_web::http::{KeepAlive, Request};
+use rust_web::web::adapter::async_std::{serve_connection, serve_connection_until};
+use rust_web::web::{
+    normalize_host, AdminAction, AdminConfig, AdminError, AdminService, FileService, Reply, Service, Signal, StaticFiles,
+    VirtualHosts,
+};
+use serde_json::{json, Value};
+use async_std::net::TcpListener;
+use async_std::os::unix::net::{UnixListener, UnixStream};
+use async_std::path::{Path, PathBuf};
 
 /**
- * 一个简易版web server 最终版，一个带命令行的程序 
- * start: 启动 
- * stop: 停止 
- * restart: 重启 
- * quit: 退出 
- * port: 设置监听端口 
- * dir: 设置响应文件根目录。 
- * 
- * stop 和 quit 可由 http 请求控制。
+ * 一个简易版web server 最终版，一个带命令行的程序
+ * start: 启动
+ * stop: 停止
+ * restart: 重启
+ * quit: 退出
+ * port: 设置监听端口，运行中时先监听新端口，成功后再关闭旧端口，已经建立的连接不受影响；新端口监听失败时保持旧端口。
+ * listen: 再监听一个端口（listen 8080），所有端口提供同样的服务。
+ * unlisten: 不再监听某个端口，至少保留一个。
+ * dir: 设置默认虚拟主机的根目录，立即对新请求生效。
+ * vhost: 按请求的 Host 头使用不同的根目录（vhost add example.com /srv/example、vhost remove example.com、vhost list），
+ *   没有匹配的主机名时使用 dir 设置的默认根目录。
+ * listing: 开启或关闭目录列表（listing on|off），对所有虚拟主机立即生效。
+ * grace: 设置停止时等待正在处理的请求的秒数，默认 10 秒。
+ * status、connections、config dump: 以一行 JSON 输出运行状态、正在处理的连接和当前配置。
+ * help: 列出所有命令，help port 显示一个命令的用法，也是 JSON。
+ *
+ * stop、restart、quit 先停止接受新连接，空闲的连接立即关闭，正在处理的请求最多等 grace 秒，
+ * 之后强制关闭剩下的连接，并打印正常结束和强制关闭的连接数。
+ *
+ * 同样的命令可以从三个地方输入：
+ *   标准输入，每行一个命令；
+ *   控制 socket（默认 $TMPDIR/rust_web.sock，用环境变量 RUST_WEB_CONTROL_SOCKET 设置，只有启动的用户能连接），
+ *     用 `rust_web ctl status` 发送一个命令并打印输出，命令失败时退出码是 1，适合在服务管理器下使用；
+ *   `rust_web --script setup.txt` 启动时依次执行文件中的命令，空行和 # 开头的行跳过，
+ *     有命令失败时不再执行后面的命令。使用脚本时不会自动 start，需要脚本自己 start。
+ * 命令失败时输出以 `error: ` 开头。
+ *
+ * 这些命令也可以通过管理端口用 http 请求执行（见 rust_web::web::AdminService），
+ * 管理端口默认监听 127.0.0.1:20084，请求要带 `Authorization: Bearer <token>`：
+ *   curl -H "Authorization: Bearer $TOKEN" http://127.0.0.1:20084/status
+ *   curl -X POST -H "Authorization: Bearer $TOKEN" -d '{"port": 8080}' http://127.0.0.1:20084/port
+ * 地址和令牌用环境变量 RUST_WEB_ADMIN_ADDR、RUST_WEB_ADMIN_TOKEN 设置，没有设置令牌时启动时随机生成并打印出来。
  */
 #[async_std::main]
 async fn main() -> Result<()> {
-    let (cmd_sender, cmd_receiver) = channel::<Command>();
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let script = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] => None,
+        ["ctl", ref cmd @ ..] => return ctl(cmd).await,
+        ["--script", file] => {
+            let lines = async_std::fs::read_to_string(file)
+                .await
+                .map_err(|err| Error::new(err.kind(), format!("read script {} failed: {}", file, err)))?;
+            Some((file.to_string(), lines))
+        }
+        _ => {
+            eprintln!("usage: rust_web [--script file]\n       rust_web ctl <command>");
+            std::process::exit(2);
+        }
+    };
+
+    let (cmd_sender, cmd_receiver) = channel::<Job>();
 
     let local_host = "127.0.0.1";
-    let mut port = 20083;
+    // 第一个是主端口，port 命令替换的就是它
+    let mut ports = vec![20083];
     let mut dir = PathBuf::from(std::env::current_dir().unwrap_or_default());
-    let mut server = Err(Error::from(ErrorKind::Other));
+    // 主机名（已经规范化）到根目录
+    let mut vhosts = BTreeMap::<String, PathBuf>::new();
+    let mut grace = DEFAULT_GRACE;
+    let mut listing = false;
+    let mut server: Result<Server> = Err(Error::from(ErrorKind::Other));
+    // 运行中的服务也会读取，不用重启
+    let files = Files::new(&dir, &vhosts, listing);
+    // 管理接口 /status 返回的状态，由下面的命令循环更新
+    let status = Arc::new(Mutex::new(Status {
+        running: false,
+        host: local_host.to_string(),
+        ports: ports.clone(),
+        dir: dir.to_string_lossy().into_owned(),
+        vhosts: BTreeMap::new(),
+        listing: false,
+        grace,
+    }));
+
+    let admin = AdminConfig::from_env();
+    let admin_loop = start_admin_server(&admin, Arc::clone(&status), cmd_sender.clone()).await?;
+    println!("{}", admin.banner());
+    let control = Control::start(&control_socket_path(), cmd_sender.clone()).await?;
+    println!("control socket at {}", control.path.display());
 
-    cmd_sender.send(Command::Start).await.unwrap_or_default();
+    let script_loop = match script {
+        Some((file, lines)) => spawn(run_script(file, lines, cmd_sender.clone())),
+        None => {
+            cmd_sender.send(Job::new(Command::Start)).await.unwrap_or_default();
+            spawn(async {})
+        }
+    };
     let cmd_input_loop = spawn(start_cmd_input_loop(cmd_sender.clone()));
 
-    while let Ok(cmd) = cmd_receiver.recv().await {
+    while let Ok(Job { cmd, out }) = cmd_receiver.recv().await {
         match cmd {
-            Command::Unknown => {
-                println!("Unknown cmd");  
-            }
             Command::Start => {
                 match server {
                     Ok(_) => {
-                        println!("started already");
+                        out.line("started already");
                     }
                     Err(_) => {
-                        println!("starting server");
-                        server = start_http_server(local_host, port, dir.clone(), cmd_sender.clone()).await;
+                        out.line("starting server");
+                        server = Server::start(local_host, &ports, files.clone()).await;
                         match server {
                             Ok(_) => {
-                                println!("server started at http://{}:{}/ serving files in {}", local_host, port, dir.to_string_lossy());
+                                status.lock().unwrap().running = true;
+                                for port in &ports {
+                                    out.line(format!("server started at http://{}:{}/ serving files in {}", local_host, port, dir.to_string_lossy()));
+                                }
                             }
                             Err(ref err) => {
-                                println!("start server failed: {}", err);
+                                out.error(format!("start server failed: {}", err));
                             }
                         }
                     }
@@ -59,195 +139,919 @@ async fn main() -> Result<()> {
             }
             Command::Stop => {
                 match server {
-                    Ok(accept_loop_handle) => {
-                        println!("stopping");
-                        // 取消任务
-                        accept_loop_handle.cancel().await;
+                    Ok(running) => {
+                        out.line(format!("stopping, waiting up to {}s for open connections", grace.as_secs()));
+                        let (drained, aborted) = running.stop(grace).await;
+                        out.line(format!("connections: {} drained, {} aborted", drained, aborted));
                         server = Err(Error::from(ErrorKind::NotConnected));
-                        println!("stopped");
+                        status.lock().unwrap().running = false;
+                        out.line("stopped");
                     }
                     Err(ref err) => {
-                        println!("stopped already");
-                        println!("stopped server failed: {}", err);
+                        out.line("stopped already");
+                        out.line(format!("stopped server failed: {}", err));
                     }
                 }
             }
             Command::Quit => {
                 match server {
-                    Ok(accept_loop_handle) => {
-                        println!("stopping");
-                        // 取消任务
-                        accept_loop_handle.cancel().await;
-                        // server = Err(Error::from(ErrorKind::Other));
-                        println!("stopped");
+                    Ok(running) => {
+                        out.line(format!("stopping, waiting up to {}s for open connections", grace.as_secs()));
+                        let (drained, aborted) = running.stop(grace).await;
+                        out.line(format!("connections: {} drained, {} aborted", drained, aborted));
+                        out.line("stopped");
                     }
                     Err(e) => {
                         eprintln!("quit err: {}", e);
                     }
                 }
-                println!("quitting");
+                out.line("quitting");
                 // 退出循环
                 break;
             }
             Command::Port(new_port) => {
-                let old_port = port;
-                port = new_port;
-                println!("port changed from {} to {}", old_port, new_port);
+                let port = ports[0];
+                if new_port == port {
+                    out.line(format!("port is {} already", port));
+                    continue;
+                }
+                if ports.contains(&new_port) {
+                    out.error(format!("listening on {} already, unlisten it first", new_port));
+                    continue;
+                }
+                // 先监听新端口，失败时旧端口继续服务
+                if let Ok(running) = &mut server {
+                    if let Err(err) = running.rebind(local_host, port, new_port).await {
+                        out.error(format!("listen on port {} failed: {}, still listening on {}", new_port, err, port));
+                        continue;
+                    }
+                }
+                ports[0] = new_port;
+                status.lock().unwrap().ports = ports.clone();
+                out.line(format!("port changed from {} to {}", port, new_port));
+            }
+            Command::Listen(new_port) => {
+                if ports.contains(&new_port) {
+                    out.line(format!("listening on {} already", new_port));
+                    continue;
+                }
+                if let Ok(running) = &mut server {
+                    if let Err(err) = running.listen(local_host, new_port).await {
+                        out.error(format!("listen on port {} failed: {}", new_port, err));
+                        continue;
+                    }
+                }
+                ports.push(new_port);
+                status.lock().unwrap().ports = ports.clone();
+                out.line(format!("listening on ports {:?}", ports));
+            }
+            Command::Unlisten(old_port) => {
+                let Some(index) = ports.iter().position(|port| *port == old_port) else {
+                    out.error(format!("not listening on {}", old_port));
+                    continue;
+                };
+                if ports.len() == 1 {
+                    out.error(format!("{} is the only port, use port to change it or stop the server", old_port));
+                    continue;
+                }
+                // 这个端口上已经建立的连接继续处理
+                if let Ok(running) = &mut server {
+                    running.unlisten(old_port).await;
+                }
+                ports.remove(index);
+                status.lock().unwrap().ports = ports.clone();
+                out.line(format!("listening on ports {:?}", ports));
             }
             Command::Dir(new_dir) => {
+                let new_dir = PathBuf::from(new_dir);
+                if !new_dir.is_dir().await {
+                    out.error(format!("{:?} is not a directory, still serving files in {:?}", new_dir.to_string_lossy(), dir.to_string_lossy()));
+                    continue;
+                }
                 let old_dir = dir;
-                dir = PathBuf::from(new_dir);
-                println!("dir changed from {:?} to {:?}", old_dir.to_string_lossy(), dir.to_string_lossy());
+                dir = new_dir;
+                files.update(&dir, &vhosts, listing);
+                status.lock().unwrap().dir = dir.to_string_lossy().into_owned();
+                out.line(format!("dir changed from {:?} to {:?}", old_dir.to_string_lossy(), dir.to_string_lossy()));
+            }
+            Command::Listing(on) => {
+                listing = on;
+                files.update(&dir, &vhosts, listing);
+                status.lock().unwrap().listing = on;
+                out.line(format!("directory listing {}", if on { "on" } else { "off" }));
+            }
+            Command::VhostAdd(name, vhost_dir) => {
+                let name = normalize_host(&name);
+                let vhost_dir = PathBuf::from(vhost_dir);
+                if name.is_empty() {
+                    out.error("vhost add need a host name");
+                    continue;
+                }
+                if !vhost_dir.is_dir().await {
+                    out.error(format!("{:?} is not a directory", vhost_dir.to_string_lossy()));
+                    continue;
+                }
+                out.line(format!("vhost {} serving files in {:?}", name, vhost_dir.to_string_lossy()));
+                vhosts.insert(name, vhost_dir);
+                files.update(&dir, &vhosts, listing);
+                status.lock().unwrap().vhosts = vhost_names(&vhosts);
+            }
+            Command::VhostRemove(name) => {
+                let name = normalize_host(&name);
+                if vhosts.remove(&name).is_none() {
+                    out.error(format!("no vhost {}", name));
+                    continue;
+                }
+                files.update(&dir, &vhosts, listing);
+                status.lock().unwrap().vhosts = vhost_names(&vhosts);
+                out.line(format!("vhost {} removed, its requests go to the default vhost", name));
+            }
+            Command::VhostList => {
+                out.line(format!("(default) {:?}", dir.to_string_lossy()));
+                for (name, vhost_dir) in &vhosts {
+                    out.line(format!("{} {:?}", name, vhost_dir.to_string_lossy()));
+                }
+            }
+            Command::Grace(new_grace) => {
+                grace = new_grace;
+                status.lock().unwrap().grace = grace;
+                out.line(format!("grace period changed to {}s", grace.as_secs()));
+            }
+            Command::Status => {
+                let mut value = status.lock().unwrap().to_json();
+                value["connections"] = json!(server.as_ref().map_or(0, |running| running.connections.lock().unwrap().len()));
+                out.line(value.to_string());
+            }
+            Command::Connections => {
+                let connections = server.as_ref().map_or_else(|_| Vec::new(), Server::connections_json);
+                out.line(json!({ "count": connections.len(), "connections": connections }).to_string());
+            }
+            Command::Help(None) => {
+                let commands = USAGES.iter().map(Usage::to_json).collect::<Vec<_>>();
+                out.line(json!({ "commands": commands }).to_string());
+            }
+            Command::Help(Some(name)) => {
+                match usage(&name) {
+                    Some(usage) => out.line(usage.to_json().to_string()),
+                    None => out.error(format!("unknown command {:?}, try help", name)),
+                }
+            }
+            Command::ConfigDump => {
+                out.line(json!({
+                    "host": local_host,
+                    "ports": ports,
+                    "dir": dir.to_string_lossy(),
+                    "vhosts": vhost_names(&vhosts),
+                    "listing": listing,
+                    "grace": grace.as_secs(),
+                    "admin_addr": admin.addr,
+                    "control_socket": control.path.to_string_lossy(),
+                }).to_string());
             }
         }
     }
 
     println!("cmd_input_loop cancel");
     cmd_input_loop.cancel().await;
+    script_loop.cancel().await;
+    admin_loop.cancel().await;
+    // 让发出 quit 的 ctl 收到最后的输出
+    control.close().await;
     Ok(())
 }
 
 enum Command {
-    Unknown,
     Start,
     Stop,
     Quit,
     Port(u16),
     Dir(String),
+    Listing(bool),
+    Grace(Duration),
+    Listen(u16),
+    Unlisten(u16),
+    /// 主机名和根目录
+    VhostAdd(String, String),
+    VhostRemove(String),
+    VhostList,
+    Status,
+    Connections,
+    /// 没有参数时列出所有命令
+    Help(Option<String>),
+    ConfigDump,
+}
+
+// 停止时默认等待正在处理的请求的时间
+const DEFAULT_GRACE: Duration = Duration::from_secs(10);
+
+// 命令的用法，help 命令和参数错误时显示
+struct Usage {
+    name: &'static str,
+    usage: &'static str,
+    description: &'static str,
+}
+
+impl Usage {
+    fn to_json(&self) -> Value {
+        json!({ "name": self.name, "usage": self.usage, "description": self.description })
+    }
+}
+
+const USAGES: &[Usage] = &[
+    Usage { name: "start", usage: "start", description: "start the server" },
+    Usage { name: "stop", usage: "stop", description: "stop the server, waiting up to the grace period for open connections" },
+    Usage { name: "restart", usage: "restart", description: "stop and start the server" },
+    Usage { name: "quit", usage: "quit", description: "stop the server and quit" },
+    Usage { name: "port", usage: "port <num>", description: "change the main port, the old port is closed after the new one is listening" },
+    Usage { name: "listen", usage: "listen <num>", description: "listen on another port" },
+    Usage { name: "unlisten", usage: "unlisten <num>", description: "stop listening on a port, open connections are kept" },
+    Usage { name: "dir", usage: "dir <path>", description: "change the document root of the default vhost" },
+    Usage {
+        name: "vhost",
+        usage: "vhost add <host> <dir> | vhost remove <host> | vhost list",
+        description: "serve another document root for requests with a matching Host header",
+    },
+    Usage { name: "listing", usage: "listing on|off", description: "turn directory listing on or off for all vhosts" },
+    Usage { name: "grace", usage: "grace <secs>", description: "how long stop, restart and quit wait for open connections" },
+    Usage { name: "status", usage: "status", description: "print the server status as JSON" },
+    Usage { name: "connections", usage: "connections", description: "print the open connections as JSON" },
+    Usage { name: "help", usage: "help [command]", description: "print all commands or the usage of one command as JSON" },
+    Usage { name: "config", usage: "config dump", description: "print the current configuration as JSON" },
+];
+
+fn usage(name: &str) -> Option<&'static Usage> {
+    USAGES.iter().find(|usage| usage.name == name)
+}
+
+// 解析一行命令，空行返回空的列表；restart 是 stop 加 start
+fn parse_command(line: &str) -> std::result::Result<Vec<Command>, String> {
+    let parts = line.split_whitespace().collect::<Vec<_>>();
+    let Some((&name, args)) = parts.split_first() else {
+        return Ok(Vec::new());
+    };
+    let cmd = match (name, args) {
+        ("start", []) => Command::Start,
+        ("stop", []) => Command::Stop,
+        ("restart", []) => return Ok(vec![Command::Stop, Command::Start]),
+        ("quit", []) => Command::Quit,
+        ("port", [num]) => Command::Port(parse_arg(name, num)?),
+        ("listen", [num]) => Command::Listen(parse_arg(name, num)?),
+        ("unlisten", [num]) => Command::Unlisten(parse_arg(name, num)?),
+        ("dir", [dir]) => Command::Dir(dir.to_string()),
+        ("vhost", [] | ["list"]) => Command::VhostList,
+        ("vhost", ["add", host, dir]) => Command::VhostAdd(host.to_string(), dir.to_string()),
+        ("vhost", ["remove", host]) => Command::VhostRemove(host.to_string()),
+        ("listing", ["on"]) => Command::Listing(true),
+        ("listing", ["off"]) => Command::Listing(false),
+        ("grace", [secs]) => Command::Grace(Duration::from_secs(parse_arg(name, secs)?)),
+        ("status", []) => Command::Status,
+        ("connections", []) => Command::Connections,
+        ("help", []) => Command::Help(None),
+        ("help", [name]) => Command::Help(Some(name.to_string())),
+        ("config", ["dump"]) => Command::ConfigDump,
+        _ => {
+            return Err(match usage(name) {
+                Some(usage) => format!("usage: {}", usage.usage),
+                None => format!("unknown command {:?}, try help", name),
+            })
+        }
+    };
+    Ok(vec![cmd])
+}
+
+fn parse_arg<T: FromStr>(name: &str, arg: &str) -> std::result::Result<T, String> {
+    arg.parse().map_err(|_| {
+        let usage = usage(name).map_or(name, |usage| usage.usage);
+        format!("invalid argument {:?}, usage: {}", arg, usage)
+    })
 }
 
-impl From<&str> for Command {
-    fn from(src: &str) -> Self {
-        if src == "start" {
-            Command::Start
-        } else if src == "stop" {
-            Command::Stop
-        } else if src == "quit" {
-            Command::Quit
-        } else if src == "port" {
-            Command::Port(0)
-        } else if src == "dir" {
-            Command::Dir(String::new())
-        } else {
-            Command::Unknown
+// 命令输出的开头，表示命令失败
+const ERROR_PREFIX: &str = "error: ";
+
+// 交给主循环执行的命令
+struct Job {
+    cmd: Command,
+    out: Output,
+}
+
+impl Job {
+    fn new(cmd: Command) -> Job {
+        Job {
+            cmd,
+            out: Output::default(),
         }
     }
 }
 
-async fn start_cmd_input_loop(cmd_sender: Sender<Command>) -> Result<()> {
+// 命令的输出总是打印出来，从控制 socket 和脚本来的命令还会把每一行发回去；
+// 主循环处理完命令后 drop 掉它，发送端关闭表示命令执行完了
+#[derive(Default)]
+struct Output {
+    reply: Option<Sender<String>>,
+}
+
+impl Output {
+    fn line(&self, line: impl Into<String>) {
+        let line = line.into();
+        println!("{}", line);
+        if let Some(reply) = &self.reply {
+            reply.try_send(line).unwrap_or_default();
+        }
+    }
+
+    fn error(&self, message: impl std::fmt::Display) {
+        self.line(format!("{}{}", ERROR_PREFIX, message));
+    }
+}
+
+// 把命令交给主循环，返回它的输出，命令执行完时结束；主循环已经退出时返回 None
+async fn submit(cmd_sender: &Sender<Job>, cmd: Command) -> Option<Receiver<String>> {
+    let (reply, replies) = channel();
+    let out = Output { reply: Some(reply) };
+    cmd_sender.send(Job { cmd, out }).await.ok()?;
+    Some(replies)
+}
+
+async fn start_cmd_input_loop(cmd_sender: Sender<Job>) -> Result<()> {
     let stdin = stdin();
     loop {
         let mut line = String::new();
         //println!("cmd>");
-        stdin.read_line(&mut line).await.expect("Failed to read command");
-
-        let parts = line.trim().split_whitespace().collect::<Vec<_>>();
-        if parts.len() >= 1 {
-            let cmd = Command::from(parts[0]);
-            match cmd {
-                Command::Port(_) => {
-                    if parts.len() >= 2 {
-                        match parts[1].parse() {
-                            Ok(num) => {
-                                cmd_sender.send(Command::Port(num)).await.unwrap();
-                            }
-                            Err(_) => {
-                                println!("port command need an argument of 0~65535");
-                            },
-                        };
-                    } else {
-                        println!("port command need an argument of 0~65535");
-                    }
-                }
-                Command::Dir(_) => {
-                    if parts.len() >= 2 {
-                        cmd_sender.send(Command::Dir(String::from(parts[1]))).await.unwrap();
-                    } else {
-                        println!("dir command need an argument of absolute path");
-                    }
+        if stdin.read_line(&mut line).await? == 0 {
+            // 标准输入已经关闭（比如在服务管理器下运行），继续用控制 socket
+            return Ok(());
+        }
+        match parse_command(&line) {
+            Ok(cmds) => {
+                for cmd in cmds {
+                    cmd_sender.send(Job::new(cmd)).await.unwrap();
                 }
-                Command::Unknown => {
-                    if (parts[0] == "restart") {
-                        cmd_sender.send(Command::Stop).await.unwrap();
-                        cmd_sender.send(Command::Start).await.unwrap();
-                    } else {
-                        println!("unknown command");
-                        println!("start\nstop\nrestart\nquit\nport [num]\ndir [dir]\n");
+            }
+            Err(err) => Output::default().error(err),
+        }
+    }
+}
+
+// 依次执行脚本中的命令，每个命令执行完才执行下一个
+async fn run_script(file: String, lines: String, cmd_sender: Sender<Job>) {
+    for (index, line) in lines.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        println!("{}:{}> {}", file, index + 1, line);
+        let cmds = match parse_command(line) {
+            Ok(cmds) => cmds,
+            Err(err) => {
+                println!("{}{}:{}: {}, script stopped", ERROR_PREFIX, file, index + 1, err);
+                return;
+            }
+        };
+        for cmd in cmds {
+            let Some(replies) = submit(&cmd_sender, cmd).await else {
+                return;
+            };
+            let mut failed = false;
+            while let Ok(line) = replies.recv().await {
+                failed |= line.starts_with(ERROR_PREFIX);
+            }
+            if failed {
+                println!("{}{}:{}: command failed, script stopped", ERROR_PREFIX, file, index + 1);
+                return;
+            }
+        }
+    }
+}
+
+fn control_socket_path() -> PathBuf {
+    match std::env::var_os(CONTROL_SOCKET_ENV) {
+        Some(path) if !path.is_empty() => PathBuf::from(path),
+        _ => PathBuf::from(std::env::temp_dir().join("rust_web.sock")),
+    }
+}
+
+// 控制 socket 路径的环境变量
+const CONTROL_SOCKET_ENV: &str = "RUST_WEB_CONTROL_SOCKET";
+// quit 之后等控制连接发完输出的最长时间
+const CONTROL_GRACE: Duration = Duration::from_secs(1);
+
+// `rust_web ctl <command>`：把命令发到控制 socket，打印输出，有 `error: ` 开头的输出时退出码是 1
+async fn ctl(cmd: &[&str]) -> Result<()> {
+    let path = control_socket_path();
+    let stream = UnixStream::connect(&path)
+        .await
+        .map_err(|err| Error::new(err.kind(), format!("connect to {} failed: {}", path.display(), err)))?;
+    let line = if cmd.is_empty() { "help".to_string() } else { cmd.join(" ") };
+    (&stream).write_all(format!("{}\n", line).as_bytes()).await?;
+    // 告诉服务没有更多命令了，输出发完后它会关闭连接
+    stream.shutdown(Shutdown::Write)?;
+    let mut failed = false;
+    let mut lines = BufReader::new(&stream).lines();
+    while let Some(line) = lines.next().await {
+        let line = line?;
+        failed |= line.starts_with(ERROR_PREFIX);
+        println!("{}", line);
+    }
+    if failed {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+// 控制 socket：每行一个命令，和标准输入的命令一样，输出原样发回，连接的一方关闭写之后处理完就关闭
+struct Control {
+    path: PathBuf,
+    accept_loop: JoinHandle<()>,
+    sessions: Arc<Mutex<Tasks<()>>>,
+}
+
+impl Control {
+    async fn start(path: &Path, cmd_sender: Sender<Job>) -> Result<Control> {
+        if path.exists().await {
+            // 还能连上说明另一个进程在用，连不上是上次没有清理掉的
+            if UnixStream::connect(path).await.is_ok() {
+                return Err(Error::new(ErrorKind::AddrInUse, format!("{} is used by another server", path.display())));
+            }
+            async_std::fs::remove_file(path).await?;
+        }
+        let listener = UnixListener::bind(path).await?;
+        // 控制 socket 和管理令牌一样可以停止服务，只允许启动的用户连接
+        async_std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600)).await?;
+        let sessions = Arc::new(Mutex::new(Tasks::default()));
+        let registry = Arc::clone(&sessions);
+        let accept_loop = spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let cmd_sender = cmd_sender.clone();
+                Tasks::spawn(&registry, (), async move {
+                    if let Err(err) = control_session(stream, cmd_sender).await {
+                        eprintln!("control connection failed: {}", err);
                     }
-                }
-                cmd => {
-                    cmd_sender.send(cmd).await.unwrap();
-                }
+                });
+            }
+        });
+        Ok(Control {
+            path: path.to_path_buf(),
+            accept_loop,
+            sessions,
+        })
+    }
+
+    async fn close(self) {
+        self.accept_loop.cancel().await;
+        Tasks::drain(&self.sessions, CONTROL_GRACE).await;
+        async_std::fs::remove_file(&self.path).await.unwrap_or_default();
+    }
+}
+
+async fn control_session(stream: UnixStream, cmd_sender: Sender<Job>) -> Result<()> {
+    let mut writer = &stream;
+    let mut lines = BufReader::new(&stream).lines();
+    while let Some(line) = lines.next().await {
+        let line = line?;
+        let cmds = match parse_command(&line) {
+            Ok(cmds) => cmds,
+            Err(err) => {
+                writer.write_all(format!("{}{}\n", ERROR_PREFIX, err).as_bytes()).await?;
+                continue;
+            }
+        };
+        for cmd in cmds {
+            let Some(replies) = submit(&cmd_sender, cmd).await else {
+                writer.write_all(format!("{}server is quitting\n", ERROR_PREFIX).as_bytes()).await?;
+                return Ok(());
+            };
+            while let Ok(line) = replies.recv().await {
+                writer.write_all(format!("{}\n", line).as_bytes()).await?;
             }
         }
     }
+    Ok(())
+}
+
+struct Status {
+    running: bool,
+    host: String,
+    ports: Vec<u16>,
+    dir: String,
+    // 主机名到根目录
+    vhosts: BTreeMap<String, String>,
+    listing: bool,
+    grace: Duration,
+}
+
+impl Status {
+    fn to_json(&self) -> Value {
+        json!({
+            "running": self.running,
+            "host": self.host,
+            "port": self.ports[0],
+            "ports": self.ports,
+            "dir": self.dir,
+            "vhosts": self.vhosts,
+            "listing": self.listing,
+            "grace": self.grace.as_secs(),
+        })
+    }
 }
 
-async fn start_http_server(host: &str, port: u16, dir: PathBuf, cmd_sender: Sender<Command>)
+// 管理接口等命令执行完的时间比 grace 多出的部分
+const ADMIN_WAIT_MARGIN: Duration = Duration::from_secs(5);
+
+// 管理端口和 http 服务分开监听，stop 之后仍然可以 start
+async fn start_admin_server(admin: &AdminConfig, status: Arc<Mutex<Status>>, cmd_sender: Sender<Job>)
     -> Result<JoinHandle<()>> {
-    let listener = TcpListener::bind((host, port)).await?;
-    let accept_loop = spawn(async move {
+    let listener = TcpListener::bind(admin.addr.as_str()).await?;
+    let handler_sender = cmd_sender.clone();
+    let service = Arc::new(AdminService::new(&admin.token, move |action| {
+        let cmds = match action {
+            AdminAction::Status => return Ok(status.lock().unwrap().to_json()),
+            AdminAction::Start => vec![Command::Start],
+            AdminAction::Stop => vec![Command::Stop],
+            AdminAction::Restart => vec![Command::Stop, Command::Start],
+            AdminAction::Port(port) => vec![Command::Port(port)],
+            AdminAction::Dir(ref dir) => vec![Command::Dir(dir.to_string_lossy().into_owned())],
+            AdminAction::Listing(on) => vec![Command::Listing(on)],
+            AdminAction::Grace(grace) => vec![Command::Grace(grace)],
+        };
+        // 命令交给主循环执行，等它执行完再回复，有 `error: ` 开头的输出时回复错误。
+        // stop 最多等 grace，再多等一会儿；超时时命令可能还会执行完
+        let wait = status.lock().unwrap().grace + ADMIN_WAIT_MARGIN;
+        let mut output = Vec::new();
+        for cmd in cmds {
+            let replies = task::block_on(submit(&handler_sender, cmd))
+                .ok_or_else(|| AdminError::Unavailable("server is quitting".to_string()))?;
+            task::block_on(timeout(wait, async {
+                while let Ok(line) = replies.recv().await {
+                    output.push(line);
+                }
+            }))
+            .map_err(|_| AdminError::Unavailable(format!("{} did not finish in {}s", action.name(), wait.as_secs())))?;
+        }
+        if let Some(err) = output.iter().find_map(|line| line.strip_prefix(ERROR_PREFIX)) {
+            return Err(AdminError::Failed(err.to_string()));
+        }
+        Ok(json!({ "accepted": action.name(), "output": output }))
+    }).log_requests(true));
+    let admin_loop = spawn(async move {
         while let Ok((stream, addr)) = listener.accept().await {
-            println!("TcpListener accept: {} ", addr);
-            let dir = dir.clone();
+            println!("admin accept: {} ", addr);
+            let service = Arc::clone(&service);
             let cmd_sender = cmd_sender.clone();
+            // 处理函数要等主循环执行完命令，serve_connection 把它放在可以阻塞的线程上
             spawn(async move {
-                if let Ok(cmd) = handle_connection(stream, dir).await {
-                    cmd_sender.send(cmd).await.unwrap();
+                match serve_connection(stream, service, KeepAlive::default()).await {
+                    // quit 的回复已经发出，可以退出了
+                    Ok(Some(Signal::Quit)) => cmd_sender.send(Job::new(Command::Quit)).await.unwrap_or_default(),
+                    Ok(None) => {}
+                    Err(err) => eprintln!("admin connection failed: {}", err),
                 }
             });
         }
     });
-    Ok(accept_loop)
+    Ok(admin_loop)
 }
 
-async fn handle_connection(mut stream: TcpStream, dir: PathBuf) -> Result<Command> {
-    let mut str = String::new();
-    BufReader::new(&mut stream).read_line(&mut str).await?;
+fn vhost_names(vhosts: &BTreeMap<String, PathBuf>) -> BTreeMap<String, String> {
+    vhosts.iter().map(|(name, dir)| (name.clone(), dir.to_string_lossy().into_owned())).collect()
+}
 
-    let strsubs: Vec<_> = str.split(" ").collect();
-    if strsubs.len() < 3 {
-        return Err(Error::from(ErrorKind::InvalidInput));
+// 当前的文件服务，dir、vhost 或 listing 改变时整个换掉：之后的请求立即使用新的，正在处理的请求不受影响
+#[derive(Clone)]
+struct Files {
+    current: Arc<RwLock<Arc<VirtualHosts>>>,
+}
+
+impl Files {
+    fn new(dir: &PathBuf, vhosts: &BTreeMap<String, PathBuf>, listing: bool) -> Files {
+        Files {
+            current: Arc::new(RwLock::new(Arc::new(virtual_hosts(dir, vhosts, listing)))),
+        }
     }
-    let method = strsubs[0];
-    let path = strsubs[1];
-    println!("method: {method} , path: {path}");
 
-    let (path, query) = match path.find("?") {
-        Some(pos) => (&path[..pos], &path[(pos+1)..]),
-        None => (path, ""),
-    };
+    fn update(&self, dir: &PathBuf, vhosts: &BTreeMap<String, PathBuf>, listing: bool) {
+        *self.current.write().unwrap() = Arc::new(virtual_hosts(dir, vhosts, listing));
+    }
+}
 
-    if query == "sleep" {
-        sleep(Duration::new(4, 0)).await;
+impl Service for Files {
+    fn call(&self, request: &Request) -> Reply {
+        let service = Arc::clone(&self.current.read().unwrap());
+        service.call(request)
     }
+}
+
+// 没有匹配的主机名时用 dir
+fn virtual_hosts(dir: &PathBuf, vhosts: &BTreeMap<String, PathBuf>, listing: bool) -> VirtualHosts {
+    vhosts.iter().fold(
+        VirtualHosts::new(Arc::new(file_service(dir, listing))),
+        |hosts, (name, vhost_dir)| hosts.host(name, Arc::new(file_service(vhost_dir, listing))),
+    )
+}
 
-    if path == "/" {
-        stream.write("HTTP/1.1 200 OK\r\n\r\n<html><body>Welcome</body></html>".as_bytes()).await?;
+fn file_service(dir: &PathBuf, listing: bool) -> FileService {
+    // 拒绝 `..`、点文件和指向目录之外的符号链接；客户端接受时换成预压缩的 .br/.gz 文件
+    let static_files = StaticFiles::new(dir.clone())
+        .listing(listing)
+        .compression(true)
+        .precompressed(true);
+    let service = FileService::new(static_files).log_requests(true);
+    // 开启目录列表时根目录也按目录处理
+    if listing {
+        service
     } else {
-        let relative_path = match path.strip_prefix("/") {
-            Some(p) => p,
-            None => path,
-        };
-        match File::open(dir.join(relative_path)).await {
-            Ok(mut f) => {
-                stream.write("HTTP/1.1 200 OK\r\n\r\n".as_bytes()).await?;
-                copy(&mut f, &mut stream).await?;
+        service.welcome("<html><body>Welcome</body></html>")
+    }
+}
+
+// 还没结束的任务，结束时自己移除；`T` 是 connections 命令显示的信息
+struct Tasks<T> {
+    next_id: u64,
+    tasks: HashMap<u64, (T, JoinHandle<()>)>,
+}
+
+impl<T> Default for Tasks<T> {
+    fn default() -> Self {
+        Tasks {
+            next_id: 0,
+            tasks: HashMap::new(),
+        }
+    }
+}
+
+impl<T: Send + 'static> Tasks<T> {
+    fn spawn<F>(registry: &Arc<Mutex<Tasks<T>>>, info: T, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        // 先加锁再 spawn，保证任务结束时移除的是已经登记的任务
+        let mut tasks = registry.lock().unwrap();
+        let id = tasks.next_id;
+        tasks.next_id += 1;
+        let registry = Arc::clone(registry);
+        let handle = spawn(async move {
+            task.await;
+            registry.lock().unwrap().tasks.remove(&id);
+        });
+        tasks.tasks.insert(id, (info, handle));
+    }
+
+    fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    // 等所有任务最多 grace，之后取消剩下的，返回正常结束和取消的个数
+    async fn drain(registry: &Arc<Mutex<Tasks<T>>>, grace: Duration) -> (usize, usize) {
+        let handles: Vec<_> = registry.lock().unwrap().tasks.drain().map(|(_, (_, handle))| handle).collect();
+        let deadline = Instant::now() + grace;
+        let (mut drained, mut aborted) = (0, 0);
+        for mut handle in handles {
+            match timeout(deadline.saturating_duration_since(Instant::now()), &mut handle).await {
+                Ok(()) => drained += 1,
+                Err(_) => {
+                    handle.cancel().await;
+                    aborted += 1;
+                }
             }
-            Err(err) => {
-                eprintln!("{}", err);
-                stream.write(format!("HTTP/1.1 404 NOT FOUND\r\n\r\n<html><body>Not Found {}</body></html>", path).as_bytes()).await?;
+        }
+        (drained, aborted)
+    }
+}
+
+// connections 命令显示的连接信息
+struct ConnectionInfo {
+    peer: SocketAddr,
+    port: u16,
+    accepted: Instant,
+}
+
+// 运行中的 http 服务
+struct Server {
+    // 每个监听的端口一个 accept_loop，所有端口共用连接表和文件服务
+    listeners: Vec<(u16, JoinHandle<()>)>,
+    connections: Arc<Mutex<Tasks<ConnectionInfo>>>,
+    // drop 后连接不再接受新的请求，见 serve_connection_until
+    shutdown: Sender<()>,
+    shutdown_receiver: Receiver<()>,
+    files: Files,
+}
+
+impl Server {
+    // 所有端口都监听成功才启动，有一个失败时已经监听的端口随之关闭
+    async fn start(host: &str, ports: &[u16], files: Files) -> Result<Server> {
+        let mut bound = Vec::new();
+        for port in ports {
+            bound.push((*port, TcpListener::bind((host, *port)).await?));
+        }
+        let (shutdown, shutdown_receiver) = channel::<()>();
+        let mut server = Server {
+            listeners: Vec::new(),
+            connections: Arc::new(Mutex::new(Tasks::default())),
+            shutdown,
+            shutdown_receiver,
+            files,
+        };
+        for (port, listener) in bound {
+            let accept_loop = server.spawn_accept_loop(port, listener);
+            server.listeners.push((port, accept_loop));
+        }
+        Ok(server)
+    }
+
+    fn spawn_accept_loop(&self, port: u16, listener: TcpListener) -> JoinHandle<()> {
+        spawn(accept_loop(
+            port,
+            listener,
+            Arc::clone(&self.connections),
+            self.shutdown_receiver.clone(),
+            self.files.clone(),
+        ))
+    }
+
+    async fn listen(&mut self, host: &str, port: u16) -> Result<()> {
+        let listener = TcpListener::bind((host, port)).await?;
+        let accept_loop = self.spawn_accept_loop(port, listener);
+        self.listeners.push((port, accept_loop));
+        Ok(())
+    }
+
+    // 停止接受这个端口的新连接，已经建立的连接继续处理
+    async fn unlisten(&mut self, port: u16) {
+        if let Some(index) = self.listeners.iter().position(|(listening, _)| *listening == port) {
+            self.listeners.remove(index).1.cancel().await;
+        }
+    }
+
+    // 换到新端口：新端口监听成功后才停止旧端口的 accept_loop，旧端口上已经建立的连接继续处理
+    async fn rebind(&mut self, host: &str, old_port: u16, port: u16) -> Result<()> {
+        let listener = TcpListener::bind((host, port)).await?;
+        let accept_loop = self.spawn_accept_loop(port, listener);
+        match self.listeners.iter_mut().find(|(listening, _)| *listening == old_port) {
+            Some(entry) => {
+                std::mem::replace(entry, (port, accept_loop)).1.cancel().await;
             }
+            None => self.listeners.push((port, accept_loop)),
         }
+        Ok(())
     }
-    stream.flush().await?;
 
-    if query == "quit" {
-        return Ok(Command::Quit);
+    fn connections_json(&self) -> Vec<Value> {
+        let connections = self.connections.lock().unwrap();
+        let mut list: Vec<_> = connections.tasks.iter().map(|(id, (info, _))| (*id, info)).collect();
+        list.sort_by_key(|(id, _)| *id);
+        list.into_iter()
+            .map(|(id, info)| {
+                json!({
+                    "id": id,
+                    "peer": info.peer.to_string(),
+                    "port": info.port,
+                    "age": info.accepted.elapsed().as_secs(),
+                })
+            })
+            .collect()
     }
-    if query == "stop" {
-        return Ok(Command::Stop);
+
+    // 停止接受新连接，等正在处理的请求最多 grace，之后强制关闭剩下的连接，返回正常结束和强制关闭的连接数
+    async fn stop(self, grace: Duration) -> (usize, usize) {
+        for (_, accept_loop) in self.listeners {
+            accept_loop.cancel().await;
+        }
+        drop(self.shutdown);
+        Tasks::drain(&self.connections, grace).await
+    }
+}
+
+async fn accept_loop(port: u16, listener: TcpListener, connections: Arc<Mutex<Tasks<ConnectionInfo>>>, shutdown: Receiver<()>, files: Files) {
+    while let Ok((stream, addr)) = listener.accept().await {
+        println!("TcpListener accept: {} ", addr);
+        let shutdown = shutdown.clone();
+        let files = Arc::new(files.clone());
+        let info = ConnectionInfo {
+            peer: addr,
+            port,
+            accepted: Instant::now(),
+        };
+        Tasks::spawn(&connections, info, async move {
+            if let Err(err) = serve_connection_until(stream, files, KeepAlive::default(), &shutdown).await {
+                eprintln!("handle connection failed: {}", err);
+            }
+        });
     }
-    return Ok(Command::Unknown);
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Vec<Command> {
+        parse_command(line).unwrap()
+    }
+
+    // 命令的名字和参数，用来比较
+    fn describe(cmd: &Command) -> String {
+        match cmd {
+            Command::Start => "start".to_string(),
+            Command::Stop => "stop".to_string(),
+            Command::Quit => "quit".to_string(),
+            Command::Port(port) => format!("port {}", port),
+            Command::Dir(dir) => format!("dir {}", dir),
+            Command::Listing(on) => format!("listing {}", on),
+            Command::Grace(grace) => format!("grace {}", grace.as_secs()),
+            Command::Listen(port) => format!("listen {}", port),
+            Command::Unlisten(port) => format!("unlisten {}", port),
+            Command::VhostAdd(host, dir) => format!("vhost add {} {}", host, dir),
+            Command::VhostRemove(host) => format!("vhost remove {}", host),
+            Command::VhostList => "vhost list".to_string(),
+            Command::Status => "status".to_string(),
+            Command::Connections => "connections".to_string(),
+            Command::Help(name) => format!("help {:?}", name),
+            Command::ConfigDump => "config dump".to_string(),
+        }
+    }
+
+    fn parsed(line: &str) -> Vec<String> {
+        parse(line).iter().map(describe).collect()
+    }
+
+    #[test]
+    fn parse_commands() {
+        assert_eq!(parsed("start"), ["start"]);
+        assert_eq!(parsed("  port   8080 \n"), ["port 8080"]);
+        assert_eq!(parsed("vhost add example.com /srv/example"), ["vhost add example.com /srv/example"]);
+        assert_eq!(parsed("vhost remove example.com"), ["vhost remove example.com"]);
+        assert_eq!(parsed("listing off"), ["listing false"]);
+        assert_eq!(parsed("grace 3"), ["grace 3"]);
+        assert_eq!(parsed("help port"), ["help Some(\"port\")"]);
+        assert_eq!(parsed("config dump"), ["config dump"]);
+    }
+
+    #[test]
+    fn restart_is_stop_and_start() {
+        assert_eq!(parsed("restart"), ["stop", "start"]);
+    }
+
+    #[test]
+    fn vhost_without_argument_lists() {
+        assert_eq!(parsed("vhost"), ["vhost list"]);
+        assert_eq!(parsed("vhost list"), ["vhost list"]);
+    }
+
+    #[test]
+    fn blank_line_is_empty() {
+        assert!(parse("").is_empty());
+        assert!(parse("   \t\n").is_empty());
+    }
+
+    #[test]
+    fn invalid_commands() {
+        let err = |line: &str| parse_command(line).err().unwrap();
+        assert_eq!(err("port"), "usage: port <num>");
+        assert_eq!(err("port 1 2"), "usage: port <num>");
+        assert_eq!(err("port abc"), "invalid argument \"abc\", usage: port <num>");
+        assert_eq!(err("port 70000"), "invalid argument \"70000\", usage: port <num>");
+        assert_eq!(err("listing maybe"), "usage: listing on|off");
+        assert_eq!(err("vhost add example.com"), "usage: vhost add <host> <dir> | vhost remove <host> | vhost list");
+        assert_eq!(err("restart now"), "usage: restart");
+        assert_eq!(err("fly"), "unknown command \"fly\", try help");
+    }
+
+    // 用假的主循环执行脚本，返回执行了的命令；`failing` 命令输出错误
+    fn run(script: &str, failing: &str) -> Vec<String> {
+        let failing = failing.to_string();
+        task::block_on(async move {
+            let (cmd_sender, cmd_receiver) = channel::<Job>();
+            let main_loop = spawn(async move {
+                let mut executed = Vec::new();
+                while let Ok(job) = cmd_receiver.recv().await {
+                    let cmd = describe(&job.cmd);
+                    if cmd == failing {
+                        job.out.error("failed");
+                    } else {
+                        job.out.line("ok");
+                    }
+                    executed.push(cmd);
+                }
+                executed
+            });
+            run_script("test.script".to_string(), script.to_string(), cmd_sender).await;
+            main_loop.await
+        })
+    }
+
+    #[test]
+    fn script_runs_commands_in_order() {
+        let script = "# comment\n\nport 8080\n  restart  \nvhost\n";
+        assert_eq!(run(script, ""), ["port 8080", "stop", "start", "vhost list"]);
+    }
+
+    #[test]
+    fn script_stops_at_invalid_line() {
+        assert_eq!(run("start\nport abc\nstop\n", ""), ["start"]);
+    }
+
+    #[test]
+    fn script_stops_when_a_command_fails() {
+        assert_eq!(run("restart\nstatus\n", "stop"), ["stop"]);
+        assert_eq!(run("start\nstatus\nstop\n", "status"), ["start", "status"]);
+    }
+}
@@ -0,0 +1,247 @@
+use std::env;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use super::panic::{JobPanic, PanicHandler};
+use super::{OverflowPolicy, PoolCreationError, Scheduler, ShutdownPolicy, ThreadPool};
+
+/// 线程池构建器。
+///
+/// 线程数的确定顺序：`size_from_env` 指定的环境变量（设置了的话）、`size`、CPU 核数。
+/// 设置了比它大的 `max_size` 时线程池是弹性的：任务积压时加线程，空闲超过 `keep_alive` 的线程退出。
+pub struct Builder {
+    pub(super) size: Option<usize>,
+    pub(super) size_env: Option<String>,
+    pub(super) max_size: Option<usize>,
+    pub(super) keep_alive: Duration,
+    pub(super) name_prefix: Option<String>,
+    pub(super) stack_size: Option<usize>,
+    pub(super) queue_capacity: Option<usize>,
+    pub(super) overflow_policy: OverflowPolicy,
+    pub(super) scheduler: Scheduler,
+    pub(super) starvation_limit: usize,
+    pub(super) shutdown_policy: ShutdownPolicy,
+    pub(super) panic_handler: Option<Arc<PanicHandler>>,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder::new()
+    }
+}
+
+impl Builder {
+    pub fn new() -> Builder {
+        Builder {
+            size: None,
+            size_env: None,
+            max_size: None,
+            keep_alive: Duration::from_secs(60),
+            name_prefix: None,
+            stack_size: None,
+            queue_capacity: None,
+            overflow_policy: OverflowPolicy::default(),
+            scheduler: Scheduler::default(),
+            starvation_limit: 8,
+            shutdown_policy: ShutdownPolicy::default(),
+            panic_handler: None,
+        }
+    }
+
+    /// 线程数，弹性线程池中是最少的线程数
+    pub fn size(mut self, size: usize) -> Builder {
+        self.size = Some(size);
+        self
+    }
+
+    /// 从环境变量（如 `RUST_WEB_WORKERS`）读取线程数，环境变量没有设置时使用 `size`。
+    /// 环境变量的值不是正整数时 `build` 返回 `PoolCreationError::InvalidEnv`。
+    pub fn size_from_env(mut self, var: &str) -> Builder {
+        self.size_env = Some(var.to_string());
+        self
+    }
+
+    /// 最多的线程数，默认等于 `size`（线程数固定）。
+    /// 队列中的任务比空闲线程多时加线程，直到这个上限；小于 `size` 时 `build` 返回 `PoolCreationError::MaxBelowMin`。
+    pub fn max_size(mut self, size: usize) -> Builder {
+        self.max_size = Some(size);
+        self
+    }
+
+    /// 弹性线程池中多出来的线程空闲多久后退出，默认 60 秒
+    pub fn keep_alive(mut self, keep_alive: Duration) -> Builder {
+        self.keep_alive = keep_alive;
+        self
+    }
+
+    /// 线程名前缀，线程名为 `{prefix}-{id}`
+    pub fn name_prefix(mut self, prefix: &str) -> Builder {
+        self.name_prefix = Some(prefix.to_string());
+        self
+    }
+
+    /// 线程栈大小（字节）
+    pub fn stack_size(mut self, size: usize) -> Builder {
+        self.stack_size = Some(size);
+        self
+    }
+
+    /// 队列容量（至少为 1），队列满时按 `overflow_policy` 处理新任务。默认不限。
+    pub fn queue_capacity(mut self, capacity: usize) -> Builder {
+        self.queue_capacity = Some(capacity.max(1));
+        self
+    }
+
+    /// 有界队列已满时的处理方式，默认是 `OverflowPolicy::Reject`
+    pub fn overflow_policy(mut self, policy: OverflowPolicy) -> Builder {
+        self.overflow_policy = policy;
+        self
+    }
+
+    /// 调度方式，默认是 `Scheduler::Shared`
+    pub fn scheduler(mut self, scheduler: Scheduler) -> Builder {
+        self.scheduler = scheduler;
+        self
+    }
+
+    /// 有任务的低优先级队列被连续跳过多少次后先从它取一个任务（至少为 1），默认 8 次
+    pub fn starvation_limit(mut self, limit: usize) -> Builder {
+        self.starvation_limit = limit.max(1);
+        self
+    }
+
+    /// 停机策略，见 `ThreadPool::set_shutdown_policy`
+    pub fn shutdown_policy(mut self, policy: ShutdownPolicy) -> Builder {
+        self.shutdown_policy = policy;
+        self
+    }
+
+    /// 任务 panic 时的回调，见 `ThreadPool::set_panic_handler`
+    pub fn panic_handler<F>(mut self, handler: F) -> Builder
+    where
+        F: Fn(&JobPanic) + Send + Sync + 'static,
+    {
+        self.panic_handler = Some(Arc::new(handler));
+        self
+    }
+
+    pub fn build(self) -> Result<ThreadPool, PoolCreationError> {
+        ThreadPool::start(self)
+    }
+
+    /// 最终使用的线程数
+    pub(super) fn resolve_size(&self) -> Result<usize, PoolCreationError> {
+        let from_env = match &self.size_env {
+            Some(var) => match env::var(var) {
+                Ok(value) => match value.trim().parse::<usize>() {
+                    Ok(size) if size > 0 => Some(size),
+                    _ => {
+                        return Err(PoolCreationError::InvalidEnv {
+                            var: var.clone(),
+                            value,
+                        })
+                    }
+                },
+                Err(_) => None,
+            },
+            None => None,
+        };
+        let size = from_env.or(self.size).unwrap_or_else(|| {
+            thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(4)
+        });
+        if size == 0 {
+            return Err(PoolCreationError::ZeroSize);
+        }
+        Ok(size)
+    }
+
+    /// 最终使用的线程数上限，`min` 是 `resolve_size` 的结果
+    pub(super) fn resolve_max_size(&self, min: usize) -> Result<usize, PoolCreationError> {
+        match self.max_size {
+            Some(max) if max < min => Err(PoolCreationError::MaxBelowMin { min, max }),
+            Some(max) => Ok(max),
+            None => Ok(min),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::sync::mpsc;
+
+    use super::*;
+    use crate::pool::ExecuteError;
+
+    #[test]
+    fn zero_size_is_an_error() {
+        assert!(matches!(ThreadPool::build(0), Err(PoolCreationError::ZeroSize)));
+        assert!(matches!(Builder::new().size(0).build(), Err(PoolCreationError::ZeroSize)));
+    }
+
+    #[test]
+    fn max_below_min_is_an_error() {
+        let err = Builder::new().size(4).max_size(2).build().err().unwrap();
+        assert!(matches!(err, PoolCreationError::MaxBelowMin { min: 4, max: 2 }));
+        assert_eq!(err.to_string(), "thread pool max size 2 is less than its size 4");
+        assert_eq!(Builder::new().size(2).max_size(2).resolve_max_size(2).unwrap(), 2);
+    }
+
+    #[test]
+    fn size_from_env() {
+        // 每个用例用自己的环境变量，测试并行执行时互不影响
+        let unset = "RUST_WEB_TEST_POOL_SIZE_UNSET";
+        env::remove_var(unset);
+        assert_eq!(Builder::new().size(3).size_from_env(unset).resolve_size().unwrap(), 3);
+
+        let var = "RUST_WEB_TEST_POOL_SIZE";
+        env::set_var(var, " 5 ");
+        // 环境变量优先于 `size`
+        let builder = Builder::new().size(3).size_from_env(var);
+        assert_eq!(builder.resolve_size().unwrap(), 5);
+        let pool = builder.build().unwrap();
+        assert_eq!(pool.metrics().size, 5);
+        pool.shutdown();
+    }
+
+    #[test]
+    fn invalid_env_is_an_error() {
+        let var = "RUST_WEB_TEST_POOL_SIZE_INVALID";
+        for value in ["abc", "0", "-1", ""] {
+            env::set_var(var, value);
+            match Builder::new().size(2).size_from_env(var).build() {
+                Err(PoolCreationError::InvalidEnv { var: v, value: got }) => {
+                    assert_eq!(v, var);
+                    assert_eq!(got, value);
+                }
+                other => panic!("{:?}: {:?}", value, other.map(|_| ())),
+            }
+        }
+        env::set_var(var, "abc");
+        let err = Builder::new().size_from_env(var).build().err().unwrap();
+        assert_eq!(err.to_string(), "invalid thread pool size in RUST_WEB_TEST_POOL_SIZE_INVALID: \"abc\"");
+    }
+
+    #[test]
+    fn try_execute() {
+        let pool = ThreadPool::build(1).unwrap();
+        let (sender, receiver) = mpsc::channel();
+        assert_eq!(pool.try_execute(move || sender.send(1).unwrap()), Ok(()));
+        assert_eq!(receiver.recv().unwrap(), 1);
+
+        pool.shutdown();
+        assert_eq!(pool.try_execute(|| {}), Err(ExecuteError::Closed));
+        assert!(pool.try_spawn(|| 1).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "thread pool has been shut down")]
+    fn execute_after_shutdown_panics() {
+        let pool = ThreadPool::new(1);
+        pool.shutdown();
+        pool.execute(|| {});
+    }
+}
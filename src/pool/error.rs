@@ -0,0 +1,80 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// 创建线程池失败
+#[derive(Debug)]
+pub enum PoolCreationError {
+    /// 线程数为 0
+    ZeroSize,
+    /// 环境变量中的线程数不是正整数
+    InvalidEnv { var: String, value: String },
+    /// 最多的线程数小于最少的线程数
+    MaxBelowMin { min: usize, max: usize },
+    /// 创建线程失败
+    Spawn(io::Error),
+}
+
+impl fmt::Display for PoolCreationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PoolCreationError::ZeroSize => write!(f, "thread pool size must be greater than 0"),
+            PoolCreationError::InvalidEnv { var, value } => {
+                write!(f, "invalid thread pool size in {}: {:?}", var, value)
+            }
+            PoolCreationError::MaxBelowMin { min, max } => {
+                write!(f, "thread pool max size {} is less than its size {}", max, min)
+            }
+            PoolCreationError::Spawn(err) => write!(f, "failed to spawn worker thread: {}", err),
+        }
+    }
+}
+
+impl Error for PoolCreationError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PoolCreationError::Spawn(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// 提交任务失败
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecuteError {
+    /// 线程池已经停机（或已经没有可用的工作线程）
+    Closed,
+    /// 有界队列已满，且溢出策略是 `OverflowPolicy::Reject`
+    QueueFull,
+}
+
+impl fmt::Display for ExecuteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExecuteError::Closed => write!(f, "thread pool has been shut down"),
+            ExecuteError::QueueFull => write!(f, "thread pool queue is full"),
+        }
+    }
+}
+
+impl Error for ExecuteError {}
+
+/// `JobHandle` 等待任务结果失败
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JobError {
+    /// 任务 panic，附带 panic 信息
+    Panicked(String),
+    /// 任务没有执行就被丢弃了
+    Cancelled,
+}
+
+impl fmt::Display for JobError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JobError::Panicked(msg) => write!(f, "job panicked: {}", msg),
+            JobError::Cancelled => write!(f, "job was cancelled before it ran"),
+        }
+    }
+}
+
+impl Error for JobError {}
@@ -0,0 +1,234 @@
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
+
+use super::panic::panic_message;
+use super::JobError;
+
+/// `ThreadPool::spawn` 返回的任务句柄。
+/// 可以在同步代码中用 `join` 阻塞等待结果，也可以在异步代码中直接 `.await`。
+pub struct JobHandle<T> {
+    inner: Arc<Inner<T>>,
+}
+
+struct Inner<T> {
+    slot: Mutex<Slot<T>>,
+    done: Condvar,
+}
+
+struct Slot<T> {
+    result: Option<Result<T, JobError>>,
+    waker: Option<Waker>,
+}
+
+impl<T> Inner<T> {
+    fn lock(&self) -> MutexGuard<'_, Slot<T>> {
+        self.slot.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T> JobHandle<T> {
+    /// 阻塞等待任务结束。任务 panic 时返回 `JobError::Panicked`，
+    /// 任务没有执行就被丢弃（停机时放弃、队列满时被挤掉）时返回 `JobError::Cancelled`。
+    pub fn join(self) -> Result<T, JobError> {
+        let mut slot = self.inner.lock();
+        loop {
+            if let Some(result) = slot.result.take() {
+                return result;
+            }
+            slot = self
+                .inner
+                .done
+                .wait(slot)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    /// 任务是否已经结束（包括 panic 和被取消）
+    pub fn is_finished(&self) -> bool {
+        self.inner.lock().result.is_some()
+    }
+}
+
+impl<T> Future for JobHandle<T> {
+    type Output = Result<T, JobError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut slot = self.inner.lock();
+        match slot.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// 包装任务：在工作线程中执行并把结果写回句柄
+pub(super) fn wrap<'a, F, T>(f: F) -> (impl FnOnce() + Send + 'a, JobHandle<T>)
+where
+    F: FnOnce() -> T + Send + 'a,
+    T: Send + 'a,
+{
+    let inner = Arc::new(Inner {
+        slot: Mutex::new(Slot {
+            result: None,
+            waker: None,
+        }),
+        done: Condvar::new(),
+    });
+    let completer = Completer {
+        inner: Some(Arc::clone(&inner)),
+    };
+    let job = move || {
+        let result = panic::catch_unwind(AssertUnwindSafe(f))
+            .map_err(|payload| JobError::Panicked(panic_message(payload.as_ref())));
+        completer.complete(result);
+    };
+    (job, JobHandle { inner })
+}
+
+struct Completer<T> {
+    inner: Option<Arc<Inner<T>>>,
+}
+
+impl<T> Completer<T> {
+    fn complete(mut self, result: Result<T, JobError>) {
+        if let Some(inner) = self.inner.take() {
+            finish(&inner, result);
+        }
+    }
+}
+
+impl<T> Drop for Completer<T> {
+    // 任务没有执行就被丢弃时，让等待的一方得到 Cancelled 而不是永远等下去
+    fn drop(&mut self) {
+        if let Some(inner) = self.inner.take() {
+            finish(&inner, Err(JobError::Cancelled));
+        }
+    }
+}
+
+fn finish<T>(inner: &Inner<T>, result: Result<T, JobError>) {
+    let waker = {
+        let mut slot = inner.lock();
+        slot.result = Some(result);
+        slot.waker.take()
+    };
+    inner.done.notify_all();
+    if let Some(waker) = waker {
+        waker.wake();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    use futures::executor::block_on;
+
+    use crate::pool::{JobError, OverflowPolicy, ShutdownPolicy};
+    use crate::ThreadPool;
+
+    #[test]
+    fn join() {
+        let pool = ThreadPool::new(2);
+        let handles: Vec<_> = (0..10).map(|i| pool.spawn(move || i * i)).collect();
+        let results: Vec<_> = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+        assert_eq!(results, (0..10).map(|i| i * i).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn join_waits_for_the_job() {
+        let pool = ThreadPool::new(1);
+        let (release, released) = mpsc::channel::<()>();
+        let handle = pool.spawn(move || {
+            let _ = released.recv();
+            "done"
+        });
+        thread::sleep(Duration::from_millis(20));
+        assert!(!handle.is_finished());
+        drop(release);
+        assert_eq!(handle.join(), Ok("done"));
+    }
+
+    #[test]
+    fn await_handle() {
+        let pool = ThreadPool::new(1);
+        let (release, released) = mpsc::channel::<()>();
+        let handle = pool.spawn(move || {
+            let _ = released.recv();
+            String::from("async")
+        });
+        // 先 poll 一次得到 Pending，任务结束时通过 waker 唤醒
+        let releaser = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            drop(release);
+        });
+        assert_eq!(block_on(handle), Ok(String::from("async")));
+        releaser.join().unwrap();
+
+        assert_eq!(block_on(pool.spawn(|| 42)), Ok(42));
+    }
+
+    #[test]
+    fn panicked_job() {
+        let pool = ThreadPool::new(1);
+        let handle = pool.spawn(|| -> i32 { panic!("job failed: {}", 7) });
+        assert_eq!(handle.join(), Err(JobError::Panicked(String::from("job failed: 7"))));
+        assert_eq!(block_on(pool.spawn(|| -> () { panic!("async") })), Err(JobError::Panicked(String::from("async"))));
+
+        // 通过句柄返回的 panic 不计入 panic 回调，线程还能继续执行任务
+        assert_eq!(pool.panicked_jobs(), 0);
+        assert_eq!(pool.spawn(|| 1).join(), Ok(1));
+    }
+
+    #[test]
+    fn abandoned_job_is_cancelled() {
+        let pool = ThreadPool::builder().size(1).shutdown_policy(ShutdownPolicy::Abandon).build().unwrap();
+        let (started_sender, started) = mpsc::channel();
+        let (release, released) = mpsc::channel::<()>();
+        let running = pool.spawn(move || {
+            started_sender.send(()).unwrap();
+            let _ = released.recv();
+        });
+        started.recv().unwrap();
+        let queued = pool.spawn(|| 1);
+
+        let release = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            drop(release);
+        });
+        pool.shutdown();
+        release.join().unwrap();
+        assert_eq!(running.join(), Ok(()));
+        assert_eq!(queued.join(), Err(JobError::Cancelled));
+    }
+
+    #[test]
+    fn dropped_job_is_cancelled() {
+        let pool = ThreadPool::builder()
+            .size(1)
+            .queue_capacity(1)
+            .overflow_policy(OverflowPolicy::DropOldest)
+            .build()
+            .unwrap();
+        let (release, released) = mpsc::channel::<()>();
+        let (started_sender, started) = mpsc::channel();
+        pool.execute(move || {
+            started_sender.send(()).unwrap();
+            let _ = released.recv();
+        });
+        started.recv().unwrap();
+        let oldest = pool.spawn(|| 1);
+        let newest = pool.spawn(|| 2);
+        assert_eq!(block_on(oldest), Err(JobError::Cancelled));
+        drop(release);
+        assert_eq!(newest.join(), Ok(2));
+    }
+}
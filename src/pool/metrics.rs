@@ -0,0 +1,129 @@
+/// 线程池当前状态的快照，见 `ThreadPool::metrics`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolMetrics {
+    /// 当前的线程数
+    pub size: usize,
+    /// 正在等待任务的线程数
+    pub idle: usize,
+    /// 队列中等待执行的任务数
+    pub queued: usize,
+    /// 最少的线程数
+    pub min_size: usize,
+    /// 最多的线程数，等于 `min_size` 时线程数固定
+    pub max_size: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::pool::Scheduler;
+    use crate::ThreadPool;
+
+    // 等到线程数变成 `size`，超时返回 false
+    fn wait_for_size(pool: &ThreadPool, size: usize) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            if pool.metrics().size == size {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        false
+    }
+
+    fn grow_and_retire(scheduler: Scheduler) {
+        let pool = ThreadPool::builder()
+            .size(1)
+            .max_size(4)
+            .keep_alive(Duration::from_millis(50))
+            .scheduler(scheduler)
+            .build()
+            .unwrap();
+        // 线程启动后才登记为空闲
+        while pool.metrics().idle == 0 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(
+            pool.metrics(),
+            PoolMetrics {
+                size: 1,
+                idle: 1,
+                queued: 0,
+                min_size: 1,
+                max_size: 4,
+            }
+        );
+
+        // 任务积压时加线程，4 个任务同时在执行
+        let (started_sender, started) = mpsc::channel();
+        let mut release = Vec::new();
+        for _ in 0..5 {
+            let started_sender = started_sender.clone();
+            let (sender, released) = mpsc::channel::<()>();
+            release.push(sender);
+            pool.execute(move || {
+                started_sender.send(()).unwrap();
+                let _ = released.recv();
+            });
+        }
+        for _ in 0..4 {
+            started.recv_timeout(Duration::from_secs(5)).unwrap();
+        }
+        // 到了上限不再加线程，第 5 个任务在队列中等待
+        assert!(started.recv_timeout(Duration::from_millis(50)).is_err());
+        let metrics = pool.metrics();
+        assert_eq!((metrics.size, metrics.idle, metrics.queued), (4, 0, 1));
+
+        // 空闲超过 keep_alive 的线程退出，最后剩下 `size` 个
+        drop(release);
+        started.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(wait_for_size(&pool, 1), "{:?}", pool.metrics());
+
+        // 退出后还能再加线程
+        let handles: Vec<_> = (0..4).map(|i| pool.spawn(move || i)).collect();
+        let sum: i32 = handles.into_iter().map(|handle| handle.join().unwrap()).sum();
+        assert_eq!(sum, 6);
+
+        let summary = pool.shutdown();
+        assert!(summary.retired_workers >= 3, "{:?}", summary);
+    }
+
+    #[test]
+    fn elastic_pool_grows_and_retires() {
+        grow_and_retire(Scheduler::Shared);
+    }
+
+    #[test]
+    fn elastic_pool_grows_and_retires_work_stealing() {
+        grow_and_retire(Scheduler::WorkStealing);
+    }
+
+    #[test]
+    fn fixed_pool_does_not_grow() {
+        let pool = ThreadPool::builder()
+            .size(2)
+            .keep_alive(Duration::from_millis(10))
+            .build()
+            .unwrap();
+        let mut release = Vec::new();
+        for _ in 0..4 {
+            let (sender, released) = mpsc::channel::<()>();
+            release.push(sender);
+            pool.execute(move || {
+                let _ = released.recv();
+            });
+        }
+        thread::sleep(Duration::from_millis(50));
+        let metrics = pool.metrics();
+        assert_eq!((metrics.size, metrics.min_size, metrics.max_size), (2, 2, 2));
+
+        drop(release);
+        let summary = pool.shutdown();
+        assert_eq!(summary.retired_workers, 0);
+        assert_eq!(summary.joined_workers, 2);
+    }
+}
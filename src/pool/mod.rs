@@ -0,0 +1,616 @@
+//! 线程池：工作线程从队列中取任务执行。线程数可以固定，也可以在上下限之间随负载增减。
+//! 队列可以是所有线程共享的一个加锁队列，也可以是每个线程一个的工作窃取队列，见 `Scheduler`。
+
+mod builder;
+mod error;
+mod handle;
+mod metrics;
+mod overflow;
+mod panic;
+mod priority;
+mod scheduler;
+mod scope;
+mod shutdown;
+mod timer;
+mod worker;
+
+use std::collections::VecDeque;
+use std::sync::atomic::{self, AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+pub use builder::Builder;
+pub use error::{ExecuteError, JobError, PoolCreationError};
+pub use handle::JobHandle;
+pub use metrics::PoolMetrics;
+pub use overflow::OverflowPolicy;
+pub use panic::JobPanic;
+use panic::PanicHandler;
+pub use priority::Priority;
+use priority::Lanes;
+pub use scheduler::Scheduler;
+pub use scope::Scope;
+use scheduler::StealQueues;
+pub use shutdown::{ShutdownPolicy, ShutdownSummary};
+use timer::Timer;
+pub use timer::TimerHandle;
+use worker::Worker;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+pub struct ThreadPool {
+    shared: Arc<Shared>,
+    shutdown_policy: ShutdownPolicy,
+    // 第一次调用 `schedule_after`/`schedule_every` 时才创建定时器线程
+    timer: Mutex<Option<Timer>>,
+}
+
+/// 线程池和工作线程共享的状态
+struct Shared {
+    state: Mutex<State>,
+    // 工作窃取调度器的队列，共享队列调度器时为 None，任务放在 State::queue 中
+    steal: Option<StealQueues>,
+    // 按优先级出队，防止低优先级任务饿死
+    lanes: Lanes,
+    // 线程名前缀和栈大小，重新拉起线程时也要用
+    name_prefix: Option<String>,
+    stack_size: Option<usize>,
+    queue_capacity: Option<usize>,
+    overflow_policy: OverflowPolicy,
+    // 线程数上下限，不相等时是弹性线程池，多出来的线程空闲 keep_alive 后退出
+    min_size: usize,
+    max_size: usize,
+    keep_alive: Duration,
+    // 有新任务或线程池关闭时通知工作线程
+    job_available: Condvar,
+    // 有界队列有空位或线程池关闭时通知阻塞在提交任务上的线程
+    space_available: Condvar,
+    // 工作线程退出时通知等待停机的线程
+    worker_exited: Condvar,
+    // 每个任务结束都要计数，不放在 state 锁里
+    completed: AtomicUsize,
+    // 关闭后不再接受新任务。只在持有 state 锁时修改，无锁提交任务时也要读
+    closed: AtomicBool,
+    // 正在等待任务的工作线程数，只在持有 state 锁时修改
+    idle: AtomicUsize,
+    // 正在无锁提交任务的线程数，不为 0 时工作线程不会退出
+    submitting: AtomicUsize,
+}
+
+struct State {
+    // 下标是 `Priority::index`
+    queue: [VecDeque<Job>; 3],
+    workers: Vec<Worker>,
+    // 还没退出的工作线程数
+    running: usize,
+    // 已退出、可以 join 的工作线程 id
+    exited: Vec<usize>,
+    panicked: usize,
+    // 队列满时按 DropOldest 策略丢弃的任务数
+    dropped: usize,
+    // 意外退出后被重新拉起的线程数
+    respawned: usize,
+    // 弹性线程池中因空闲超时退出的线程数
+    retired: usize,
+    panic_handler: Option<Arc<PanicHandler>>,
+}
+
+impl Shared {
+    // 任务在锁外执行并且有 catch_unwind，锁一般不会中毒；即使中毒，状态也仍然一致，可以继续使用
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // 线程池标识，用来判断当前线程是不是本线程池的工作线程
+    fn id(&self) -> usize {
+        self as *const Shared as usize
+    }
+
+    // 持有 state 锁时入队，等待任务的线程在锁内检查队列后才睡眠，不会错过通知
+    fn push(&self, state: &mut State, job: Job, priority: Priority) {
+        match &self.steal {
+            Some(steal) => steal.push(self.id(), job, priority),
+            None => state.queue[priority.index()].push_back(job),
+        }
+        if self.idle.load(Ordering::SeqCst) > 0 {
+            self.job_available.notify_one();
+        }
+    }
+
+    // 工作窃取调度器、无界队列时不加锁入队，只有需要唤醒线程时才加锁。
+    // 工作线程先登记为空闲再检查队列，这里先入队再检查空闲线程数，
+    // 两边之间都有 SeqCst 屏障，所以要么工作线程取到任务，要么这里看到空闲线程并唤醒它
+    fn push_unlocked(&self, steal: &StealQueues, job: Job, priority: Priority) -> Result<(), ExecuteError> {
+        self.submitting.fetch_add(1, Ordering::SeqCst);
+        let result = if self.closed.load(Ordering::SeqCst) {
+            Err(ExecuteError::Closed)
+        } else {
+            steal.push(self.id(), job, priority);
+            Ok(())
+        };
+        self.submitting.fetch_sub(1, Ordering::SeqCst);
+        atomic::fence(Ordering::SeqCst);
+
+        // 已经关闭时，等本线程提交完才退出的工作线程都要叫醒
+        let closed = self.closed.load(Ordering::SeqCst);
+        if closed || self.idle.load(Ordering::SeqCst) > 0 {
+            let _state = self.lock();
+            if closed {
+                self.job_available.notify_all();
+            } else {
+                self.job_available.notify_one();
+            }
+        }
+        result
+    }
+
+    // 提交任务，见 `ThreadPool::try_execute_with_priority`
+    fn submit(self: &Arc<Self>, job: Job, priority: Priority) -> Result<(), ExecuteError> {
+        self.submit_with(job, priority, self.overflow_policy)
+    }
+
+    // 按指定的溢出策略提交任务。定时器线程不能在自己的线程里执行任务，`CallerRuns` 换成 `Reject`
+    fn submit_with(
+        self: &Arc<Self>,
+        job: Job,
+        priority: Priority,
+        overflow_policy: OverflowPolicy,
+    ) -> Result<(), ExecuteError> {
+        // 弹性线程池要在入队时决定是否加线程，只能走加锁的路径
+        if let (Some(steal), None, false) = (&self.steal, self.queue_capacity, self.elastic()) {
+            return self.push_unlocked(steal, job, priority);
+        }
+
+        let mut state = self.lock();
+        let mut dropped = None;
+        loop {
+            if self.closed.load(Ordering::SeqCst) || state.running == 0 {
+                return Err(ExecuteError::Closed);
+            }
+            let full = match self.queue_capacity {
+                Some(capacity) => self.queued(&state) >= capacity,
+                None => false,
+            };
+            if !full {
+                break;
+            }
+            match overflow_policy {
+                OverflowPolicy::Reject => return Err(ExecuteError::QueueFull),
+                OverflowPolicy::Block => {
+                    state = self
+                        .space_available
+                        .wait(state)
+                        .unwrap_or_else(PoisonError::into_inner);
+                }
+                OverflowPolicy::DropOldest => {
+                    dropped = self.pop_oldest(&mut state);
+                    state.dropped += 1;
+                    break;
+                }
+                OverflowPolicy::CallerRuns => {
+                    drop(state);
+                    job();
+                    return Ok(());
+                }
+            }
+        }
+        self.push(&mut state, job, priority);
+        self.grow(&mut state);
+        drop(state);
+
+        // 被丢弃的任务在锁外析构，它持有的资源（比如连接）在这里释放
+        drop(dropped);
+        Ok(())
+    }
+
+    fn elastic(&self) -> bool {
+        self.max_size > self.min_size
+    }
+
+    // 弹性线程池：队列中的任务比空闲线程多、还没到线程数上限时加一个线程
+    fn grow(self: &Arc<Self>, state: &mut State) {
+        if state.running >= self.max_size || self.queued(state) <= self.idle.load(Ordering::SeqCst) {
+            return;
+        }
+        // 复用退出线程的 id，工作窃取调度器按 id 登记本地队列
+        let id = (0..state.workers.len())
+            .find(|id| state.workers.iter().all(|w| w.id != *id))
+            .unwrap_or(state.workers.len());
+        match Worker::new(id, Arc::clone(self), None) {
+            Ok(worker) => {
+                state.workers.push(worker);
+                state.running += 1;
+            }
+            // 加线程失败时现有的线程仍然会处理队列中的任务
+            Err(err) => eprintln!("failed to spawn worker {}: {}", id, err),
+        }
+    }
+
+    fn pop(&self, state: &mut State, worker_id: usize) -> Option<Job> {
+        match &self.steal {
+            Some(steal) => steal.pop(worker_id, &self.lanes),
+            None => {
+                let waiting = state.queue.each_ref().map(|queue| !queue.is_empty());
+                self.lanes
+                    .pick(waiting, |priority| state.queue[priority.index()].pop_front())
+            }
+        }
+    }
+
+    // 队列满时先丢弃优先级低的任务
+    fn pop_oldest(&self, state: &mut State) -> Option<Job> {
+        match &self.steal {
+            Some(steal) => steal.pop_oldest(),
+            None => state.queue.iter_mut().rev().find_map(VecDeque::pop_front),
+        }
+    }
+
+    fn drain(&self, state: &mut State) -> Vec<Job> {
+        match &self.steal {
+            Some(steal) => steal.drain(),
+            None => state.queue.iter_mut().flat_map(|queue| queue.drain(..)).collect(),
+        }
+    }
+
+    fn queued(&self, state: &State) -> usize {
+        match &self.steal {
+            Some(steal) => steal.len(),
+            None => state.queue.iter().map(VecDeque::len).sum(),
+        }
+    }
+}
+
+impl ThreadPool {
+    /// 创建线程池。
+    /// size: 线程池中线程的数量。
+    /// `new` 函数在 size 为 0 时会 panic，需要处理错误时用 `build`。
+    pub fn new(size: usize) -> ThreadPool {
+        match ThreadPool::build(size) {
+            Ok(pool) => pool,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// 创建线程池，size 为 0 或创建线程失败时返回错误。
+    pub fn build(size: usize) -> Result<ThreadPool, PoolCreationError> {
+        Builder::new().size(size).build()
+    }
+
+    /// 线程池构建器，可以设置线程名、栈大小、队列容量等
+    pub fn builder() -> Builder {
+        Builder::new()
+    }
+
+    fn start(builder: Builder) -> Result<ThreadPool, PoolCreationError> {
+        let size = builder.resolve_size()?;
+        let max_size = builder.resolve_max_size(size)?;
+
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                queue: Default::default(),
+                workers: Vec::with_capacity(size),
+                running: 0,
+                exited: Vec::new(),
+                panicked: 0,
+                dropped: 0,
+                respawned: 0,
+                retired: 0,
+                panic_handler: builder.panic_handler,
+            }),
+            steal: match builder.scheduler {
+                Scheduler::Shared => None,
+                Scheduler::WorkStealing => Some(StealQueues::new()),
+            },
+            lanes: Lanes::new(builder.starvation_limit),
+            name_prefix: builder.name_prefix,
+            stack_size: builder.stack_size,
+            queue_capacity: builder.queue_capacity,
+            overflow_policy: builder.overflow_policy,
+            min_size: size,
+            max_size,
+            keep_alive: builder.keep_alive,
+            job_available: Condvar::new(),
+            space_available: Condvar::new(),
+            worker_exited: Condvar::new(),
+            completed: AtomicUsize::new(0),
+            closed: AtomicBool::new(false),
+            idle: AtomicUsize::new(0),
+            submitting: AtomicUsize::new(0),
+        });
+
+        let pool = ThreadPool {
+            shared,
+            shutdown_policy: builder.shutdown_policy,
+            timer: Mutex::new(None),
+        };
+
+        {
+            let mut state = pool.shared.lock();
+            for id in 0..size {
+                // create some threads and store them in the vector
+                // 创建失败时 pool 被 drop，已经创建的线程会随之退出
+                let worker = Worker::new(id, Arc::clone(&pool.shared), None).map_err(PoolCreationError::Spawn)?;
+                state.workers.push(worker);
+                state.running += 1;
+            }
+        }
+
+        Ok(pool)
+    }
+
+    /// 设置 `shutdown`、`shutdown_timeout` 以及 drop 时使用的停机策略，默认是 `Drain`。
+    pub fn set_shutdown_policy(&mut self, policy: ShutdownPolicy) {
+        self.shutdown_policy = policy;
+    }
+
+    /// 设置任务 panic 时的回调，可以用来记录日志或计数。
+    /// 任务 panic 不会杀死工作线程；回调在执行该任务的线程中调用。
+    pub fn set_panic_handler<F>(&mut self, handler: F)
+    where
+        F: Fn(&JobPanic) + Send + Sync + 'static,
+    {
+        self.shared.lock().panic_handler = Some(Arc::new(handler));
+    }
+
+    /// 到目前为止 panic 的任务数
+    pub fn panicked_jobs(&self) -> usize {
+        self.shared.lock().panicked
+    }
+
+    /// 按 DropOldest 策略丢弃的任务数
+    pub fn dropped_jobs(&self) -> usize {
+        self.shared.lock().dropped
+    }
+
+    /// 队列中等待执行的任务数
+    pub fn queued_jobs(&self) -> usize {
+        let state = self.shared.lock();
+        self.shared.queued(&state)
+    }
+
+    /// 当前的线程数、空闲线程数和队列中的任务数等
+    pub fn metrics(&self) -> PoolMetrics {
+        let state = self.shared.lock();
+        PoolMetrics {
+            size: state.running,
+            idle: self.shared.idle.load(Ordering::SeqCst),
+            queued: self.shared.queued(&state),
+            min_size: self.shared.min_size,
+            max_size: self.shared.max_size,
+        }
+    }
+
+    /// 提交任务。
+    /// 线程池已经停机，或有界队列已满且溢出策略是 `Reject` 时会 panic，需要处理错误时用 `try_execute`。
+    pub fn execute<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        if let Err(err) = self.try_execute(f) {
+            panic!("{}", err);
+        }
+    }
+
+    /// 提交任务，线程池已经停机时返回 `ExecuteError::Closed`。
+    /// 有界队列已满时按溢出策略处理，`Reject` 策略返回 `ExecuteError::QueueFull`。
+    pub fn try_execute<F>(&self, f: F) -> Result<(), ExecuteError>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.try_execute_with_priority(Priority::Normal, f)
+    }
+
+    /// 按优先级提交任务，失败时的行为同 `execute`
+    pub fn execute_with_priority<F>(&self, priority: Priority, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        if let Err(err) = self.try_execute_with_priority(priority, f) {
+            panic!("{}", err);
+        }
+    }
+
+    /// 按优先级提交任务，失败时的行为同 `try_execute`。
+    /// 优先级不影响有界队列的容量检查，`DropOldest` 策略先丢弃优先级低的任务。
+    pub fn try_execute_with_priority<F>(&self, priority: Priority, f: F) -> Result<(), ExecuteError>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.submit(Box::new(f), priority)
+    }
+
+    fn submit(&self, job: Job, priority: Priority) -> Result<(), ExecuteError> {
+        self.shared.submit(job, priority)
+    }
+
+    /// 提交有返回值的任务，返回的句柄可以 `join` 阻塞等待，也可以 `.await`。
+    /// 适合在 tokio/axum 的 handler 中把 CPU 密集的计算交给线程池。
+    /// 任务 panic 时通过句柄返回 `JobError::Panicked`，不会触发 panic 回调。
+    /// 提交失败时的行为同 `execute`。
+    pub fn spawn<F, T>(&self, f: F) -> JobHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        match self.try_spawn(f) {
+            Ok(handle) => handle,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// 同 `spawn`，提交失败时的行为同 `try_execute`。
+    pub fn try_spawn<F, T>(&self, f: F) -> Result<JobHandle<T>, ExecuteError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        self.try_spawn_with_priority(Priority::Normal, f)
+    }
+
+    /// 按优先级提交有返回值的任务，失败时的行为同 `spawn`
+    pub fn spawn_with_priority<F, T>(&self, priority: Priority, f: F) -> JobHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        match self.try_spawn_with_priority(priority, f) {
+            Ok(handle) => handle,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// 按优先级提交有返回值的任务，失败时的行为同 `try_spawn`
+    pub fn try_spawn_with_priority<F, T>(&self, priority: Priority, f: F) -> Result<JobHandle<T>, ExecuteError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (job, handle) = handle::wrap(f);
+        self.try_execute_with_priority(priority, job)?;
+        Ok(handle)
+    }
+
+    /// 在 `f` 中通过 `Scope` 提交的任务可以借用调用者栈上的数据，类似 `std::thread::scope`，
+    /// 但任务在线程池现有的线程中执行，不会创建新线程。
+    /// `scope` 返回前会等待所有这些任务结束；`Scope::execute` 提交的任务 panic 时，`scope` 在等待结束后 panic。
+    /// 不要在本线程池的任务中调用，所有线程都在等待时没有线程执行它们提交的任务。
+    pub fn scope<'env, F, T>(&self, f: F) -> T
+    where
+        F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> T,
+    {
+        scope::run(self, f)
+    }
+
+    /// `delay` 之后把任务交给工作线程执行，返回的句柄可以取消它。
+    /// 到期时队列已满且溢出策略是 `Reject` 或 `CallerRuns` 时这个任务被丢弃（定时器线程不执行任务）。线程池已经停机时会 panic。
+    pub fn schedule_after<F>(&self, delay: Duration, f: F) -> TimerHandle
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.with_timer(|timer| timer.schedule_after(delay, Box::new(f)))
+    }
+
+    /// 每隔 `period` 把任务交给工作线程执行一次（第一次在 `period` 之后），直到取消或停机。
+    /// 按固定频率触发，上一次还没执行完时下一次可能在另一个线程中同时执行。
+    /// `period` 为 0 或线程池已经停机时会 panic。
+    pub fn schedule_every<F>(&self, period: Duration, f: F) -> TimerHandle
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        assert!(!period.is_zero(), "schedule_every period must be greater than 0");
+        self.with_timer(|timer| timer.schedule_every(period, Box::new(f)))
+    }
+
+    fn with_timer<T>(&self, f: impl FnOnce(&Timer) -> T) -> T {
+        let mut timer = self.timer.lock().unwrap_or_else(PoisonError::into_inner);
+        // 在定时器的锁内检查，停机时先关闭线程池再取走定时器，不会在停机后又创建一个
+        if self.shared.closed.load(Ordering::SeqCst) {
+            panic!("{}", ExecuteError::Closed);
+        }
+        let timer = match &mut *timer {
+            Some(timer) => timer,
+            None => match Timer::new(Arc::clone(&self.shared)) {
+                Ok(new) => timer.insert(new),
+                Err(err) => panic!("failed to spawn timer thread: {}", err),
+            },
+        };
+        f(timer)
+    }
+
+    /// 停机：不再接受新任务，按停机策略处理队列中的任务，等待所有线程退出。
+    /// 重复调用时后面的调用不做任何事，返回的统计都是 0。
+    pub fn shutdown(&self) -> ShutdownSummary {
+        self.shutdown_with(None)
+    }
+
+    /// 同 `shutdown`，但最多等待 `timeout`。
+    /// 超时后队列中剩余的任务不再执行，仍在执行任务的线程被分离，执行完手上的任务后自行退出。
+    pub fn shutdown_timeout(&self, timeout: Duration) -> ShutdownSummary {
+        self.shutdown_with(Some(Instant::now() + timeout))
+    }
+
+    fn shutdown_with(&self, deadline: Option<Instant>) -> ShutdownSummary {
+        let mut summary = ShutdownSummary::new(self.shutdown_policy);
+
+        let mut state = self.shared.lock();
+        if self.shared.closed.swap(true, Ordering::SeqCst) {
+            return summary;
+        }
+        // 丢弃的任务在释放锁之后才析构
+        let mut abandoned = Vec::new();
+        if self.shutdown_policy == ShutdownPolicy::Abandon {
+            abandoned = self.shared.drain(&mut state);
+        }
+        self.shared.job_available.notify_all();
+        self.shared.space_available.notify_all();
+
+        while state.running > 0 {
+            match deadline {
+                None => {
+                    state = self
+                        .shared
+                        .worker_exited
+                        .wait(state)
+                        .unwrap_or_else(PoisonError::into_inner);
+                }
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break;
+                    }
+                    state = self
+                        .shared
+                        .worker_exited
+                        .wait_timeout(state, deadline - now)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0;
+                }
+            }
+        }
+
+        if state.running > 0 {
+            // 超时：剩下的任务不再执行，忙碌的线程执行完当前任务后会发现队列已空并退出
+            summary.timed_out = true;
+            abandoned.extend(self.shared.drain(&mut state));
+        }
+        summary.abandoned_jobs = abandoned.len();
+        summary.completed_jobs = self.shared.completed.load(Ordering::Relaxed);
+        summary.panicked_jobs = state.panicked;
+        summary.dropped_jobs = state.dropped;
+        summary.respawned_workers = state.respawned;
+        summary.retired_workers = state.retired;
+
+        let workers = std::mem::take(&mut state.workers);
+        let exited = std::mem::take(&mut state.exited);
+        // join 时不能持有锁，否则还没退出的线程拿不到锁
+        drop(state);
+        drop(abandoned);
+
+        // 线程池已经关闭，定时器线程这时再提交任务只会失败，可能阻塞在有界队列上的提交也已经被叫醒
+        let timer = self.timer.lock().unwrap_or_else(PoisonError::into_inner).take();
+        if let Some(mut timer) = timer {
+            summary.cancelled_timers = timer.stop();
+        }
+
+        for mut worker in workers {
+            if !exited.contains(&worker.id) {
+                summary.detached_workers += 1;
+                continue;
+            }
+            if let Some(thread) = worker.thread.take() {
+                match thread.join() {
+                    Ok(()) => summary.joined_workers += 1,
+                    Err(_) => summary.panicked_workers += 1,
+                }
+            }
+        }
+
+        summary
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
@@ -0,0 +1,152 @@
+/// 有界队列已满时提交任务的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// 拒绝新任务，`try_execute` 返回 `ExecuteError::QueueFull`
+    #[default]
+    Reject,
+    /// 阻塞提交任务的线程，直到队列有空位（或线程池停机）
+    Block,
+    /// 丢弃队列中最早的任务，为新任务腾出位置
+    DropOldest,
+    /// 在提交任务的线程中直接执行新任务，任务 panic 会传播给调用者
+    CallerRuns,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{self, Receiver, Sender};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::pool::ExecuteError;
+    use crate::ThreadPool;
+
+    // 一个线程、队列容量 1
+    fn pool(policy: OverflowPolicy) -> ThreadPool {
+        ThreadPool::builder()
+            .size(1)
+            .queue_capacity(1)
+            .overflow_policy(policy)
+            .build()
+            .unwrap()
+    }
+
+    // 占住唯一的工作线程，直到返回的 Sender 被 drop
+    fn block_worker(pool: &ThreadPool) -> Sender<()> {
+        let (started_sender, started) = mpsc::channel();
+        let (release, released): (Sender<()>, Receiver<()>) = mpsc::channel();
+        pool.execute(move || {
+            started_sender.send(()).unwrap();
+            let _ = released.recv();
+        });
+        started.recv().unwrap();
+        release
+    }
+
+    fn record(log: &Arc<Mutex<Vec<&'static str>>>, name: &'static str) -> impl FnOnce() + Send + 'static {
+        let log = Arc::clone(log);
+        move || log.lock().unwrap().push(name)
+    }
+
+    #[test]
+    fn reject() {
+        let pool = pool(OverflowPolicy::Reject);
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let release = block_worker(&pool);
+        assert_eq!(pool.try_execute(record(&log, "queued")), Ok(()));
+        assert_eq!(pool.try_execute(record(&log, "rejected")), Err(ExecuteError::QueueFull));
+        assert_eq!(pool.queued_jobs(), 1);
+
+        drop(release);
+        pool.shutdown();
+        assert_eq!(*log.lock().unwrap(), ["queued"]);
+    }
+
+    #[test]
+    fn caller_runs() {
+        let pool = pool(OverflowPolicy::CallerRuns);
+        let release = block_worker(&pool);
+        pool.execute(|| {});
+        // 队列已满，任务在提交的线程中执行
+        let (sender, receiver) = mpsc::channel();
+        assert_eq!(pool.try_execute(move || sender.send(thread::current().id()).unwrap()), Ok(()));
+        assert_eq!(receiver.try_recv().unwrap(), thread::current().id());
+
+        drop(release);
+        pool.shutdown();
+    }
+
+    #[test]
+    #[should_panic(expected = "caller")]
+    fn caller_runs_propagates_panic() {
+        let pool = pool(OverflowPolicy::CallerRuns);
+        let _release = block_worker(&pool);
+        pool.execute(|| {});
+        pool.execute(|| panic!("caller"));
+    }
+
+    #[test]
+    fn block() {
+        let pool = Arc::new(pool(OverflowPolicy::Block));
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let release = block_worker(&pool);
+        pool.execute(record(&log, "first"));
+
+        let (submitted_sender, submitted) = mpsc::channel();
+        let submitter = {
+            let pool = Arc::clone(&pool);
+            let job = record(&log, "second");
+            thread::spawn(move || {
+                let result = pool.try_execute(job);
+                submitted_sender.send(()).unwrap();
+                result
+            })
+        };
+        // 队列有空位之前提交的线程一直阻塞
+        assert!(submitted.recv_timeout(Duration::from_millis(100)).is_err());
+
+        drop(release);
+        submitted.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(submitter.join().unwrap(), Ok(()));
+        pool.shutdown();
+        assert_eq!(*log.lock().unwrap(), ["first", "second"]);
+    }
+
+    #[test]
+    fn block_returns_closed_on_shutdown() {
+        let pool = Arc::new(pool(OverflowPolicy::Block));
+        let release = block_worker(&pool);
+        pool.execute(|| {});
+
+        let submitter = {
+            let pool = Arc::clone(&pool);
+            thread::spawn(move || pool.try_execute(|| {}))
+        };
+        thread::sleep(Duration::from_millis(50));
+        // 停机叫醒阻塞的提交者
+        let shutdown = {
+            let pool = Arc::clone(&pool);
+            thread::spawn(move || pool.shutdown())
+        };
+        assert_eq!(submitter.join().unwrap(), Err(ExecuteError::Closed));
+        drop(release);
+        shutdown.join().unwrap();
+    }
+
+    #[test]
+    fn drop_oldest() {
+        let pool = pool(OverflowPolicy::DropOldest);
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let release = block_worker(&pool);
+        pool.execute(record(&log, "oldest"));
+        pool.execute(record(&log, "newest"));
+        assert_eq!(pool.dropped_jobs(), 1);
+
+        drop(release);
+        let summary = pool.shutdown();
+        assert_eq!(summary.dropped_jobs, 1);
+        assert_eq!(*log.lock().unwrap(), ["newest"]);
+    }
+}
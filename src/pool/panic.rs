@@ -0,0 +1,23 @@
+use std::any::Any;
+
+/// 任务 panic 的信息，传给 `ThreadPool::set_panic_handler` 设置的回调
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JobPanic {
+    /// 执行该任务的线程 id
+    pub worker_id: usize,
+    /// panic 信息
+    pub message: String,
+}
+
+pub(crate) type PanicHandler = dyn Fn(&JobPanic) + Send + Sync + 'static;
+
+/// 从 panic 的 payload 中取出信息，`panic!` 的参数一般是 `&str` 或 `String`
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        String::from("Box<dyn Any>")
+    }
+}
@@ -0,0 +1,169 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// 任务优先级，优先级高的任务先出队。
+/// 为了不让低优先级的任务饿死，一个有任务的队列被连续跳过 `Builder::starvation_limit` 次后，下一次先从它取任务。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Priority {
+    /// 对延迟敏感的任务，比如健康检查
+    High,
+    #[default]
+    Normal,
+    /// 慢任务、后台任务
+    Low,
+}
+
+impl Priority {
+    // 按出队的先后顺序
+    pub(super) const ALL: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+
+    pub(super) fn index(self) -> usize {
+        match self {
+            Priority::High => 0,
+            Priority::Normal => 1,
+            Priority::Low => 2,
+        }
+    }
+}
+
+/// 决定下一个任务从哪个优先级的队列中取，记录每个队列被跳过的次数。
+/// 计数只用来防饿死，工作窃取模式下不加锁地更新，偶尔不准也没关系。
+pub(super) struct Lanes {
+    skipped: [AtomicUsize; 3],
+    starvation_limit: usize,
+}
+
+impl Lanes {
+    pub(super) fn new(starvation_limit: usize) -> Lanes {
+        Lanes {
+            skipped: Default::default(),
+            starvation_limit,
+        }
+    }
+
+    /// 按优先级依次用 `pop` 取任务。`waiting` 是取任务之前各队列是否有任务，用来给被跳过的队列计数
+    pub(super) fn pick<T>(&self, waiting: [bool; 3], mut pop: impl FnMut(Priority) -> Option<T>) -> Option<T> {
+        // 先照顾被跳过太多次的队列，优先级低的先
+        for priority in Priority::ALL.into_iter().rev() {
+            let skipped = &self.skipped[priority.index()];
+            if skipped.load(Ordering::Relaxed) < self.starvation_limit {
+                continue;
+            }
+            skipped.store(0, Ordering::Relaxed);
+            if let Some(job) = pop(priority) {
+                return Some(job);
+            }
+        }
+
+        for priority in Priority::ALL {
+            if let Some(job) = pop(priority) {
+                self.skipped[priority.index()].store(0, Ordering::Relaxed);
+                for lower in &Priority::ALL[priority.index() + 1..] {
+                    if waiting[lower.index()] {
+                        self.skipped[lower.index()].fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+                return Some(job);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::sync::mpsc;
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::pool::Scheduler;
+    use crate::ThreadPool;
+
+    // 按 `lanes` 取完三个队列，返回出队顺序
+    fn drain(lanes: &Lanes, mut queues: [VecDeque<&'static str>; 3]) -> Vec<&'static str> {
+        let mut order = Vec::new();
+        loop {
+            let waiting = queues.each_ref().map(|queue| !queue.is_empty());
+            match lanes.pick(waiting, |priority| queues[priority.index()].pop_front()) {
+                Some(job) => order.push(job),
+                None => return order,
+            }
+        }
+    }
+
+    #[test]
+    fn higher_priority_first() {
+        let lanes = Lanes::new(100);
+        let queues = [
+            VecDeque::from(["h1", "h2"]),
+            VecDeque::from(["n1", "n2"]),
+            VecDeque::from(["l1"]),
+        ];
+        assert_eq!(drain(&lanes, queues), ["h1", "h2", "n1", "n2", "l1"]);
+    }
+
+    #[test]
+    fn starved_lane_is_promoted() {
+        // 低优先级队列被连续跳过 2 次后，下一次先取它
+        let lanes = Lanes::new(2);
+        let queues = [
+            VecDeque::from(["h1", "h2", "h3", "h4", "h5"]),
+            VecDeque::new(),
+            VecDeque::from(["l1", "l2"]),
+        ];
+        assert_eq!(drain(&lanes, queues), ["h1", "h2", "l1", "h3", "h4", "l2", "h5"]);
+
+        let lanes = Lanes::new(1);
+        let queues = [
+            VecDeque::from(["h1", "h2", "h3"]),
+            VecDeque::from(["n1", "n2"]),
+            VecDeque::from(["l1"]),
+        ];
+        // h1 之后普通和低优先级都被跳过了一次，低优先级的先
+        assert_eq!(drain(&lanes, queues), ["h1", "l1", "n1", "h2", "n2", "h3"]);
+    }
+
+    #[test]
+    fn empty_lanes_are_not_counted() {
+        // 没有任务的队列不计跳过次数，之后来的任务不会插队
+        let lanes = Lanes::new(1);
+        assert_eq!(drain(&lanes, [VecDeque::from(["h1"]), VecDeque::new(), VecDeque::new()]), ["h1"]);
+        let queues = [VecDeque::from(["h2"]), VecDeque::new(), VecDeque::from(["l1"])];
+        assert_eq!(drain(&lanes, queues), ["h2", "l1"]);
+    }
+
+    fn pool_order(scheduler: Scheduler) {
+        let pool = ThreadPool::builder().size(1).scheduler(scheduler).build().unwrap();
+        let (started_sender, started) = mpsc::channel();
+        let (release, released) = mpsc::channel::<()>();
+        pool.execute(move || {
+            started_sender.send(()).unwrap();
+            let _ = released.recv();
+        });
+        started.recv().unwrap();
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        for (priority, name) in [
+            (Priority::Low, "low"),
+            (Priority::Normal, "normal"),
+            (Priority::High, "high"),
+            (Priority::Normal, "normal2"),
+        ] {
+            let order = Arc::clone(&order);
+            pool.execute_with_priority(priority, move || order.lock().unwrap().push(name));
+        }
+        drop(release);
+        pool.shutdown();
+        assert_eq!(*order.lock().unwrap(), ["high", "normal", "normal2", "low"]);
+    }
+
+    #[test]
+    fn pool_runs_higher_priority_first() {
+        pool_order(Scheduler::Shared);
+    }
+
+    #[test]
+    fn pool_runs_higher_priority_first_work_stealing() {
+        pool_order(Scheduler::WorkStealing);
+    }
+}
@@ -0,0 +1,239 @@
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{PoisonError, RwLock};
+
+use crossbeam_deque::{Injector, Steal, Stealer, Worker as Deque};
+
+use super::priority::{Lanes, Priority};
+use super::Job;
+
+/// 工作线程取任务的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Scheduler {
+    /// 所有线程从同一个加锁的队列中取任务
+    #[default]
+    Shared,
+    /// 每个线程有自己的任务队列，空闲时从全局队列或其他线程的队列中窃取任务。
+    /// 任务很小、数量很多时锁竞争更少；在工作线程中提交的任务优先由该线程自己执行。
+    /// 只有一个线程提交任务时未必更快，可以用 `cargo bench --bench thread_pool` 对比。
+    WorkStealing,
+}
+
+thread_local! {
+    // 工作窃取模式下当前工作线程的本地队列，以及它所属线程池的标识
+    static LOCAL: RefCell<Option<(usize, Deque<Job>)>> = const { RefCell::new(None) };
+}
+
+/// 工作窃取调度器的队列：每个优先级一个全局队列，加上每个工作线程的本地队列，都是无锁的。
+/// 本地队列只放普通优先级的任务。
+pub(super) struct StealQueues {
+    // 下标是 `Priority::index`
+    injectors: [Injector<Job>; 3],
+    // 下标是工作线程 id
+    stealers: RwLock<Vec<Option<Stealer<Job>>>>,
+    // 各优先级的任务数
+    len: [AtomicUsize; 3],
+}
+
+impl StealQueues {
+    pub(super) fn new() -> StealQueues {
+        StealQueues {
+            injectors: Default::default(),
+            stealers: RwLock::new(Vec::new()),
+            len: Default::default(),
+        }
+    }
+
+    /// 为工作线程 `id` 创建本地队列，其他线程通过登记的 stealer 从中窃取任务
+    pub(super) fn register(&self, id: usize) -> Deque<Job> {
+        let deque = Deque::new_fifo();
+        let mut stealers = self.stealers.write().unwrap_or_else(PoisonError::into_inner);
+        if stealers.len() <= id {
+            stealers.resize_with(id + 1, || None);
+        }
+        stealers[id] = Some(deque.stealer());
+        deque
+    }
+
+    /// 弹性线程池中线程 `id` 空闲退出时注销它的本地队列，这时队列一定是空的
+    pub(super) fn unregister(&self, id: usize) {
+        let mut stealers = self.stealers.write().unwrap_or_else(PoisonError::into_inner);
+        if let Some(slot) = stealers.get_mut(id) {
+            *slot = None;
+        }
+    }
+
+    /// 在工作线程 `id` 启动时调用，把本地队列放进线程局部变量
+    pub(super) fn install(pool: usize, deque: Deque<Job>) {
+        LOCAL.with(|local| *local.borrow_mut() = Some((pool, deque)));
+    }
+
+    /// 工作线程退出时取回本地队列，用于交给重新拉起的线程
+    pub(super) fn uninstall() -> Option<Deque<Job>> {
+        LOCAL.with(|local| local.borrow_mut().take().map(|(_, deque)| deque))
+    }
+
+    /// 在本线程池的工作线程中提交的普通优先级任务放进本地队列，其他的放进对应优先级的全局队列
+    pub(super) fn push(&self, pool: usize, job: Job, priority: Priority) {
+        // 先计数再放进队列：任务一放进去就可能被取走并减掉计数，反过来会让计数下溢
+        self.len[priority.index()].fetch_add(1, Ordering::SeqCst);
+        let job = match priority {
+            Priority::Normal => LOCAL.with(|local| match &*local.borrow() {
+                Some((owner, deque)) if *owner == pool => {
+                    deque.push(job);
+                    None
+                }
+                _ => Some(job),
+            }),
+            _ => Some(job),
+        };
+        if let Some(job) = job {
+            self.injectors[priority.index()].push(job);
+        }
+    }
+
+    /// 工作线程 `id` 取下一个任务，按 `lanes` 决定优先级
+    pub(super) fn pop(&self, id: usize, lanes: &Lanes) -> Option<Job> {
+        let waiting = [0, 1, 2].map(|i| self.len[i].load(Ordering::Relaxed) > 0);
+        lanes.pick(waiting, |priority| match priority {
+            Priority::Normal => self.pop_normal(id),
+            _ => self.pop_injector(priority),
+        })
+    }
+
+    // 普通优先级：先本地队列，再全局队列，最后从其他线程窃取
+    fn pop_normal(&self, id: usize) -> Option<Job> {
+        let injector = &self.injectors[Priority::Normal.index()];
+        let job = LOCAL.with(|local| {
+            let local = local.borrow();
+            let deque = local.as_ref().map(|(_, deque)| deque);
+            if let Some(job) = deque.and_then(Deque::pop) {
+                return Some(job);
+            }
+            loop {
+                let steal = match deque {
+                    Some(deque) => injector.steal_batch_and_pop(deque),
+                    None => injector.steal(),
+                };
+                let steal = steal.or_else(|| self.steal_from_others(id));
+                match steal {
+                    Steal::Success(job) => return Some(job),
+                    Steal::Empty => return None,
+                    Steal::Retry => continue,
+                }
+            }
+        });
+        if job.is_some() {
+            self.len[Priority::Normal.index()].fetch_sub(1, Ordering::SeqCst);
+        }
+        job
+    }
+
+    fn pop_injector(&self, priority: Priority) -> Option<Job> {
+        loop {
+            match self.injectors[priority.index()].steal() {
+                Steal::Success(job) => {
+                    self.len[priority.index()].fetch_sub(1, Ordering::SeqCst);
+                    return Some(job);
+                }
+                Steal::Empty => return None,
+                Steal::Retry => continue,
+            }
+        }
+    }
+
+    fn steal_from_others(&self, id: usize) -> Steal<Job> {
+        let stealers = self.stealers.read().unwrap_or_else(PoisonError::into_inner);
+        // 从自己后面的线程开始，避免所有线程都去偷同一个
+        let n = stealers.len();
+        (1..n)
+            .filter_map(|offset| stealers[(id + offset) % n].as_ref())
+            .map(Stealer::steal)
+            .collect()
+    }
+
+    /// 取出优先级最低的队列中最早提交的任务（尽量），用于 `DropOldest`
+    pub(super) fn pop_oldest(&self) -> Option<Job> {
+        for priority in Priority::ALL.into_iter().rev() {
+            if priority != Priority::Normal {
+                if let Some(job) = self.pop_injector(priority) {
+                    return Some(job);
+                }
+                continue;
+            }
+            loop {
+                let steal = self.injectors[priority.index()].steal().or_else(|| {
+                    let stealers = self.stealers.read().unwrap_or_else(PoisonError::into_inner);
+                    stealers.iter().flatten().map(Stealer::steal).collect()
+                });
+                match steal {
+                    Steal::Success(job) => {
+                        self.len[priority.index()].fetch_sub(1, Ordering::SeqCst);
+                        return Some(job);
+                    }
+                    Steal::Empty => break,
+                    Steal::Retry => continue,
+                }
+            }
+        }
+        None
+    }
+
+    /// 取出所有还没执行的任务
+    pub(super) fn drain(&self) -> Vec<Job> {
+        let mut jobs = Vec::new();
+        while let Some(job) = self.pop_oldest() {
+            jobs.push(job);
+        }
+        jobs
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.len.iter().map(|len| len.load(Ordering::SeqCst)).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    use crate::pool::Scheduler;
+    use crate::ThreadPool;
+
+    const JOBS: usize = 50_000;
+
+    #[test]
+    fn queued_jobs_never_exceeds_submitted() {
+        let pool = Arc::new(ThreadPool::builder().size(8).scheduler(Scheduler::WorkStealing).build().unwrap());
+        for _ in 0..3 {
+            let done = Arc::new(AtomicUsize::new(0));
+            let stop = Arc::new(AtomicBool::new(false));
+            let poller = {
+                let pool = Arc::clone(&pool);
+                let stop = Arc::clone(&stop);
+                thread::spawn(move || {
+                    let mut max = 0;
+                    while !stop.load(Ordering::SeqCst) {
+                        max = max.max(pool.queued_jobs());
+                    }
+                    max
+                })
+            };
+            for _ in 0..JOBS {
+                let done = Arc::clone(&done);
+                pool.execute(move || {
+                    done.fetch_add(1, Ordering::SeqCst);
+                });
+            }
+            while done.load(Ordering::SeqCst) < JOBS {
+                thread::yield_now();
+            }
+            stop.store(true, Ordering::SeqCst);
+            let max = poller.join().unwrap();
+            assert!(max <= JOBS, "queued_jobs reported {}", max);
+            assert_eq!(pool.queued_jobs(), 0);
+        }
+    }
+}
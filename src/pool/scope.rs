@@ -0,0 +1,310 @@
+use std::marker::PhantomData;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+
+use super::handle::{self, JobHandle};
+use super::{ExecuteError, Job, Priority, ThreadPool};
+
+/// `ThreadPool::scope` 中提交任务的作用域，提交的任务可以借用 `'env` 中的数据
+pub struct Scope<'scope, 'env: 'scope> {
+    pool: &'scope ThreadPool,
+    state: Arc<ScopeState>,
+    // 与 std::thread::Scope 相同，让两个生命周期都不变（invariant）
+    scope: PhantomData<&'scope mut &'scope ()>,
+    env: PhantomData<&'env mut &'env ()>,
+}
+
+struct ScopeState {
+    counts: Mutex<Counts>,
+    all_done: Condvar,
+}
+
+struct Counts {
+    // 还没结束（也没被丢弃）的任务数
+    pending: usize,
+    // `execute` 提交的任务中 panic 的个数
+    panicked: usize,
+}
+
+impl ScopeState {
+    fn lock(&self) -> MutexGuard<'_, Counts> {
+        self.counts.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+pub(super) fn run<'env, F, T>(pool: &ThreadPool, f: F) -> T
+where
+    F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> T,
+{
+    let scope = Scope {
+        pool,
+        state: Arc::new(ScopeState {
+            counts: Mutex::new(Counts {
+                pending: 0,
+                panicked: 0,
+            }),
+            all_done: Condvar::new(),
+        }),
+        scope: PhantomData,
+        env: PhantomData,
+    };
+
+    // f panic 时也要等任务结束，之后才能让借用的数据失效
+    let result = panic::catch_unwind(AssertUnwindSafe(|| f(&scope)));
+
+    let mut counts = scope.state.lock();
+    while counts.pending > 0 {
+        counts = scope
+            .state
+            .all_done
+            .wait(counts)
+            .unwrap_or_else(PoisonError::into_inner);
+    }
+    let panicked = counts.panicked;
+    drop(counts);
+
+    match result {
+        Err(payload) => panic::resume_unwind(payload),
+        Ok(_) if panicked > 0 => panic!("{} scoped job(s) panicked", panicked),
+        Ok(value) => value,
+    }
+}
+
+impl<'scope, 'env> Scope<'scope, 'env> {
+    /// 提交可以借用外部数据的任务，失败时的行为同 `ThreadPool::execute`
+    pub fn execute<F>(&'scope self, f: F)
+    where
+        F: FnOnce() + Send + 'scope,
+    {
+        if let Err(err) = self.try_execute(f) {
+            panic!("{}", err);
+        }
+    }
+
+    /// 同 `execute`，失败时的行为同 `ThreadPool::try_execute`
+    pub fn try_execute<F>(&'scope self, f: F) -> Result<(), ExecuteError>
+    where
+        F: FnOnce() + Send + 'scope,
+    {
+        let state = Arc::clone(&self.state);
+        self.submit(move || {
+            if panic::catch_unwind(AssertUnwindSafe(f)).is_err() {
+                state.lock().panicked += 1;
+            }
+        })
+    }
+
+    /// 提交有返回值的任务，任务 panic 时通过句柄返回 `JobError::Panicked`，不会让 `scope` panic
+    pub fn spawn<F, T>(&'scope self, f: F) -> JobHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'scope,
+        T: Send + 'scope,
+    {
+        match self.try_spawn(f) {
+            Ok(handle) => handle,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// 同 `spawn`，失败时的行为同 `ThreadPool::try_spawn`
+    pub fn try_spawn<F, T>(&'scope self, f: F) -> Result<JobHandle<T>, ExecuteError>
+    where
+        F: FnOnce() -> T + Send + 'scope,
+        T: Send + 'scope,
+    {
+        let (job, handle) = handle::wrap(f);
+        self.submit(job)?;
+        Ok(handle)
+    }
+
+    fn submit<F>(&'scope self, f: F) -> Result<(), ExecuteError>
+    where
+        F: FnOnce() + Send + 'scope,
+    {
+        self.state.lock().pending += 1;
+        let job = ScopedJob {
+            f,
+            _done: Done(Arc::clone(&self.state)),
+        };
+        let job: Box<dyn FnOnce() + Send + 'scope> = Box::new(move || job.run());
+        // SAFETY: `run` 返回前会等到所有任务执行完或被丢弃（`Done` 析构），
+        // 任务借用的 'scope 数据在这之前一直有效
+        let job: Job = unsafe { mem::transmute::<Box<dyn FnOnce() + Send + 'scope>, Job>(job) };
+        // 提交失败时任务在这里被丢弃，同样会减少计数
+        self.pool.submit(job, Priority::Normal)
+    }
+}
+
+// 字段按声明顺序析构：先析构任务（和它借用的东西），最后才通知 scope 任务已结束
+struct ScopedJob<F> {
+    f: F,
+    _done: Done,
+}
+
+impl<F: FnOnce()> ScopedJob<F> {
+    fn run(self) {
+        (self.f)();
+    }
+}
+
+struct Done(Arc<ScopeState>);
+
+impl Drop for Done {
+    fn drop(&mut self) {
+        let mut counts = self.0.lock();
+        counts.pending -= 1;
+        if counts.pending == 0 {
+            self.0.all_done.notify_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::pool::{ExecuteError, JobError, OverflowPolicy, ShutdownPolicy};
+    use crate::ThreadPool;
+
+    // 占住唯一的工作线程，直到 `release` 为 true
+    fn block_until(started: &AtomicBool, release: &AtomicBool) {
+        started.store(true, Ordering::SeqCst);
+        while !release.load(Ordering::SeqCst) {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn wait_for(started: &AtomicBool) {
+        while !started.load(Ordering::SeqCst) {
+            thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn jobs_borrowing_stack_data_finish_before_return() {
+        let pool = ThreadPool::new(4);
+        let mut slots = vec![0usize; 64];
+        let sum = AtomicUsize::new(0);
+        pool.scope(|s| {
+            for (i, slot) in slots.iter_mut().enumerate() {
+                let sum = &sum;
+                s.execute(move || {
+                    thread::sleep(Duration::from_millis(1));
+                    *slot = i + 1;
+                    sum.fetch_add(i + 1, Ordering::SeqCst);
+                });
+            }
+        });
+        assert_eq!(slots, (1..=64).collect::<Vec<_>>());
+        assert_eq!(sum.load(Ordering::SeqCst), 64 * 65 / 2);
+    }
+
+    #[test]
+    fn spawn_returns_borrowed_results() {
+        let pool = ThreadPool::new(2);
+        let words = ["a", "bb", "ccc"];
+        let total: usize = pool.scope(|s| {
+            let handles: Vec<_> = words.iter().map(|word| s.spawn(move || word.len())).collect();
+            let failed = s.spawn(|| -> usize { panic!("spawned job") });
+            assert!(matches!(failed.join(), Err(JobError::Panicked(_))));
+            handles.into_iter().map(|handle| handle.join().unwrap()).sum()
+        });
+        assert_eq!(total, 6);
+    }
+
+    #[test]
+    fn waits_for_jobs_when_f_panics() {
+        let pool = ThreadPool::new(1);
+        let finished = AtomicBool::new(false);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            pool.scope(|s| {
+                s.execute(|| {
+                    thread::sleep(Duration::from_millis(50));
+                    finished.store(true, Ordering::SeqCst);
+                });
+                panic!("scope body");
+            })
+        }));
+        let payload = result.unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"scope body"));
+        assert!(finished.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn panics_after_waiting_when_executed_job_panics() {
+        let pool = ThreadPool::new(2);
+        let finished = AtomicBool::new(false);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            pool.scope(|s| {
+                s.execute(|| panic!("scoped job"));
+                s.execute(|| {
+                    thread::sleep(Duration::from_millis(50));
+                    finished.store(true, Ordering::SeqCst);
+                });
+            })
+        }));
+        let payload = result.unwrap_err();
+        assert_eq!(payload.downcast_ref::<String>().map(String::as_str), Some("1 scoped job(s) panicked"));
+        assert!(finished.load(Ordering::SeqCst));
+        // 线程池本身不受影响
+        assert_eq!(pool.spawn(|| 1).join().unwrap(), 1);
+    }
+
+    #[test]
+    fn job_dropped_by_drop_oldest_is_not_waited_for() {
+        let pool = ThreadPool::builder()
+            .size(1)
+            .queue_capacity(1)
+            .overflow_policy(OverflowPolicy::DropOldest)
+            .build()
+            .unwrap();
+        let (started, release) = (AtomicBool::new(false), AtomicBool::new(false));
+        let (dropped_ran, newest_ran) = (AtomicBool::new(false), AtomicBool::new(false));
+        pool.scope(|s| {
+            s.execute(|| block_until(&started, &release));
+            wait_for(&started);
+            s.execute(|| dropped_ran.store(true, Ordering::SeqCst));
+            // 队列已满，上一个任务被丢弃
+            s.execute(|| newest_ran.store(true, Ordering::SeqCst));
+            release.store(true, Ordering::SeqCst);
+        });
+        assert!(!dropped_ran.load(Ordering::SeqCst));
+        assert!(newest_ran.load(Ordering::SeqCst));
+        assert_eq!(pool.dropped_jobs(), 1);
+    }
+
+    #[test]
+    fn job_abandoned_at_shutdown_is_not_waited_for() {
+        let pool = ThreadPool::builder()
+            .size(1)
+            .shutdown_policy(ShutdownPolicy::Abandon)
+            .build()
+            .unwrap();
+        let (started, release) = (AtomicBool::new(false), AtomicBool::new(false));
+        let abandoned_ran = AtomicBool::new(false);
+        let summary = thread::scope(|threads| {
+            pool.scope(|s| {
+                s.execute(|| block_until(&started, &release));
+                wait_for(&started);
+                s.execute(|| abandoned_ran.store(true, Ordering::SeqCst));
+                let shutdown = threads.spawn(|| pool.shutdown());
+                // 停机时丢弃队列中的任务，之后再放开工作线程
+                while pool.queued_jobs() > 0 {
+                    thread::sleep(Duration::from_millis(1));
+                }
+                release.store(true, Ordering::SeqCst);
+                // 停机后提交失败，任务同样被丢弃
+                assert_eq!(s.try_execute(|| abandoned_ran.store(true, Ordering::SeqCst)), Err(ExecuteError::Closed));
+                shutdown
+            })
+            .join()
+            .unwrap()
+        });
+        assert!(!abandoned_ran.load(Ordering::SeqCst));
+        assert_eq!(summary.abandoned_jobs, 1);
+    }
+}
@@ -0,0 +1,188 @@
+/// 停机时如何处理队列中还没开始执行的任务
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ShutdownPolicy {
+    /// 执行完队列中所有任务再退出
+    #[default]
+    Drain,
+    /// 丢弃队列中的任务，线程执行完手上的任务就退出
+    Abandon,
+}
+
+/// 停机结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShutdownSummary {
+    /// 本次停机使用的策略
+    pub policy: ShutdownPolicy,
+    /// 线程池生命周期内正常执行完的任务数
+    pub completed_jobs: usize,
+    /// 线程池生命周期内 panic 的任务数
+    pub panicked_jobs: usize,
+    /// 线程池生命周期内队列满时按 `DropOldest` 策略丢弃的任务数
+    pub dropped_jobs: usize,
+    /// 没有执行就被丢弃的任务数（`Abandon` 策略，或等待超时后仍在队列中的任务）
+    pub abandoned_jobs: usize,
+    /// 正常退出并被 join 的线程数
+    pub joined_workers: usize,
+    /// join 时发现因 panic 而退出的线程数
+    pub panicked_workers: usize,
+    /// 线程池生命周期内意外退出后被重新拉起的线程数
+    pub respawned_workers: usize,
+    /// 线程池生命周期内因空闲超时而退出的线程数（弹性线程池）
+    pub retired_workers: usize,
+    /// 等待超时后仍在执行任务、被分离（detach）的线程数
+    pub detached_workers: usize,
+    /// 停机时还没到期、被丢弃的定时任务数
+    pub cancelled_timers: usize,
+    /// 是否等待超时
+    pub timed_out: bool,
+}
+
+impl ShutdownSummary {
+    pub(crate) fn new(policy: ShutdownPolicy) -> ShutdownSummary {
+        ShutdownSummary {
+            policy,
+            completed_jobs: 0,
+            panicked_jobs: 0,
+            dropped_jobs: 0,
+            abandoned_jobs: 0,
+            joined_workers: 0,
+            panicked_workers: 0,
+            respawned_workers: 0,
+            retired_workers: 0,
+            detached_workers: 0,
+            cancelled_timers: 0,
+            timed_out: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc::{self, Receiver, Sender};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::pool::ExecuteError;
+    use crate::ThreadPool;
+
+    // 占住一个工作线程，直到返回的 Sender 被 drop；返回时任务已经开始执行
+    fn block_worker(pool: &ThreadPool) -> Sender<()> {
+        let (started_sender, started) = mpsc::channel();
+        let (release, released): (Sender<()>, Receiver<()>) = mpsc::channel();
+        pool.execute(move || {
+            started_sender.send(()).unwrap();
+            let _ = released.recv();
+        });
+        started.recv().unwrap();
+        release
+    }
+
+    fn count_jobs(pool: &ThreadPool, jobs: usize) -> Arc<AtomicUsize> {
+        let counter = Arc::new(AtomicUsize::new(0));
+        for _ in 0..jobs {
+            let counter = Arc::clone(&counter);
+            pool.execute(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        counter
+    }
+
+    #[test]
+    fn drain_runs_queued_jobs() {
+        let pool = ThreadPool::new(2);
+        let release = block_worker(&pool);
+        let counter = count_jobs(&pool, 20);
+        drop(release);
+
+        let summary = pool.shutdown();
+        assert_eq!(counter.load(Ordering::SeqCst), 20);
+        assert_eq!(summary.policy, ShutdownPolicy::Drain);
+        assert_eq!(summary.completed_jobs, 21);
+        assert_eq!(summary.abandoned_jobs, 0);
+        assert_eq!(summary.joined_workers, 2);
+        assert!(!summary.timed_out);
+
+        // 停机后不能再提交，重复停机什么也不做
+        assert_eq!(pool.try_execute(|| {}), Err(ExecuteError::Closed));
+        assert_eq!(pool.shutdown(), ShutdownSummary::new(ShutdownPolicy::Drain));
+    }
+
+    #[test]
+    fn drop_drains() {
+        let pool = ThreadPool::new(1);
+        let counter = count_jobs(&pool, 10);
+        drop(pool);
+        assert_eq!(counter.load(Ordering::SeqCst), 10);
+    }
+
+    #[test]
+    fn abandon_drops_queued_jobs() {
+        let pool = Arc::new(ThreadPool::builder().size(1).shutdown_policy(ShutdownPolicy::Abandon).build().unwrap());
+        let release = block_worker(&pool);
+        let counter = count_jobs(&pool, 5);
+
+        let shutdown = {
+            let pool = Arc::clone(&pool);
+            thread::spawn(move || pool.shutdown())
+        };
+        // 停机先丢弃队列中的任务，再等正在执行的任务
+        while pool.queued_jobs() > 0 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        drop(release);
+        let summary = shutdown.join().unwrap();
+        assert_eq!(counter.load(Ordering::SeqCst), 0);
+        assert_eq!(summary.policy, ShutdownPolicy::Abandon);
+        assert_eq!(summary.abandoned_jobs, 5);
+        assert_eq!(summary.completed_jobs, 1);
+        assert_eq!(summary.joined_workers, 1);
+    }
+
+    #[test]
+    fn set_shutdown_policy() {
+        let mut pool = ThreadPool::new(1);
+        pool.set_shutdown_policy(ShutdownPolicy::Abandon);
+        let release = block_worker(&pool);
+        count_jobs(&pool, 3);
+        drop(release);
+        // 被占住的线程也可能在停机之前取走一个任务
+        let summary = pool.shutdown();
+        assert_eq!(summary.policy, ShutdownPolicy::Abandon);
+        assert!(summary.abandoned_jobs <= 3);
+        assert_eq!(summary.completed_jobs + summary.abandoned_jobs, 4);
+    }
+
+    #[test]
+    fn shutdown_timeout_detaches_busy_workers() {
+        let pool = ThreadPool::new(1);
+        let release = block_worker(&pool);
+        let counter = count_jobs(&pool, 3);
+
+        let started = Instant::now();
+        let summary = pool.shutdown_timeout(Duration::from_millis(50));
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        assert!(summary.timed_out);
+        assert_eq!(summary.abandoned_jobs, 3);
+        assert_eq!(summary.detached_workers, 1);
+        assert_eq!(summary.joined_workers, 0);
+
+        // 被分离的线程执行完手上的任务后退出，不会再执行被放弃的任务
+        drop(release);
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(counter.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn shutdown_timeout_without_busy_workers() {
+        let pool = ThreadPool::new(2);
+        let counter = count_jobs(&pool, 10);
+        let summary = pool.shutdown_timeout(Duration::from_secs(5));
+        assert!(!summary.timed_out);
+        assert_eq!(counter.load(Ordering::SeqCst), 10);
+        assert_eq!(summary.joined_workers, 2);
+    }
+}
@@ -0,0 +1,387 @@
+use std::cmp::{Ordering as CmpOrdering, Reverse};
+use std::collections::BinaryHeap;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{ExecuteError, Job, OverflowPolicy, Priority, Shared};
+
+/// `schedule_after`、`schedule_every` 返回的句柄，用来取消定时任务。
+/// 句柄被丢弃时任务不会被取消。
+#[derive(Clone)]
+pub struct TimerHandle {
+    task: Arc<Task>,
+}
+
+impl TimerHandle {
+    /// 取消定时任务：之后不再触发，已经到期、还在队列中等待执行的这一次也不再执行。
+    /// 正在执行的不受影响。
+    pub fn cancel(&self) {
+        self.task.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.task.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+struct Task {
+    cancelled: AtomicBool,
+    kind: Kind,
+}
+
+enum Kind {
+    // 只执行一次，触发时取出
+    Once(Mutex<Option<Job>>),
+    Every(Duration, Box<dyn Fn() + Send + Sync>),
+}
+
+/// 定时器：一个线程按到期时间把任务交给工作线程执行
+pub(super) struct Timer {
+    inner: Arc<Inner>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+struct Inner {
+    queue: Mutex<Queue>,
+    // 有更早到期的任务或定时器停止时通知定时器线程
+    changed: Condvar,
+}
+
+struct Queue {
+    entries: BinaryHeap<Reverse<Entry>>,
+    // 到期时间相同时按加入的先后顺序触发
+    next_seq: u64,
+    stopped: bool,
+}
+
+struct Entry {
+    deadline: Instant,
+    seq: u64,
+    task: Arc<Task>,
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Entry) -> bool {
+        (self.deadline, self.seq) == (other.deadline, other.seq)
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Entry) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Entry) -> CmpOrdering {
+        (self.deadline, self.seq).cmp(&(other.deadline, other.seq))
+    }
+}
+
+impl Inner {
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        self.queue.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn insert(&self, queue: &mut Queue, deadline: Instant, task: Arc<Task>) {
+        let seq = queue.next_seq;
+        queue.next_seq += 1;
+        // 新任务比原来最早的还早时要叫醒定时器线程重新计算等待时间
+        let earliest = match queue.entries.peek() {
+            Some(Reverse(e)) => deadline < e.deadline,
+            None => true,
+        };
+        queue.entries.push(Reverse(Entry { deadline, seq, task }));
+        if earliest {
+            self.changed.notify_one();
+        }
+    }
+}
+
+impl Timer {
+    pub(super) fn new(shared: Arc<Shared>) -> io::Result<Timer> {
+        let inner = Arc::new(Inner {
+            queue: Mutex::new(Queue {
+                entries: BinaryHeap::new(),
+                next_seq: 0,
+                stopped: false,
+            }),
+            changed: Condvar::new(),
+        });
+
+        let name = match &shared.name_prefix {
+            Some(prefix) => format!("{}-timer", prefix),
+            None => "thread-pool-timer".to_string(),
+        };
+        let thread = {
+            let inner = Arc::clone(&inner);
+            thread::Builder::new()
+                .name(name)
+                .spawn(move || run(&inner, &shared))?
+        };
+
+        Ok(Timer {
+            inner,
+            thread: Some(thread),
+        })
+    }
+
+    pub(super) fn schedule_after(&self, delay: Duration, job: Job) -> TimerHandle {
+        self.schedule(delay, Kind::Once(Mutex::new(Some(job))))
+    }
+
+    pub(super) fn schedule_every(&self, period: Duration, f: Box<dyn Fn() + Send + Sync>) -> TimerHandle {
+        self.schedule(period, Kind::Every(period, f))
+    }
+
+    fn schedule(&self, delay: Duration, kind: Kind) -> TimerHandle {
+        let task = Arc::new(Task {
+            cancelled: AtomicBool::new(false),
+            kind,
+        });
+        let mut queue = self.inner.lock();
+        self.inner.insert(&mut queue, Instant::now() + delay, Arc::clone(&task));
+        TimerHandle { task }
+    }
+
+    /// 停止定时器线程，还没到期的任务全部丢弃，返回丢弃的个数（不含已取消的）
+    pub(super) fn stop(&mut self) -> usize {
+        let entries = {
+            let mut queue = self.inner.lock();
+            queue.stopped = true;
+            self.inner.changed.notify_one();
+            std::mem::take(&mut queue.entries)
+        };
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        entries
+            .into_iter()
+            .filter(|Reverse(entry)| !entry.task.cancelled.load(Ordering::SeqCst))
+            .count()
+    }
+}
+
+fn run(inner: &Inner, shared: &Arc<Shared>) {
+    let mut queue = inner.lock();
+    loop {
+        if queue.stopped {
+            return;
+        }
+        let now = Instant::now();
+        let deadline = match queue.entries.peek() {
+            Some(Reverse(entry)) => entry.deadline,
+            None => {
+                queue = inner.changed.wait(queue).unwrap_or_else(PoisonError::into_inner);
+                continue;
+            }
+        };
+        if deadline > now {
+            queue = inner
+                .changed
+                .wait_timeout(queue, deadline - now)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+            continue;
+        }
+
+        let Some(Reverse(entry)) = queue.entries.pop() else {
+            continue;
+        };
+        if entry.task.cancelled.load(Ordering::SeqCst) {
+            continue;
+        }
+        // 周期任务按固定频率触发，执行得慢错过的那几次直接跳过
+        if let Kind::Every(period, _) = &entry.task.kind {
+            let mut next = entry.deadline + *period;
+            while next <= now {
+                next += *period;
+            }
+            inner.insert(&mut queue, next, Arc::clone(&entry.task));
+        }
+
+        // 提交任务时不持有定时器的锁，提交可能因为队列满而阻塞
+        drop(queue);
+        fire(shared, entry.task);
+        queue = inner.lock();
+    }
+}
+
+fn fire(shared: &Arc<Shared>, task: Arc<Task>) {
+    let job: Job = match &task.kind {
+        Kind::Once(job) => match job.lock().unwrap_or_else(PoisonError::into_inner).take() {
+            Some(job) => {
+                let task = Arc::clone(&task);
+                Box::new(move || {
+                    if !task.cancelled.load(Ordering::SeqCst) {
+                        job();
+                    }
+                })
+            }
+            None => return,
+        },
+        Kind::Every(..) => {
+            let task = Arc::clone(&task);
+            Box::new(move || {
+                if let Kind::Every(_, f) = &task.kind {
+                    if !task.cancelled.load(Ordering::SeqCst) {
+                        f();
+                    }
+                }
+            })
+        }
+    };
+    // 任务在定时器线程中执行的话，慢任务会推迟其他定时任务，panic 会杀死定时器线程，
+    // 所以 `CallerRuns` 时和 `Reject` 一样跳过这一次
+    let overflow_policy = match shared.overflow_policy {
+        OverflowPolicy::CallerRuns => OverflowPolicy::Reject,
+        policy => policy,
+    };
+    match shared.submit_with(job, Priority::Normal, overflow_policy) {
+        Ok(()) | Err(ExecuteError::Closed) => {}
+        // 队列满时这一次就不执行了，周期任务下一次到期时再提交
+        Err(ExecuteError::QueueFull) => eprintln!("thread pool queue is full, skipping a timer job"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::mpsc::{self, Receiver, Sender};
+
+    use super::*;
+    use crate::ThreadPool;
+
+    // 占住唯一的工作线程，直到返回的 Sender 被 drop
+    fn block_worker(pool: &ThreadPool) -> Sender<()> {
+        let (started_sender, started) = mpsc::channel();
+        let (release, released): (Sender<()>, Receiver<()>) = mpsc::channel();
+        pool.execute(move || {
+            started_sender.send(()).unwrap();
+            let _ = released.recv();
+        });
+        started.recv().unwrap();
+        release
+    }
+
+    #[test]
+    fn schedule_after() {
+        let pool = ThreadPool::new(2);
+        let (sender, receiver) = mpsc::channel();
+        let start = Instant::now();
+        for (delay, name) in [(60, "late"), (20, "early")] {
+            let sender = sender.clone();
+            pool.schedule_after(Duration::from_millis(delay), move || sender.send((name, start.elapsed())).unwrap());
+        }
+        let (name, elapsed) = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(name, "early");
+        assert!(elapsed >= Duration::from_millis(20), "{:?}", elapsed);
+        let (name, elapsed) = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(name, "late");
+        assert!(elapsed >= Duration::from_millis(60), "{:?}", elapsed);
+    }
+
+    #[test]
+    fn schedule_every_at_fixed_rate() {
+        let pool = ThreadPool::new(1);
+        let (sender, receiver) = mpsc::channel();
+        let start = Instant::now();
+        let sender = Mutex::new(sender);
+        let handle = pool.schedule_every(Duration::from_millis(20), move || {
+            let _ = sender.lock().unwrap().send(start.elapsed());
+        });
+        let ticks: Vec<Duration> = (0..5)
+            .map(|_| receiver.recv_timeout(Duration::from_secs(5)).unwrap())
+            .collect();
+        // 第 n 次不早于 n 个周期
+        for (n, elapsed) in ticks.iter().enumerate() {
+            assert!(*elapsed >= Duration::from_millis(20 * (n as u64 + 1)), "{:?}", ticks);
+        }
+
+        handle.cancel();
+        assert!(handle.is_cancelled());
+        // 取消时可能已经有一次提交了
+        thread::sleep(Duration::from_millis(50));
+        while receiver.try_recv().is_ok() {}
+        assert!(receiver.recv_timeout(Duration::from_millis(60)).is_err());
+    }
+
+    #[test]
+    fn cancel_before_deadline() {
+        let pool = ThreadPool::new(1);
+        let counter = Arc::new(AtomicUsize::new(0));
+        let handle = {
+            let counter = Arc::clone(&counter);
+            pool.schedule_after(Duration::from_millis(20), move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+            })
+        };
+        // 克隆的句柄也能取消
+        handle.clone().cancel();
+        thread::sleep(Duration::from_millis(60));
+        assert_eq!(counter.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn shutdown_cancels_pending_timers() {
+        let pool = ThreadPool::new(1);
+        pool.schedule_after(Duration::from_secs(60), || {});
+        pool.schedule_every(Duration::from_secs(60), || {});
+        pool.schedule_after(Duration::from_secs(60), || {}).cancel();
+        let summary = pool.shutdown();
+        // 已经取消的不计入
+        assert_eq!(summary.cancelled_timers, 2);
+    }
+
+    fn skipped_on_full_queue(policy: OverflowPolicy) {
+        let pool = ThreadPool::builder()
+            .size(1)
+            .queue_capacity(1)
+            .overflow_policy(policy)
+            .build()
+            .unwrap();
+        let release = block_worker(&pool);
+        pool.execute(|| {});
+
+        // 到期时队列满，这一次不执行，也不在定时器线程中执行
+        let (sender, receiver) = mpsc::channel();
+        {
+            let sender = sender.clone();
+            pool.schedule_after(Duration::from_millis(10), move || {
+                sender.send(thread::current().name().map(str::to_string)).unwrap()
+            });
+        }
+        thread::sleep(Duration::from_millis(60));
+        drop(release);
+        assert!(receiver.recv_timeout(Duration::from_millis(60)).is_err());
+
+        // 定时器线程还在工作
+        pool.schedule_after(Duration::from_millis(10), move || sender.send(None).unwrap());
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(5)).unwrap(), None);
+    }
+
+    #[test]
+    fn queue_full_skips_the_job() {
+        skipped_on_full_queue(OverflowPolicy::Reject);
+    }
+
+    #[test]
+    fn queue_full_skips_the_job_with_caller_runs() {
+        skipped_on_full_queue(OverflowPolicy::CallerRuns);
+    }
+
+    #[test]
+    fn panicking_timer_job_does_not_stop_the_timer() {
+        let pool = ThreadPool::new(1);
+        pool.schedule_after(Duration::from_millis(5), || panic!("timer job"));
+        let (sender, receiver) = mpsc::channel();
+        pool.schedule_after(Duration::from_millis(30), move || sender.send(()).unwrap());
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(pool.panicked_jobs(), 1);
+    }
+}
@@ -0,0 +1,286 @@
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{self, Ordering};
+use std::sync::{Arc, PoisonError};
+use std::thread;
+use std::time::Instant;
+
+use crossbeam_deque::Worker as Deque;
+
+use super::panic::{panic_message, JobPanic};
+use super::scheduler::StealQueues;
+use super::{Job, Shared, State};
+
+pub(super) struct Worker {
+    pub(super) id: usize,
+    pub(super) thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Worker {
+    /// 创建工作线程。工作窃取模式下 `deque` 是接手的本地队列（重新拉起线程时），为 None 时新建一个
+    pub(super) fn new(id: usize, shared: Arc<Shared>, deque: Option<Deque<Job>>) -> io::Result<Worker> {
+        let mut builder = thread::Builder::new();
+        if let Some(prefix) = &shared.name_prefix {
+            builder = builder.name(format!("{}-{}", prefix, id));
+        }
+        if let Some(size) = shared.stack_size {
+            builder = builder.stack_size(size);
+        }
+        let thread = builder.spawn(move || run(id, shared, deque))?;
+
+        Ok(Worker {
+            id,
+            thread: Some(thread),
+        })
+    }
+}
+
+fn run(id: usize, shared: Arc<Shared>, deque: Option<Deque<Job>>) {
+    if let Some(steal) = &shared.steal {
+        let deque = deque.unwrap_or_else(|| steal.register(id));
+        StealQueues::install(shared.id(), deque);
+    }
+
+    // 无论正常退出还是意外 panic 退出，都要登记；意外退出时由它负责重新拉起同 id 的线程
+    let mut exit = ExitGuard {
+        id,
+        shared: Arc::clone(&shared),
+        retired: false,
+    };
+
+    loop {
+        let job = match next_job(&shared, id) {
+            Ok(job) => job,
+            Err(Exit::Retire) => {
+                exit.retired = true;
+                break;
+            }
+            Err(Exit::Shutdown) => break,
+        };
+        // 任务 panic 不会杀死工作线程
+        match panic::catch_unwind(AssertUnwindSafe(job)) {
+            Ok(()) => {
+                shared.completed.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(payload) => {
+                let handler = {
+                    let mut state = shared.lock();
+                    state.panicked += 1;
+                    state.panic_handler.clone()
+                };
+                // 回调在锁外调用，回调里再 panic 也只会触发线程重启
+                if let Some(handler) = handler {
+                    handler(&JobPanic {
+                        worker_id: id,
+                        message: panic_message(payload.as_ref()),
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// 工作线程为什么不再取任务
+enum Exit {
+    /// 线程池关闭且没有任务了
+    Shutdown,
+    /// 弹性线程池中空闲超时，线程数多于下限
+    Retire,
+}
+
+/// 取下一个任务
+fn next_job(shared: &Shared, id: usize) -> Result<Job, Exit> {
+    // 工作窃取模式先不加锁地找任务，找不到再加锁检查并睡眠
+    if let Some(job) = shared.steal.as_ref().and_then(|steal| steal.pop(id, &shared.lanes)) {
+        if shared.queue_capacity.is_some() {
+            let _state = shared.lock();
+            shared.space_available.notify_one();
+        }
+        return Ok(job);
+    }
+
+    let mut state = shared.lock();
+    // 先登记为空闲再检查队列，与不加锁的入队配合，见 `Shared::push_unlocked`
+    shared.idle.fetch_add(1, Ordering::SeqCst);
+    atomic::fence(Ordering::SeqCst);
+    let deadline = Instant::now() + shared.keep_alive;
+    let job = loop {
+        if let Some(job) = shared.pop(&mut state, id) {
+            break Ok(job);
+        }
+        // 还有线程在不加锁地提交任务时不能退出，它提交完会叫醒这里
+        if shared.closed.load(Ordering::SeqCst) && shared.submitting.load(Ordering::SeqCst) == 0 {
+            break Err(Exit::Shutdown);
+        }
+        if !shared.elastic() || state.running <= shared.min_size {
+            state = shared
+                .job_available
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+            continue;
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            retire(shared, &mut state, id);
+            break Err(Exit::Retire);
+        }
+        state = shared
+            .job_available
+            .wait_timeout(state, deadline - now)
+            .unwrap_or_else(PoisonError::into_inner)
+            .0;
+    };
+    shared.idle.fetch_sub(1, Ordering::SeqCst);
+
+    if job.is_ok() && shared.queue_capacity.is_some() {
+        shared.space_available.notify_one();
+    }
+    job
+}
+
+// 空闲线程退出时的登记在锁内一次做完，id 马上就可以被新加的线程复用
+fn retire(shared: &Shared, state: &mut State, id: usize) {
+    // JoinHandle 直接丢弃，线程马上就会结束
+    state.workers.retain(|w| w.id != id);
+    state.running -= 1;
+    state.retired += 1;
+    if let Some(steal) = &shared.steal {
+        steal.unregister(id);
+    }
+}
+
+struct ExitGuard {
+    id: usize,
+    shared: Arc<Shared>,
+    // 空闲超时退出，已经在 `retire` 中登记过
+    retired: bool,
+}
+
+impl Drop for ExitGuard {
+    fn drop(&mut self) {
+        if self.retired {
+            StealQueues::uninstall();
+            return;
+        }
+        let mut state = self.shared.lock();
+
+        // 线程在 catch_unwind 之外 panic（比如 panic 回调本身 panic），
+        // 线程池没有关闭时用同样的 id 重新拉起一个线程，线程池不会因此变小
+        let deque = StealQueues::uninstall();
+        if thread::panicking() && !self.shared.closed.load(Ordering::SeqCst) {
+            // 本地队列里的任务交给新线程
+            match Worker::new(self.id, Arc::clone(&self.shared), deque) {
+                Ok(worker) => {
+                    if let Some(slot) = state.workers.iter_mut().find(|w| w.id == self.id) {
+                        // 旧线程的 JoinHandle 直接丢弃，它马上就会结束
+                        *slot = worker;
+                    }
+                    state.respawned += 1;
+                    return;
+                }
+                // 拉起失败只能让线程池少一个线程
+                Err(err) => eprintln!("failed to respawn worker {}: {}", self.id, err),
+            }
+        }
+
+        state.running -= 1;
+        state.exited.push(self.id);
+        self.shared.worker_exited.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::pool::{JobPanic, Scheduler};
+    use crate::ThreadPool;
+
+    #[test]
+    fn panicking_job_does_not_kill_worker() {
+        let panics = Arc::new(Mutex::new(Vec::new()));
+        let pool = {
+            let panics = Arc::clone(&panics);
+            ThreadPool::builder()
+                .size(1)
+                .name_prefix("isolate")
+                .panic_handler(move |panic: &JobPanic| panics.lock().unwrap().push(panic.clone()))
+                .build()
+                .unwrap()
+        };
+        let names = Arc::new(Mutex::new(Vec::new()));
+        for i in 0..3 {
+            pool.execute(move || panic!("job {}", i));
+            let names = Arc::clone(&names);
+            pool.execute(move || names.lock().unwrap().push(thread::current().name().map(str::to_string)));
+        }
+        let summary = pool.shutdown();
+
+        // 同一个线程执行了所有任务，没有重新拉起
+        assert_eq!(*names.lock().unwrap(), vec![Some("isolate-0".to_string()); 3]);
+        assert_eq!(summary.panicked_jobs, 3);
+        assert_eq!(summary.completed_jobs, 3);
+        assert_eq!(summary.respawned_workers, 0);
+        assert_eq!(summary.joined_workers, 1);
+        let panics = panics.lock().unwrap();
+        let messages: Vec<_> = panics.iter().map(|panic| panic.message.as_str()).collect();
+        assert_eq!(messages, ["job 0", "job 1", "job 2"]);
+        assert!(panics.iter().all(|panic| panic.worker_id == 0));
+    }
+
+    #[test]
+    fn panicked_jobs_counts_without_handler() {
+        let pool = ThreadPool::new(2);
+        for _ in 0..4 {
+            pool.execute(|| panic!("boom"));
+        }
+        let summary = pool.shutdown();
+        assert_eq!(summary.panicked_jobs, 4);
+        assert_eq!(pool.panicked_jobs(), 4);
+    }
+
+    fn respawn(scheduler: Scheduler) {
+        // 只有一个线程，任务按顺序执行：前面的线程都重新拉起之后才会执行到最后的任务
+        let pool = ThreadPool::builder()
+            .size(1)
+            .scheduler(scheduler)
+            // 回调本身 panic 会杀死工作线程
+            .panic_handler(|_: &JobPanic| panic!("handler panicked"))
+            .build()
+            .unwrap();
+        for _ in 0..3 {
+            pool.execute(|| panic!("job"));
+        }
+        // 被重新拉起的线程继续处理任务，线程池大小不变
+        let counter = Arc::new(AtomicUsize::new(0));
+        for _ in 0..50 {
+            let counter = Arc::clone(&counter);
+            pool.execute(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        let (sender, receiver) = mpsc::channel();
+        pool.execute(move || sender.send(()).unwrap());
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(pool.metrics().size, 1);
+
+        let summary = pool.shutdown();
+        assert_eq!(counter.load(Ordering::SeqCst), 50);
+        assert_eq!(summary.panicked_jobs, 3);
+        assert_eq!(summary.respawned_workers, 3);
+        assert_eq!(summary.joined_workers, 1);
+    }
+
+    #[test]
+    fn dead_worker_is_respawned() {
+        respawn(Scheduler::Shared);
+    }
+
+    #[test]
+    fn dead_worker_is_respawned_work_stealing() {
+        respawn(Scheduler::WorkStealing);
+    }
+}
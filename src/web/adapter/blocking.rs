@@ -0,0 +1,126 @@
+use std::fs::File;
+use std::io::{self, copy, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use crate::http::{self, KeepAlive};
+use crate::web::admin::{AdminConfig, AdminService};
+use crate::web::compression::Compressor;
+use crate::web::service::{Connection, FileBody, Outgoing, Service, Signal};
+use crate::web::static_files::BodyPart;
+
+/// 监听管理端口 `config.addr`，在后台线程中接受连接，每个连接一个线程交给 `service` 处理。
+/// `POST /quit` 的回复发完后调用 `on_quit`
+pub fn spawn_admin<F>(config: &AdminConfig, service: AdminService, on_quit: F) -> io::Result<JoinHandle<()>>
+where
+    F: Fn() + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind(config.addr.as_str())?;
+    let service = Arc::new(service);
+    let on_quit = Arc::new(on_quit);
+    Ok(thread::spawn(move || {
+        while let Ok((stream, _)) = listener.accept() {
+            let service = Arc::clone(&service);
+            let on_quit = Arc::clone(&on_quit);
+            thread::spawn(move || {
+                if let Ok(Some(Signal::Quit)) = serve_connection(stream, service.as_ref(), KeepAlive::default()) {
+                    on_quit();
+                }
+            });
+        }
+    }))
+}
+
+/// 用阻塞 IO 处理一个连接，直到连接关闭、空闲超时或请求带了 `Signal`
+pub fn serve_connection<S: Service + ?Sized>(
+    mut stream: TcpStream,
+    service: &S,
+    keep_alive: KeepAlive,
+) -> io::Result<Option<Signal>> {
+    let mut connection = Connection::new(keep_alive);
+
+    let mut buf = [0; 4096];
+    loop {
+        while let Some(outgoing) = connection.next_reply(service) {
+            let (signal, keep_alive) = (outgoing.reply.signal, outgoing.keep_alive);
+            send(&mut stream, outgoing)?;
+            if signal.is_some() || !keep_alive {
+                return Ok(signal);
+            }
+        }
+
+        // 每次读之前重新设置，收到一半的请求时只剩下整个请求的剩余时间
+        let result = match connection.read_timeout() {
+            Some(timeout) => stream.set_read_timeout(Some(timeout)).and_then(|_| stream.read(&mut buf)),
+            None => Err(ErrorKind::TimedOut.into()),
+        };
+        let n = match result {
+            Ok(n) => n,
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            // 空闲超时直接关闭连接，请求超时回复 408
+            Err(err) if http::is_timeout(&err) => {
+                if let Some(outgoing) = connection.timeout() {
+                    send(&mut stream, outgoing)?;
+                }
+                return Ok(None);
+            }
+            Err(err) => return Err(err),
+        };
+        if n == 0 {
+            if let Some(outgoing) = connection.eof() {
+                send(&mut stream, outgoing)?;
+            }
+            return Ok(None);
+        }
+        connection.receive(&buf[..n]);
+    }
+}
+
+fn send(stream: &mut TcpStream, outgoing: Outgoing) -> io::Result<()> {
+    let Outgoing { reply, head_only, .. } = outgoing;
+    if let Some(delay) = reply.delay {
+        thread::sleep(delay);
+    }
+    // 先打开文件，打开失败时还没有发送任何内容
+    let file = match &reply.file {
+        Some(body) if !head_only => Some((File::open(&body.path)?, body)),
+        _ => None,
+    };
+    stream.write_all(&reply.response.head_bytes())?;
+    match file {
+        Some((mut file, body)) => write_file(stream, &mut file, body)?,
+        None if !head_only => stream.write_all(reply.response.body_bytes())?,
+        None => {}
+    }
+    stream.flush()
+}
+
+fn write_file(stream: &mut TcpStream, file: &mut File, body: &FileBody) -> io::Result<()> {
+    if let Some(encoding) = body.compress {
+        // 边读边压缩，按 chunked 发送
+        let mut compressor = Compressor::new(encoding);
+        let mut buf = vec![0; 16 * 1024];
+        loop {
+            let n = file.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            stream.write_all(&compressor.push(&buf[..n])?)?;
+        }
+        return stream.write_all(&compressor.finish()?);
+    }
+    for part in &body.parts {
+        match part {
+            BodyPart::Bytes(bytes) => stream.write_all(bytes)?,
+            BodyPart::File { offset, len } => {
+                file.seek(SeekFrom::Start(*offset))?;
+                // 文件在取得长度之后变短时 body 不完整，关闭连接，免得客户端把下一个响应当成 body
+                if copy(&mut file.take(*len), stream)? < *len {
+                    return Err(ErrorKind::UnexpectedEof.into());
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,7 @@
+//! 在具体的运行时上处理连接：读写套接字和文件，其他都交给 `Connection` 和 `Service`。
+//! 每种运行时一个 `serve_connection`，返回请求中带的 `Signal`（如果有），
+//! 和一个在后台监听管理端口的 `spawn_admin`。
+
+pub mod async_std;
+pub mod blocking;
+pub mod tokio;
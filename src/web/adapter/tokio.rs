@@ -0,0 +1,146 @@
+use std::io::{self, SeekFrom};
+use std::sync::Arc;
+
+use ::tokio::fs::File;
+use ::tokio::io::{copy, AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use ::tokio::net::{TcpListener, TcpStream};
+use ::tokio::task::{self, JoinHandle};
+use ::tokio::time;
+
+use crate::http::KeepAlive;
+use crate::web::admin::{AdminConfig, AdminService};
+use crate::web::compression::Compressor;
+use crate::web::service::{Connection, FileBody, Outgoing, Service, Signal};
+use crate::web::static_files::BodyPart;
+
+/// 监听管理端口 `config.addr`，在后台任务中接受连接，每个连接一个任务交给 `service` 处理。
+/// `POST /quit` 的回复发完后调用 `on_quit`
+pub async fn spawn_admin<F>(config: &AdminConfig, service: AdminService, on_quit: F) -> io::Result<JoinHandle<()>>
+where
+    F: Fn() + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind(config.addr.as_str()).await?;
+    let service = Arc::new(service);
+    let on_quit = Arc::new(on_quit);
+    Ok(task::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let service = Arc::clone(&service);
+            let on_quit = Arc::clone(&on_quit);
+            task::spawn(async move {
+                if let Ok(Some(Signal::Quit)) = serve_connection(stream, service, KeepAlive::default()).await {
+                    on_quit();
+                }
+            });
+        }
+    }))
+}
+
+/// 在 tokio 上处理一个连接，直到连接关闭、空闲超时或请求带了 `Signal`。
+/// `Service::call` 可能读文件系统，在 `spawn_blocking` 的线程上执行，不占用异步任务的线程
+pub async fn serve_connection<S: Service + ?Sized + 'static>(
+    mut stream: TcpStream,
+    service: Arc<S>,
+    keep_alive: KeepAlive,
+) -> io::Result<Option<Signal>> {
+    let mut connection = Connection::new(keep_alive);
+
+    let mut buf = [0; 4096];
+    loop {
+        while let Some(outgoing) = next_reply(&mut connection, &service).await? {
+            let (signal, keep_alive) = (outgoing.reply.signal, outgoing.keep_alive);
+            send(&mut stream, outgoing).await?;
+            if signal.is_some() || !keep_alive {
+                return Ok(signal);
+            }
+        }
+
+        let read = match connection.read_timeout() {
+            Some(timeout) => time::timeout(timeout, stream.read(&mut buf)).await.ok(),
+            None => None,
+        };
+        let n = match read {
+            Some(n) => n?,
+            // 空闲超时直接关闭连接，请求超时回复 408
+            None => {
+                if let Some(outgoing) = connection.timeout() {
+                    send(&mut stream, outgoing).await?;
+                }
+                return Ok(None);
+            }
+        };
+        if n == 0 {
+            if let Some(outgoing) = connection.eof() {
+                send(&mut stream, outgoing).await?;
+            }
+            return Ok(None);
+        }
+        connection.receive(&buf[..n]);
+    }
+}
+
+async fn next_reply<S: Service + ?Sized + 'static>(
+    connection: &mut Connection,
+    service: &Arc<S>,
+) -> io::Result<Option<Outgoing>> {
+    let request = match connection.next_request() {
+        Some(Ok(request)) => request,
+        Some(Err(outgoing)) => return Ok(Some(outgoing)),
+        None => return Ok(None),
+    };
+    let service = Arc::clone(service);
+    let (request, reply) = task::spawn_blocking(move || {
+        let reply = service.call(&request);
+        (request, reply)
+    })
+    .await
+    .map_err(io::Error::other)?;
+    Ok(Some(connection.respond(&request, reply)))
+}
+
+async fn send(stream: &mut TcpStream, outgoing: Outgoing) -> io::Result<()> {
+    let Outgoing { reply, head_only, .. } = outgoing;
+    if let Some(delay) = reply.delay {
+        time::sleep(delay).await;
+    }
+    // 先打开文件，打开失败时还没有发送任何内容
+    let file = match &reply.file {
+        Some(body) if !head_only => Some((File::open(&body.path).await?, body)),
+        _ => None,
+    };
+    stream.write_all(&reply.response.head_bytes()).await?;
+    match file {
+        Some((mut file, body)) => write_file(stream, &mut file, body).await?,
+        None if !head_only => stream.write_all(reply.response.body_bytes()).await?,
+        None => {}
+    }
+    stream.flush().await
+}
+
+async fn write_file(stream: &mut TcpStream, file: &mut File, body: &FileBody) -> io::Result<()> {
+    if let Some(encoding) = body.compress {
+        // 边读边压缩，按 chunked 发送
+        let mut compressor = Compressor::new(encoding);
+        let mut buf = vec![0; 16 * 1024];
+        loop {
+            let n = file.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            stream.write_all(&compressor.push(&buf[..n])?).await?;
+        }
+        return stream.write_all(&compressor.finish()?).await;
+    }
+    for part in &body.parts {
+        match part {
+            BodyPart::Bytes(bytes) => stream.write_all(bytes).await?,
+            BodyPart::File { offset, len } => {
+                file.seek(SeekFrom::Start(*offset)).await?;
+                // 文件在取得长度之后变短时 body 不完整，关闭连接，免得客户端把下一个响应当成 body
+                if copy(&mut file.take(*len), stream).await? < *len {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,375 @@
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rand::Rng;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::http::{Request, Response};
+
+use super::router::{Context, Router};
+use super::service::{Reply, Service, Signal};
+
+/// 管理端口监听地址的环境变量
+pub const ADMIN_ADDR_ENV: &str = "RUST_WEB_ADMIN_ADDR";
+/// 管理令牌的环境变量
+pub const ADMIN_TOKEN_ENV: &str = "RUST_WEB_ADMIN_TOKEN";
+/// 没有设置 `RUST_WEB_ADMIN_ADDR` 时的监听地址，只接受本机连接
+pub const DEFAULT_ADMIN_ADDR: &str = "127.0.0.1:20084";
+
+/// 管理端口的配置
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdminConfig {
+    pub addr: String,
+    pub token: String,
+    /// 令牌是随机生成的，需要告诉使用者
+    pub generated_token: bool,
+}
+
+impl AdminConfig {
+    /// 从环境变量读取，没有设置令牌时随机生成一个
+    pub fn from_env() -> AdminConfig {
+        let addr = var(ADMIN_ADDR_ENV).unwrap_or_else(|| DEFAULT_ADMIN_ADDR.to_string());
+        match var(ADMIN_TOKEN_ENV) {
+            Some(token) => AdminConfig {
+                addr,
+                token,
+                generated_token: false,
+            },
+            None => AdminConfig {
+                addr,
+                token: generate_token(),
+                generated_token: true,
+            },
+        }
+    }
+
+    /// 启动时打印的说明：管理端口的地址，令牌是随机生成的时候还有令牌。
+    /// 通过环境变量设置的令牌不打印，以免写进日志
+    pub fn banner(&self) -> String {
+        let mut banner = format!("admin endpoint at http://{}/", self.addr);
+        if self.generated_token {
+            banner.push_str(&format!("\nadmin token: {}", self.token));
+        }
+        banner
+    }
+}
+
+/// 32 个十六进制字符的随机令牌
+pub fn generate_token() -> String {
+    let bytes: [u8; 16] = rand::thread_rng().gen();
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn var(key: &str) -> Option<String> {
+    env::var(key).ok().map(|value| value.trim().to_string()).filter(|value| !value.is_empty())
+}
+
+/// 管理接口上的操作，交给 `AdminService` 的处理函数执行
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdminAction {
+    /// `GET /status`
+    Status,
+    /// `POST /start`
+    Start,
+    /// `POST /stop`
+    Stop,
+    /// `POST /restart`
+    Restart,
+    /// `POST /port`，body 是 `{"port": 8080}`
+    Port(u16),
+    /// `POST /dir`，body 是 `{"dir": "/srv/www"}`
+    Dir(PathBuf),
+    /// `POST /listing`，body 是 `{"enabled": true}`
+    Listing(bool),
+    /// `POST /grace`，body 是 `{"seconds": 10}`：停止时等待正在处理的请求的最长时间
+    Grace(Duration),
+}
+
+impl AdminAction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AdminAction::Status => "status",
+            AdminAction::Start => "start",
+            AdminAction::Stop => "stop",
+            AdminAction::Restart => "restart",
+            AdminAction::Port(_) => "port",
+            AdminAction::Dir(_) => "dir",
+            AdminAction::Listing(_) => "listing",
+            AdminAction::Grace(_) => "grace",
+        }
+    }
+}
+
+/// 处理函数执行操作失败
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdminError {
+    /// 这个服务不支持的操作：501
+    Unsupported,
+    /// 暂时无法执行，比如服务正在退出：503
+    Unavailable(String),
+    /// 执行了但是失败，比如目录不存在、端口被占用：409
+    Failed(String),
+}
+
+impl AdminError {
+    pub fn status_code(&self) -> u16 {
+        match self {
+            AdminError::Unsupported => 501,
+            AdminError::Unavailable(_) => 503,
+            AdminError::Failed(_) => 409,
+        }
+    }
+}
+
+impl fmt::Display for AdminError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AdminError::Unsupported => write!(f, "unsupported action"),
+            AdminError::Unavailable(reason) => write!(f, "unavailable: {}", reason),
+            AdminError::Failed(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl Error for AdminError {}
+
+type ActionHandler = dyn Fn(AdminAction) -> Result<Value, AdminError> + Send + Sync;
+
+/// 管理接口：在单独的地址上监听，每个请求都要带 `Authorization: Bearer <token>`。
+///
+/// 请求和响应都是 JSON。处理函数的返回值作为 200 响应的 body，错误时回复 `{"error": ...}`：
+/// 令牌不对 401，body 不合法 400，执行失败 409，不支持的操作 501，暂时无法执行 503。
+///
+/// `POST /quit` 不交给处理函数：回复发出后连接返回 `Signal::Quit`，由调用者退出程序。
+pub struct AdminService {
+    token: String,
+    router: Router,
+    log_requests: bool,
+}
+
+impl AdminService {
+    pub fn new<F>(token: &str, handler: F) -> AdminService
+    where
+        F: Fn(AdminAction) -> Result<Value, AdminError> + Send + Sync + 'static,
+    {
+        let handler: Arc<ActionHandler> = Arc::new(handler);
+        let action = |handler: &Arc<ActionHandler>, action: AdminAction| {
+            let handler = Arc::clone(handler);
+            move |_: &Context| run(&*handler, action.clone())
+        };
+        let router = Router::new()
+            .get("/status", action(&handler, AdminAction::Status))
+            .post("/start", action(&handler, AdminAction::Start))
+            .post("/stop", action(&handler, AdminAction::Stop))
+            .post("/restart", action(&handler, AdminAction::Restart))
+            .post("/quit", |_: &Context| json_response(200, &json!({ "accepted": "quit" })))
+            .post("/port", with_body(&handler, |body: PortBody| AdminAction::Port(body.port)))
+            .post("/dir", with_body(&handler, |body: DirBody| AdminAction::Dir(body.dir)))
+            .post("/listing", with_body(&handler, |body: ListingBody| AdminAction::Listing(body.enabled)))
+            .post("/grace", with_body(&handler, |body: GraceBody| AdminAction::Grace(Duration::from_secs(body.seconds))))
+            .fallback(|_: &Context| error(404, "not found"));
+        AdminService {
+            token: token.to_string(),
+            router,
+            log_requests: false,
+        }
+    }
+
+    /// 每个请求在标准输出打印一行方法和路径，默认关闭
+    pub fn log_requests(mut self, on: bool) -> AdminService {
+        self.log_requests = on;
+        self
+    }
+
+    fn authorized(&self, request: &Request) -> bool {
+        let Some((scheme, token)) = request.header("Authorization").and_then(|value| value.trim().split_once(' ')) else {
+            return false;
+        };
+        scheme.eq_ignore_ascii_case("Bearer") && constant_time_eq(token.trim().as_bytes(), self.token.as_bytes())
+    }
+}
+
+impl Service for AdminService {
+    fn call(&self, request: &Request) -> Reply {
+        if self.log_requests {
+            println!("admin: {} {}", request.method(), request.path());
+        }
+        if !self.authorized(request) {
+            return error(401, "invalid or missing token")
+                .header("WWW-Authenticate", "Bearer")
+                .into();
+        }
+        let mut reply = Reply::new(self.router.handle(request));
+        if request.path() == "/quit" && reply.response.status() == 200 {
+            reply.signal = Some(Signal::Quit);
+        }
+        reply
+    }
+}
+
+#[derive(Deserialize)]
+struct PortBody {
+    port: u16,
+}
+
+#[derive(Deserialize)]
+struct DirBody {
+    dir: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct ListingBody {
+    enabled: bool,
+}
+
+#[derive(Deserialize)]
+struct GraceBody {
+    seconds: u64,
+}
+
+// 先把 JSON body 解析成 `T`，再转成操作
+fn with_body<T, M>(handler: &Arc<ActionHandler>, make: M) -> impl Fn(&Context) -> Response + Send + Sync + 'static
+where
+    T: DeserializeOwned,
+    M: Fn(T) -> AdminAction + Send + Sync + 'static,
+{
+    let handler = Arc::clone(handler);
+    move |context: &Context| match serde_json::from_slice::<T>(context.request().body()) {
+        Ok(body) => run(&*handler, make(body)),
+        Err(err) => error(400, &format!("invalid body: {}", err)),
+    }
+}
+
+fn run(handler: &ActionHandler, action: AdminAction) -> Response {
+    match handler(action) {
+        Ok(body) => json_response(200, &body),
+        Err(err) => error(err.status_code(), &err.to_string()),
+    }
+}
+
+fn error(status: u16, message: &str) -> Response {
+    json_response(status, &json!({ "error": message }))
+}
+
+fn json_response(status: u16, body: &Value) -> Response {
+    Response::new(status)
+        .header("Content-Type", "application/json")
+        .body(body.to_string())
+}
+
+// 比较时间只和长度有关，不会因为前缀相同而提前返回
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::RequestParser;
+
+    const TOKEN: &str = "s3cret-token";
+
+    fn request(method: &str, path: &str, authorization: Option<&str>, body: &str) -> Request {
+        let authorization = authorization.map(|value| format!("Authorization: {}\r\n", value)).unwrap_or_default();
+        let raw = format!(
+            "{} {} HTTP/1.1\r\nHost: a\r\n{}Content-Length: {}\r\n\r\n{}",
+            method,
+            path,
+            authorization,
+            body.len(),
+            body
+        );
+        RequestParser::new().parse(raw.as_bytes()).unwrap().unwrap().0
+    }
+
+    fn authorized(method: &str, path: &str, body: &str) -> Request {
+        request(method, path, Some(&format!("Bearer {}", TOKEN)), body)
+    }
+
+    fn body(reply: &Reply) -> Value {
+        serde_json::from_slice(reply.response.body_bytes()).unwrap()
+    }
+
+    fn service() -> AdminService {
+        AdminService::new(TOKEN, |action| match action {
+            AdminAction::Status => Ok(json!({ "running": true })),
+            AdminAction::Port(0) => Err(AdminError::Failed("port 0 is not allowed".to_string())),
+            AdminAction::Port(port) => Ok(json!({ "port": port })),
+            AdminAction::Stop => Err(AdminError::Unavailable("quitting".to_string())),
+            _ => Err(AdminError::Unsupported),
+        })
+    }
+
+    #[test]
+    fn rejects_bad_tokens() {
+        let service = service();
+        let wrong = format!("Bearer {}x", TOKEN);
+        let prefix = format!("Bearer {}", &TOKEN[..TOKEN.len() - 1]);
+        let basic = format!("Basic {}", TOKEN);
+        let cases = [None, Some("Bearer wrong-token!"), Some(wrong.as_str()), Some(prefix.as_str()), Some(basic.as_str()), Some("Bearer"), Some(TOKEN)];
+        for authorization in cases {
+            let reply = service.call(&request("GET", "/status", authorization, ""));
+            assert_eq!(reply.response.status(), 401, "{:?}", authorization);
+            assert_eq!(reply.response.headers().get("WWW-Authenticate"), Some("Bearer"));
+            assert_eq!(body(&reply)["error"], "invalid or missing token");
+        }
+    }
+
+    #[test]
+    fn accepts_token() {
+        let service = service();
+        let reply = service.call(&authorized("GET", "/status", ""));
+        assert_eq!(reply.response.status(), 200);
+        assert_eq!(body(&reply), json!({ "running": true }));
+        // scheme 不区分大小写
+        let reply = service.call(&request("GET", "/status", Some(&format!("bearer  {} ", TOKEN)), ""));
+        assert_eq!(reply.response.status(), 200);
+    }
+
+    #[test]
+    fn body_and_errors() {
+        let service = service();
+        let status = |method: &str, path: &str, body: &str| service.call(&authorized(method, path, body)).response.status();
+        assert_eq!(status("POST", "/port", r#"{"port": 8080}"#), 200);
+        assert_eq!(status("POST", "/port", r#"{"port": "8080"}"#), 400);
+        assert_eq!(status("POST", "/port", r#"{"port": 8080"#), 400);
+        assert_eq!(status("POST", "/port", ""), 400);
+        assert_eq!(status("POST", "/port", r#"{"port": 0}"#), 409);
+        assert_eq!(status("POST", "/stop", ""), 503);
+        assert_eq!(status("POST", "/start", ""), 501);
+        assert_eq!(status("GET", "/nope", ""), 404);
+
+        let reply = service.call(&authorized("POST", "/port", r#"{"port": 0}"#));
+        assert_eq!(body(&reply)["error"], "port 0 is not allowed");
+    }
+
+    #[test]
+    fn quit_signal_only_when_authorized() {
+        let service = service();
+        let reply = service.call(&authorized("POST", "/quit", ""));
+        assert_eq!(reply.response.status(), 200);
+        assert_eq!(reply.signal, Some(Signal::Quit));
+
+        let reply = service.call(&request("POST", "/quit", Some("Bearer nope"), ""));
+        assert_eq!(reply.response.status(), 401);
+        assert_eq!(reply.signal, None);
+        // 方法不对时也不退出
+        let reply = service.call(&authorized("GET", "/quit", ""));
+        assert_ne!(reply.response.status(), 200);
+        assert_eq!(reply.signal, None);
+    }
+
+    #[test]
+    fn constant_time_eq_compares_length() {
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"abcd"));
+        assert!(!constant_time_eq(b"", b"a"));
+    }
+}
@@ -0,0 +1,268 @@
+use std::io::{self, Write};
+
+use flate2::write::GzEncoder;
+
+use super::negotiate;
+
+/// 响应的压缩方式（`Content-Encoding`），不压缩时不用这个类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    Gzip,
+    Brotli,
+}
+
+impl Encoding {
+    // 同样可以接受时优先用前面的
+    pub(super) const ALL: [Encoding; 2] = [Encoding::Brotli, Encoding::Gzip];
+
+    /// `Content-Encoding` 中的名字
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Brotli => "br",
+        }
+    }
+
+    /// 预压缩文件的扩展名，如 `app.js.gz`
+    pub fn extension(self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gz",
+            Encoding::Brotli => "br",
+        }
+    }
+}
+
+/// 按 `Accept-Encoding` 从 `available` 中选一种压缩方式，None 表示不压缩。
+/// 没有 `Accept-Encoding` 时不压缩；q 值相同时压缩优先于 `identity`，`br` 优先于 `gzip`。
+pub fn negotiate_encoding(accept_encoding: Option<&str>, available: &[Encoding]) -> Option<Encoding> {
+    let list = negotiate::parse_list(accept_encoding?);
+    let any = negotiate::quality(&list, "*");
+    let identity = negotiate::quality(&list, "identity").or(any).unwrap_or(1.0);
+
+    let mut best: Option<(Encoding, f32)> = None;
+    for encoding in Encoding::ALL.into_iter().filter(|encoding| available.contains(encoding)) {
+        let quality = match encoding {
+            // 老的客户端会发 `x-gzip`
+            Encoding::Gzip => negotiate::quality(&list, "gzip").or_else(|| negotiate::quality(&list, "x-gzip")),
+            Encoding::Brotli => negotiate::quality(&list, "br"),
+        };
+        let quality = quality.or(any).unwrap_or(0.0);
+        let better = match best {
+            Some((_, best)) => quality > best,
+            None => true,
+        };
+        if quality > 0.0 && better {
+            best = Some((encoding, quality));
+        }
+    }
+    best.filter(|(_, quality)| *quality >= identity).map(|(encoding, _)| encoding)
+}
+
+/// 值得压缩的类型：文本和几种文本格式的应用类型。图片、音视频和压缩包本来就是压缩过的
+pub fn is_compressible(content_type: &str) -> bool {
+    let mime_type = content_type.split(';').next().unwrap_or_default().trim();
+    mime_type.starts_with("text/")
+        || matches!(
+            mime_type,
+            "application/json"
+                | "application/javascript"
+                | "application/xml"
+                | "application/wasm"
+                | "image/svg+xml"
+                | "image/x-icon"
+                | "image/bmp"
+        )
+}
+
+/// 边读文件边压缩：每次放进一块原始数据，得到压缩后的 `Transfer-Encoding: chunked` 分块。
+///
+/// 不做 IO，同步和异步的服务都可以用：依次发送 `push` 返回的字节（可能为空），最后发送 `finish` 返回的字节。
+pub struct Compressor {
+    encoder: Encoder,
+}
+
+enum Encoder {
+    Gzip(GzEncoder<Vec<u8>>),
+    Brotli(Box<brotli::CompressorWriter<Vec<u8>>>),
+}
+
+// 实时压缩用较快的级别
+const GZIP_LEVEL: u32 = 6;
+const BROTLI_QUALITY: u32 = 5;
+const BROTLI_WINDOW: u32 = 22;
+
+impl Compressor {
+    pub fn new(encoding: Encoding) -> Compressor {
+        let encoder = match encoding {
+            Encoding::Gzip => Encoder::Gzip(GzEncoder::new(Vec::new(), flate2::Compression::new(GZIP_LEVEL))),
+            Encoding::Brotli => Encoder::Brotli(Box::new(brotli::CompressorWriter::new(
+                Vec::new(),
+                0,
+                BROTLI_QUALITY,
+                BROTLI_WINDOW,
+            ))),
+        };
+        Compressor { encoder }
+    }
+
+    /// 压缩一块数据，返回可以发送的分块；压缩器还在缓冲时返回空
+    pub fn push(&mut self, input: &[u8]) -> io::Result<Vec<u8>> {
+        let output = match &mut self.encoder {
+            Encoder::Gzip(encoder) => {
+                encoder.write_all(input)?;
+                std::mem::take(encoder.get_mut())
+            }
+            Encoder::Brotli(encoder) => {
+                encoder.write_all(input)?;
+                std::mem::take(encoder.get_mut())
+            }
+        };
+        Ok(chunk(&output))
+    }
+
+    /// 结束压缩，返回剩下的数据和结束分块
+    pub fn finish(self) -> io::Result<Vec<u8>> {
+        let output = match self.encoder {
+            Encoder::Gzip(encoder) => encoder.finish()?,
+            Encoder::Brotli(encoder) => encoder.into_inner(),
+        };
+        let mut bytes = chunk(&output);
+        bytes.extend_from_slice(b"0\r\n\r\n");
+        Ok(bytes)
+    }
+}
+
+// 空的数据不能作为分块发送，空分块表示结束
+fn chunk(data: &[u8]) -> Vec<u8> {
+    if data.is_empty() {
+        return Vec::new();
+    }
+    let mut bytes = format!("{:x}\r\n", data.len()).into_bytes();
+    bytes.extend_from_slice(data);
+    bytes.extend_from_slice(b"\r\n");
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    const BOTH: [Encoding; 2] = [Encoding::Gzip, Encoding::Brotli];
+
+    #[test]
+    fn negotiate() {
+        assert_eq!(negotiate_encoding(None, &BOTH), None);
+        assert_eq!(negotiate_encoding(Some(""), &BOTH), None);
+        assert_eq!(negotiate_encoding(Some("gzip"), &BOTH), Some(Encoding::Gzip));
+        assert_eq!(negotiate_encoding(Some("x-gzip"), &BOTH), Some(Encoding::Gzip));
+        assert_eq!(negotiate_encoding(Some("GZIP, deflate"), &BOTH), Some(Encoding::Gzip));
+        assert_eq!(negotiate_encoding(Some("gzip;q=0.5, br;q=0.8, identity;q=0.1"), &BOTH), Some(Encoding::Brotli));
+        assert_eq!(negotiate_encoding(Some("gzip;q=0.9, br;q=0.8, identity;q=0.1"), &BOTH), Some(Encoding::Gzip));
+        // 没有列出 identity 时它的 q 值是 1
+        assert_eq!(negotiate_encoding(Some("gzip;q=0.5, br;q=0.8"), &BOTH), None);
+        // 只有提供了的压缩方式才能选
+        assert_eq!(negotiate_encoding(Some("br"), &[Encoding::Gzip]), None);
+        assert_eq!(negotiate_encoding(Some("br, gzip"), &[]), None);
+    }
+
+    #[test]
+    fn brotli_preferred_on_a_tie() {
+        assert_eq!(negotiate_encoding(Some("gzip, br"), &BOTH), Some(Encoding::Brotli));
+        assert_eq!(negotiate_encoding(Some("gzip;q=0.5, br;q=0.5, identity;q=0.5"), &BOTH), Some(Encoding::Brotli));
+        assert_eq!(negotiate_encoding(Some("*"), &BOTH), Some(Encoding::Brotli));
+        // q 值相同时压缩优先于 identity
+        assert_eq!(negotiate_encoding(Some("identity, gzip"), &BOTH), Some(Encoding::Gzip));
+    }
+
+    #[test]
+    fn zero_quality_is_refused() {
+        assert_eq!(negotiate_encoding(Some("gzip;q=0"), &BOTH), None);
+        assert_eq!(negotiate_encoding(Some("gzip;q=0, br"), &BOTH), Some(Encoding::Brotli));
+        assert_eq!(negotiate_encoding(Some("br;q=0, *"), &BOTH), Some(Encoding::Gzip));
+        // `*;q=0` 拒绝所有没有列出的压缩方式
+        assert_eq!(negotiate_encoding(Some("*;q=0"), &BOTH), None);
+        assert_eq!(negotiate_encoding(Some("gzip, *;q=0"), &BOTH), Some(Encoding::Gzip));
+        // `identity;q=0` 时只要有可以接受的压缩方式就压缩
+        assert_eq!(negotiate_encoding(Some("identity;q=0, gzip;q=0.1"), &BOTH), Some(Encoding::Gzip));
+        // 不压缩的 q 值更高时不压缩
+        assert_eq!(negotiate_encoding(Some("identity, gzip;q=0.5"), &BOTH), None);
+        assert_eq!(negotiate_encoding(Some("*;q=0.5, identity;q=0.1"), &BOTH), Some(Encoding::Brotli));
+    }
+
+    #[test]
+    fn compressible() {
+        assert!(is_compressible("text/html; charset=utf-8"));
+        assert!(is_compressible("application/json"));
+        assert!(is_compressible("image/svg+xml"));
+        assert!(!is_compressible("image/png"));
+        assert!(!is_compressible("application/octet-stream"));
+    }
+
+    // 解开 `Transfer-Encoding: chunked`，确认最后是结束分块
+    fn dechunk(mut bytes: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        loop {
+            let line = bytes.windows(2).position(|w| w == b"\r\n").unwrap();
+            let size = usize::from_str_radix(std::str::from_utf8(&bytes[..line]).unwrap(), 16).unwrap();
+            bytes = &bytes[line + 2..];
+            if size == 0 {
+                assert_eq!(bytes, b"\r\n");
+                return data;
+            }
+            data.extend_from_slice(&bytes[..size]);
+            assert_eq!(&bytes[size..size + 2], b"\r\n");
+            bytes = &bytes[size + 2..];
+        }
+    }
+
+    fn compress(encoding: Encoding, input: &[u8]) -> Vec<u8> {
+        let mut compressor = Compressor::new(encoding);
+        let mut output = Vec::new();
+        for block in input.chunks(1000) {
+            output.extend(compressor.push(block).unwrap());
+        }
+        output.extend(compressor.finish().unwrap());
+        dechunk(&output)
+    }
+
+    fn input() -> Vec<u8> {
+        (0..20_000).flat_map(|i| format!("line {}\n", i % 97).into_bytes()).collect()
+    }
+
+    #[test]
+    fn gzip_round_trip() {
+        let input = input();
+        let compressed = compress(Encoding::Gzip, &input);
+        assert!(compressed.len() < input.len() / 4);
+        let mut output = Vec::new();
+        flate2::read::GzDecoder::new(&compressed[..]).read_to_end(&mut output).unwrap();
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn brotli_round_trip() {
+        let input = input();
+        let compressed = compress(Encoding::Brotli, &input);
+        assert!(compressed.len() < input.len() / 4);
+        let mut output = Vec::new();
+        brotli::Decompressor::new(&compressed[..], 4096).read_to_end(&mut output).unwrap();
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn empty_input() {
+        for encoding in Encoding::ALL {
+            let compressed = compress(encoding, b"");
+            assert!(!compressed.is_empty());
+            let mut output = Vec::new();
+            match encoding {
+                Encoding::Gzip => flate2::read::GzDecoder::new(&compressed[..]).read_to_end(&mut output),
+                Encoding::Brotli => brotli::Decompressor::new(&compressed[..], 4096).read_to_end(&mut output),
+            }
+            .unwrap();
+            assert!(output.is_empty());
+        }
+    }
+}
@@ -0,0 +1,349 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+/// 指定配置文件路径的环境变量
+pub const CONFIG_ENV: &str = "RUST_WEB_CONFIG";
+
+/// `run_web_server` 的配置。
+///
+/// 配置文件是 TOML 格式，所有项都可以省略：
+///
+/// ```toml
+/// address = "0.0.0.0"
+/// port = 8080
+/// workers = 4
+/// max_workers = 32
+/// doc_root = "public"
+/// index_file = "index.html"
+///
+/// [error_pages]
+/// 404 = "404.html"
+/// 500 = "500.html"
+/// ```
+///
+/// 环境变量优先于配置文件：`RUST_WEB_ADDRESS`、`RUST_WEB_PORT`、`RUST_WEB_WORKERS`、`RUST_WEB_MAX_WORKERS`、
+/// `RUST_WEB_DOC_ROOT`、`RUST_WEB_INDEX_FILE`，错误页用 `RUST_WEB_ERROR_PAGE_404` 这样的变量。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerConfig {
+    pub address: String,
+    pub port: u16,
+    /// 平时保留的线程数
+    pub workers: usize,
+    /// 连接多时最多的线程数，小于 `workers` 时按 `workers` 算
+    pub max_workers: usize,
+    /// 页面文件所在的目录
+    pub doc_root: PathBuf,
+    /// 首页文件，相对于 `doc_root`
+    pub index_file: PathBuf,
+    /// 状态码对应的错误页文件，相对于 `doc_root`。没有配置的状态码回复纯文本
+    pub error_pages: BTreeMap<u16, PathBuf>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            address: "127.0.0.1".to_string(),
+            port: 7878,
+            workers: 4,
+            max_workers: 32,
+            doc_root: PathBuf::from("."),
+            index_file: PathBuf::from("hello.html"),
+            error_pages: BTreeMap::from([(404, PathBuf::from("404.html"))]),
+        }
+    }
+}
+
+// 配置文件的内容，没写的项保持默认值
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileConfig {
+    address: Option<String>,
+    port: Option<u16>,
+    workers: Option<usize>,
+    max_workers: Option<usize>,
+    doc_root: Option<PathBuf>,
+    index_file: Option<PathBuf>,
+    #[serde(default)]
+    error_pages: BTreeMap<String, PathBuf>,
+}
+
+impl ServerConfig {
+    /// 默认配置，加上 `RUST_WEB_CONFIG` 指定的配置文件（设置了的话），最后是其他环境变量
+    pub fn load() -> Result<ServerConfig, ConfigError> {
+        let mut config = match env::var_os(CONFIG_ENV) {
+            Some(path) => ServerConfig::from_file(path)?,
+            None => ServerConfig::default(),
+        };
+        config.apply_env()?;
+        Ok(config)
+    }
+
+    /// 读取配置文件，文件中没有的项使用默认值
+    pub fn from_file(path: impl AsRef<Path>) -> Result<ServerConfig, ConfigError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|err| ConfigError::Io {
+            path: path.to_path_buf(),
+            err,
+        })?;
+        ServerConfig::from_toml(&text)
+    }
+
+    /// 解析 TOML 格式的配置，没有的项使用默认值
+    pub fn from_toml(text: &str) -> Result<ServerConfig, ConfigError> {
+        let file: FileConfig = toml::from_str(text).map_err(ConfigError::Toml)?;
+
+        let mut config = ServerConfig::default();
+        if let Some(address) = file.address {
+            config.address = address;
+        }
+        if let Some(port) = file.port {
+            config.port = port;
+        }
+        if let Some(workers) = file.workers {
+            config.workers = workers;
+        }
+        if let Some(max_workers) = file.max_workers {
+            config.max_workers = max_workers;
+        }
+        if let Some(doc_root) = file.doc_root {
+            config.doc_root = doc_root;
+        }
+        if let Some(index_file) = file.index_file {
+            config.index_file = index_file;
+        }
+        for (status, page) in file.error_pages {
+            let status = parse_status(&format!("error_pages.{}", status), &status)?;
+            config.error_pages.insert(status, page);
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// 用 `RUST_WEB_*` 环境变量覆盖配置
+    pub fn apply_env(&mut self) -> Result<(), ConfigError> {
+        if let Some(address) = var("RUST_WEB_ADDRESS") {
+            self.address = address;
+        }
+        if let Some(port) = var("RUST_WEB_PORT") {
+            self.port = parse("RUST_WEB_PORT", &port)?;
+        }
+        if let Some(workers) = var("RUST_WEB_WORKERS") {
+            self.workers = parse("RUST_WEB_WORKERS", &workers)?;
+        }
+        if let Some(max_workers) = var("RUST_WEB_MAX_WORKERS") {
+            self.max_workers = parse("RUST_WEB_MAX_WORKERS", &max_workers)?;
+        }
+        if let Some(doc_root) = var("RUST_WEB_DOC_ROOT") {
+            self.doc_root = PathBuf::from(doc_root);
+        }
+        if let Some(index_file) = var("RUST_WEB_INDEX_FILE") {
+            self.index_file = PathBuf::from(index_file);
+        }
+        for (key, page) in env::vars() {
+            if let Some(status) = key.strip_prefix("RUST_WEB_ERROR_PAGE_") {
+                let status = parse_status(&key, status)?;
+                self.error_pages.insert(status, PathBuf::from(page));
+            }
+        }
+        self.validate()
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.workers == 0 {
+            return Err(ConfigError::InvalidValue {
+                key: "workers".to_string(),
+                value: "0".to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// 首页文件的完整路径
+    pub fn index_path(&self) -> PathBuf {
+        self.doc_root.join(&self.index_file)
+    }
+
+    /// 状态码对应的错误页文件的完整路径
+    pub fn error_page(&self, status: u16) -> Option<PathBuf> {
+        self.error_pages.get(&status).map(|page| self.doc_root.join(page))
+    }
+}
+
+fn var(key: &str) -> Option<String> {
+    env::var(key).ok().filter(|value| !value.trim().is_empty())
+}
+
+fn parse<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, ConfigError> {
+    value.trim().parse().map_err(|_| ConfigError::InvalidValue {
+        key: key.to_string(),
+        value: value.to_string(),
+    })
+}
+
+fn parse_status(key: &str, value: &str) -> Result<u16, ConfigError> {
+    match parse::<u16>(key, value)? {
+        status @ 100..=599 => Ok(status),
+        _ => Err(ConfigError::InvalidValue {
+            key: key.to_string(),
+            value: value.to_string(),
+        }),
+    }
+}
+
+/// 读取配置失败
+#[derive(Debug)]
+pub enum ConfigError {
+    /// 读配置文件失败
+    Io { path: PathBuf, err: io::Error },
+    /// 配置文件不是合法的 TOML，或者有未知的项、类型不对
+    Toml(toml::de::Error),
+    /// 配置项（或环境变量）的值不合法
+    InvalidValue { key: String, value: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, err } => write!(f, "failed to read config file {}: {}", path.display(), err),
+            ConfigError::Toml(err) => write!(f, "invalid config file: {}", err),
+            ConfigError::InvalidValue { key, value } => write!(f, "invalid value for {}: {:?}", key, value),
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConfigError::Io { err, .. } => Some(err),
+            ConfigError::Toml(err) => Some(err),
+            ConfigError::InvalidValue { .. } => None,
+        }
+    }
+}
+
+impl From<ConfigError> for io::Error {
+    fn from(err: ConfigError) -> io::Error {
+        let kind = match &err {
+            ConfigError::Io { err, .. } => err.kind(),
+            _ => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_toml() {
+        let config = ServerConfig::from_toml(
+            r#"
+            port = 8080
+            workers = 2
+            doc_root = "public"
+
+            [error_pages]
+            500 = "500.html"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.port, 8080);
+        assert_eq!(config.workers, 2);
+        // 没写的项保持默认值
+        assert_eq!(config.address, "127.0.0.1");
+        assert_eq!(config.max_workers, 32);
+        assert_eq!(config.index_path(), Path::new("public/hello.html"));
+        assert_eq!(config.error_page(404), Some(PathBuf::from("public/404.html")));
+        assert_eq!(config.error_page(500), Some(PathBuf::from("public/500.html")));
+        assert_eq!(config.error_page(503), None);
+
+        assert_eq!(ServerConfig::from_toml("").unwrap(), ServerConfig::default());
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        // 拼错的配置项不能被悄悄忽略
+        let err = ServerConfig::from_toml("port = 8080\nworker = 8\n").unwrap_err();
+        assert!(matches!(err, ConfigError::Toml(_)), "{:?}", err);
+        assert!(err.to_string().contains("worker"), "{}", err);
+
+        assert!(matches!(ServerConfig::from_toml("port = \"http\""), Err(ConfigError::Toml(_))));
+        assert!(matches!(ServerConfig::from_toml("port = 70000"), Err(ConfigError::Toml(_))));
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        match ServerConfig::from_toml("workers = 0") {
+            Err(ConfigError::InvalidValue { key, value }) => assert_eq!((key.as_str(), value.as_str()), ("workers", "0")),
+            other => panic!("{:?}", other),
+        }
+        match ServerConfig::from_toml("[error_pages]\n999 = \"999.html\"") {
+            Err(ConfigError::InvalidValue { key, .. }) => assert_eq!(key, "error_pages.999"),
+            other => panic!("{:?}", other),
+        }
+        let mut config = ServerConfig {
+            workers: 0,
+            ..ServerConfig::default()
+        };
+        assert!(matches!(config.validate(), Err(ConfigError::InvalidValue { .. })));
+        config.workers = 1;
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn missing_file() {
+        let err = ServerConfig::from_file("/nonexistent/rust_web.toml").unwrap_err();
+        assert!(matches!(&err, ConfigError::Io { err, .. } if err.kind() == io::ErrorKind::NotFound));
+        assert_eq!(io::Error::from(err).kind(), io::ErrorKind::NotFound);
+    }
+
+    // 所有读写 `RUST_WEB_*` 的断言都在这一个测试里，测试并行执行时不会互相影响
+    #[test]
+    fn env_overrides() {
+        let vars = [
+            ("RUST_WEB_ADDRESS", "0.0.0.0"),
+            ("RUST_WEB_PORT", " 9090 "),
+            ("RUST_WEB_WORKERS", "3"),
+            ("RUST_WEB_MAX_WORKERS", "6"),
+            ("RUST_WEB_DOC_ROOT", "www"),
+            ("RUST_WEB_INDEX_FILE", "index.html"),
+            ("RUST_WEB_ERROR_PAGE_500", "oops.html"),
+        ];
+        for (key, value) in vars {
+            env::set_var(key, value);
+        }
+        let mut config = ServerConfig::from_toml("port = 8080\nworkers = 2").unwrap();
+        config.apply_env().unwrap();
+        assert_eq!(config.address, "0.0.0.0");
+        assert_eq!(config.port, 9090);
+        assert_eq!((config.workers, config.max_workers), (3, 6));
+        assert_eq!(config.index_path(), Path::new("www/index.html"));
+        assert_eq!(config.error_page(500), Some(PathBuf::from("www/oops.html")));
+
+        // 空的环境变量当作没有设置
+        env::set_var("RUST_WEB_PORT", "");
+        let mut config = ServerConfig::default();
+        config.apply_env().unwrap();
+        assert_eq!(config.port, 7878);
+
+        env::set_var("RUST_WEB_PORT", "http");
+        let err = ServerConfig::default().apply_env().unwrap_err();
+        assert_eq!(err.to_string(), "invalid value for RUST_WEB_PORT: \"http\"");
+        env::set_var("RUST_WEB_PORT", "9090");
+
+        env::set_var("RUST_WEB_WORKERS", "0");
+        assert!(matches!(ServerConfig::default().apply_env(), Err(ConfigError::InvalidValue { .. })));
+
+        for (key, _) in vars {
+            env::remove_var(key);
+        }
+    }
+}
@@ -0,0 +1,325 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::json;
+
+use crate::http::{Request, Response};
+
+use super::negotiate;
+
+struct Entry {
+    name: String,
+    is_dir: bool,
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SortKey {
+    Name,
+    Size,
+    Modified,
+}
+
+impl SortKey {
+    fn as_str(self) -> &'static str {
+        match self {
+            SortKey::Name => "name",
+            SortKey::Size => "size",
+            SortKey::Modified => "modified",
+        }
+    }
+}
+
+/// 见 `StaticFiles::listing_response`，目录总在文件前面
+pub(super) fn render(request: &Request, dir: &Path, allow_dotfiles: bool) -> io::Result<Response> {
+    // 列表中的链接是相对路径，请求路径要以 `/` 结尾
+    if !request.path().ends_with('/') {
+        let location = match request.query() {
+            Some(query) => format!("{}?{}", directory_location(request.path()), query),
+            None => directory_location(request.path()),
+        };
+        return Ok(Response::new(301)
+            .header("Location", location)
+            .header("Content-Type", "text/plain; charset=utf-8")
+            .body("301 Moved Permanently"));
+    }
+
+    let mut entries = read_entries(dir, allow_dotfiles)?;
+    let sort = match request.query_param("sort") {
+        Some("size") => SortKey::Size,
+        Some("modified") => SortKey::Modified,
+        _ => SortKey::Name,
+    };
+    let descending = request.query_param("order") == Some("desc");
+    entries.sort_by(|a, b| {
+        let ordering = match sort {
+            SortKey::Name => a.name.cmp(&b.name),
+            SortKey::Size => a.size.cmp(&b.size).then_with(|| a.name.cmp(&b.name)),
+            SortKey::Modified => a.modified.cmp(&b.modified).then_with(|| a.name.cmp(&b.name)),
+        };
+        let ordering = if descending { ordering.reverse() } else { ordering };
+        b.is_dir.cmp(&a.is_dir).then(ordering)
+    });
+
+    if wants_json(request) {
+        Ok(json_listing(request.path(), &entries))
+    } else {
+        Ok(html_listing(request.path(), &entries, sort, descending))
+    }
+}
+
+fn read_entries(dir: &Path, allow_dotfiles: bool) -> io::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') && !allow_dotfiles {
+            continue;
+        }
+        // 跟随符号链接，链接失效时用链接本身的信息
+        let metadata = match fs::metadata(entry.path()) {
+            Ok(metadata) => metadata,
+            Err(_) => entry.metadata()?,
+        };
+        entries.push(Entry {
+            name,
+            is_dir: metadata.is_dir(),
+            size: if metadata.is_dir() { 0 } else { metadata.len() },
+            modified: metadata.modified().ok(),
+        });
+    }
+    Ok(entries)
+}
+
+fn wants_json(request: &Request) -> bool {
+    let Some(accept) = request.header("Accept") else {
+        return false;
+    };
+    let list = negotiate::parse_list(accept);
+    let json = negotiate::quality(&list, "application/json").unwrap_or(0.0);
+    let html = negotiate::quality(&list, "text/html").unwrap_or(0.0);
+    json > html
+}
+
+fn json_listing(path: &str, entries: &[Entry]) -> Response {
+    let entries: Vec<_> = entries
+        .iter()
+        .map(|entry| {
+            json!({
+                "name": entry.name,
+                "type": if entry.is_dir { "directory" } else { "file" },
+                "size": entry.size,
+                "modified": entry
+                    .modified
+                    .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                    .map(|since| since.as_secs()),
+            })
+        })
+        .collect();
+    let body = json!({ "path": path, "entries": entries });
+    Response::new(200)
+        .header("Content-Type", "application/json")
+        .body(body.to_string())
+}
+
+fn html_listing(path: &str, entries: &[Entry], sort: SortKey, descending: bool) -> Response {
+    let title = format!("Index of {}", escape_html(path));
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{title}</title></head>\n<body><h1>{title}</h1>\n<table>\n<tr>"
+    );
+    // 点击当前排序的列切换升降序
+    for (key, label) in [(SortKey::Name, "Name"), (SortKey::Size, "Size"), (SortKey::Modified, "Modified")] {
+        let order = if key == sort && !descending { "desc" } else { "asc" };
+        html.push_str(&format!("<th><a href=\"?sort={}&amp;order={}\">{}</a></th>", key.as_str(), order, label));
+    }
+    html.push_str("</tr>\n");
+    if path != "/" {
+        html.push_str("<tr><td><a href=\"../\">../</a></td><td></td><td></td></tr>\n");
+    }
+    for entry in entries {
+        let suffix = if entry.is_dir { "/" } else { "" };
+        let size = if entry.is_dir { "-".to_string() } else { entry.size.to_string() };
+        let modified = entry.modified.map(httpdate::fmt_http_date).unwrap_or_default();
+        html.push_str(&format!(
+            "<tr><td><a href=\"{}{}\">{}{}</a></td><td>{}</td><td>{}</td></tr>\n",
+            encode_segment(&entry.name),
+            suffix,
+            escape_html(&entry.name),
+            suffix,
+            size,
+            modified
+        ));
+    }
+    html.push_str("</table>\n</body></html>\n");
+    Response::new(200)
+        .header("Content-Type", "text/html; charset=utf-8")
+        .body(html)
+}
+
+pub(super) fn escape_html(src: &str) -> String {
+    let mut escaped = String::with_capacity(src.len());
+    for c in src.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// 目录的重定向地址：只用解码后的路径重新编码，不用原始的请求目标（绝对形式的目标会带上别人的主机名）；
+// 空的段也去掉，免得 `//host` 变成协议相对地址
+fn directory_location(path: &str) -> String {
+    let mut location = String::with_capacity(path.len() + 1);
+    for segment in path.split('/').filter(|segment| !segment.is_empty()) {
+        location.push('/');
+        location.push_str(&encode_segment(segment));
+    }
+    location.push('/');
+    location
+}
+
+// 链接中的文件名：除了不保留字符都百分号编码
+fn encode_segment(src: &str) -> String {
+    let mut encoded = String::with_capacity(src.len());
+    for b in src.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~') {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::http::RequestParser;
+
+    // 目录 `adir`、`bdir`，文件按大小 `small` < `a<"&b.txt` < `big`，还有一个点文件
+    fn listing_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rust_web_listing_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("adir")).unwrap();
+        fs::create_dir_all(dir.join("bdir")).unwrap();
+        fs::write(dir.join("small"), "1").unwrap();
+        fs::write(dir.join("big"), "1".repeat(100)).unwrap();
+        fs::write(dir.join(".hidden"), "secret").unwrap();
+        if cfg!(unix) {
+            fs::write(dir.join("a<\"&b.txt"), "1".repeat(10)).unwrap();
+        }
+        dir
+    }
+
+    fn get(target: &str, accept: Option<&str>, dir: &Path, allow_dotfiles: bool) -> (Response, String) {
+        let accept = accept.map(|accept| format!("Accept: {}\r\n", accept)).unwrap_or_default();
+        let raw = format!("GET {} HTTP/1.1\r\nHost: a\r\n{}\r\n", target, accept);
+        let request = RequestParser::new().parse(raw.as_bytes()).unwrap().unwrap().0;
+        let response = render(&request, dir, allow_dotfiles).unwrap();
+        let body = String::from_utf8(response.body_bytes().to_vec()).unwrap();
+        (response, body)
+    }
+
+    fn json_names(body: &str) -> Vec<String> {
+        let value: serde_json::Value = serde_json::from_str(body).unwrap();
+        value["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["name"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn sort_by_size_descending_directories_first() {
+        let dir = listing_dir("sort");
+        let (_, body) = get("/?sort=size&order=desc", Some("application/json"), &dir, false);
+        let mut expected = vec!["bdir", "adir", "big"];
+        if cfg!(unix) {
+            expected.push("a<\"&b.txt");
+        }
+        expected.push("small");
+        assert_eq!(json_names(&body), expected);
+
+        let (_, body) = get("/?sort=size", Some("application/json"), &dir, false);
+        expected[..2].reverse();
+        expected[2..].reverse();
+        assert_eq!(json_names(&body), expected);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn dotfiles_hidden() {
+        let dir = listing_dir("dotfiles");
+        let (_, body) = get("/", Some("application/json"), &dir, false);
+        assert!(!json_names(&body).contains(&".hidden".to_string()));
+        let (_, body) = get("/", None, &dir, false);
+        assert!(!body.contains(".hidden"));
+
+        let (_, body) = get("/", Some("application/json"), &dir, true);
+        assert!(json_names(&body).contains(&".hidden".to_string()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn accept_negotiation() {
+        let dir = listing_dir("accept");
+        let json = |accept: Option<&str>| {
+            let (response, _) = get("/", accept, &dir, false);
+            response.headers().get("Content-Type").unwrap().to_string()
+        };
+        assert_eq!(json(Some("application/json")), "application/json");
+        assert_eq!(json(Some("text/html;q=0.5, application/json")), "application/json");
+        // 没有 Accept 或同样可以接受时用 HTML
+        assert_eq!(json(None), "text/html; charset=utf-8");
+        assert_eq!(json(Some("text/html, application/json")), "text/html; charset=utf-8");
+        assert_eq!(json(Some("*/*")), "text/html; charset=utf-8");
+        assert_eq!(json(Some("application/json;q=0.5, text/html")), "text/html; charset=utf-8");
+
+        let (_, body) = get("/", Some("application/json"), &dir, false);
+        let value: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(value["path"], "/");
+        let big = value["entries"].as_array().unwrap().iter().find(|entry| entry["name"] == "big").unwrap();
+        assert_eq!(big["type"], "file");
+        assert_eq!(big["size"], 100);
+        assert!(big["modified"].is_u64());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn names_are_escaped() {
+        let dir = listing_dir("escape");
+        let (_, body) = get("/", None, &dir, false);
+        assert!(body.contains("<a href=\"a%3C%22%26b.txt\">a&lt;&quot;&amp;b.txt</a>"), "{}", body);
+        assert!(!body.contains("a<\"&b"));
+        assert!(body.contains("<a href=\"adir/\">adir/</a>"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn redirects_to_trailing_slash() {
+        let dir = listing_dir("redirect");
+        let (response, _) = get("/docs?sort=size", None, &dir, false);
+        assert_eq!(response.status(), 301);
+        assert_eq!(response.headers().get("Location"), Some("/docs/?sort=size"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn directory_location_reencodes_path() {
+        assert_eq!(directory_location("/docs"), "/docs/");
+        assert_eq!(directory_location("/a b/c#d"), "/a%20b/c%23d/");
+        assert_eq!(directory_location("//evil.example/x"), "/evil.example/x/");
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+/// 不认识的扩展名使用的类型
+pub const DEFAULT_MIME_TYPE: &str = "application/octet-stream";
+
+/// 按文件扩展名确定 `Content-Type`。
+///
+/// 内置常见的网页、图片、字体、音视频和压缩包类型，可以用 `insert` 覆盖或补充，
+/// 扩展名不区分大小写，不认识的扩展名使用 `application/octet-stream`。
+#[derive(Debug, Clone, Default)]
+pub struct MimeTypes {
+    // 扩展名（小写，不带点）到类型
+    overrides: HashMap<String, String>,
+}
+
+impl MimeTypes {
+    pub fn new() -> MimeTypes {
+        MimeTypes::default()
+    }
+
+    /// 设置扩展名对应的类型，扩展名可以带前面的点，如 `"md"` 或 `".md"`
+    pub fn insert(mut self, extension: &str, mime_type: &str) -> MimeTypes {
+        let extension = extension.trim_start_matches('.').to_ascii_lowercase();
+        self.overrides.insert(extension, mime_type.to_string());
+        self
+    }
+
+    /// 文件对应的类型
+    pub fn get(&self, path: &Path) -> &str {
+        let Some(extension) = path.extension().and_then(|ext| ext.to_str()) else {
+            return DEFAULT_MIME_TYPE;
+        };
+        let extension = extension.to_ascii_lowercase();
+        match self.overrides.get(&extension) {
+            Some(mime_type) => mime_type,
+            None => builtin(&extension).unwrap_or(DEFAULT_MIME_TYPE),
+        }
+    }
+}
+
+// 文本类型都按 UTF-8 发送
+fn builtin(extension: &str) -> Option<&'static str> {
+    let mime_type = match extension {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" | "map" => "application/json",
+        "xml" => "application/xml",
+        "txt" | "log" => "text/plain; charset=utf-8",
+        "md" => "text/markdown; charset=utf-8",
+        "csv" => "text/csv; charset=utf-8",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "svg" => "image/svg+xml",
+        "ico" => "image/x-icon",
+        "bmp" => "image/bmp",
+
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "ogg" => "audio/ogg",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "tar" => "application/x-tar",
+        _ => return None,
+    };
+    Some(mime_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_types() {
+        let types = MimeTypes::new();
+        assert_eq!(types.get(Path::new("index.html")), "text/html; charset=utf-8");
+        assert_eq!(types.get(Path::new("app.min.js")), "text/javascript; charset=utf-8");
+        assert_eq!(types.get(Path::new("dir/logo.svg")), "image/svg+xml");
+        assert_eq!(types.get(Path::new("font.woff2")), "font/woff2");
+    }
+
+    #[test]
+    fn extension_is_case_insensitive() {
+        let types = MimeTypes::new();
+        assert_eq!(types.get(Path::new("PHOTO.JPG")), "image/jpeg");
+        assert_eq!(types.get(Path::new("Index.Html")), "text/html; charset=utf-8");
+        let types = types.insert("WASM", "application/x-wasm");
+        assert_eq!(types.get(Path::new("app.wasm")), "application/x-wasm");
+        assert_eq!(types.get(Path::new("app.WaSm")), "application/x-wasm");
+    }
+
+    #[test]
+    fn insert_overrides_builtin() {
+        let types = MimeTypes::new()
+            .insert(".md", "text/plain; charset=utf-8")
+            .insert("webmanifest", "application/manifest+json");
+        assert_eq!(types.get(Path::new("README.md")), "text/plain; charset=utf-8");
+        assert_eq!(types.get(Path::new("site.webmanifest")), "application/manifest+json");
+        // 其他内置类型不受影响
+        assert_eq!(types.get(Path::new("notes.txt")), "text/plain; charset=utf-8");
+    }
+
+    #[test]
+    fn unknown_extension_is_octet_stream() {
+        let types = MimeTypes::new();
+        for path in ["data.bin", "archive.xyz", "Makefile", ".bashrc", "dir.d/file"] {
+            assert_eq!(types.get(Path::new(path)), DEFAULT_MIME_TYPE, "{}", path);
+        }
+    }
+}
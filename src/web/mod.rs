@@ -0,0 +1,370 @@
+#![allow(dead_code)]
+
+mod admin;
+mod compression;
+mod config;
+mod listing;
+mod mime;
+mod negotiate;
+mod range;
+mod router;
+mod service;
+mod static_files;
+mod vhost;
+
+pub mod adapter;
+
+use std::{fs, thread};
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::io::{self, prelude::*};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use crate::ThreadPool;
+use crate::pool::{ExecuteError, OverflowPolicy, Priority};
+use crate::http::{self, KeepAlive, Response};
+
+pub use admin::{
+    generate_token, AdminAction, AdminConfig, AdminError, AdminService, ADMIN_ADDR_ENV, ADMIN_TOKEN_ENV,
+    DEFAULT_ADMIN_ADDR,
+};
+pub use compression::{is_compressible, negotiate_encoding, Compressor, Encoding};
+pub use config::{ConfigError, ServerConfig, CONFIG_ENV};
+pub use mime::{MimeTypes, DEFAULT_MIME_TYPE};
+pub use router::{Context, Handler, Router};
+pub use service::{Connection, FileBody, FileService, Outgoing, Reply, Service, Signal};
+pub use static_files::{BodyPart, FileResponse, Resolved, StaticError, StaticFiles, Variant};
+pub use vhost::{normalize_host, VirtualHosts};
+
+// 启动 web 服务，监听失败或创建线程池失败时返回错误
+pub fn run_web_server(config: ServerConfig) -> io::Result<()> {
+    let listener = TcpListener::bind((config.address.as_str(), config.port))?;
+    println!("Listening on {}", listener.local_addr()?);
+    let pool = ThreadPool::builder()
+        .size(config.workers)
+        .max_size(config.max_workers.max(config.workers))
+        .keep_alive(WORKER_KEEP_ALIVE)
+        .queue_capacity(QUEUE_CAPACITY)
+        .overflow_policy(OverflowPolicy::Reject)
+        .build()
+        .map_err(io::Error::other)?;
+    handle_tcp_listener_use_thread_pool(listener, pool, Arc::new(routes(Arc::new(config))));
+    Ok(())
+}
+
+// 示例页面的路由
+fn routes(config: Arc<ServerConfig>) -> Router {
+    let index_config = Arc::clone(&config);
+    let sleep_config = Arc::clone(&config);
+    Router::new()
+        // 首页
+        .get("/", move |_: &Context| html_file(&index_config, 200, &index_config.index_path()))
+        .get("/sleep", move |_: &Context| {
+            // sleep 1 秒中后打开页面
+            thread::sleep(Duration::from_secs(1));
+            html_file(&sleep_config, 200, &sleep_config.index_path())
+        })
+        // 其他页面
+        .fallback(move |_: &Context| error_page(&config, 404))
+}
+
+// 读取失败时发送 500 错误页
+fn html_file(config: &ServerConfig, status: u16, path: &Path) -> Response {
+    match fs::read(path) {
+        Ok(contents) => Response::new(status)
+            .header("Content-Type", "text/html; charset=utf-8")
+            .body(contents),
+        Err(err) => {
+            eprintln!("read {} failed: {}", path.display(), err);
+            error_page(config, 500)
+        }
+    }
+}
+
+// 配置了错误页时发送错误页，没有配置或读取失败时发送纯文本，状态码不变
+fn error_page(config: &ServerConfig, status: u16) -> Response {
+    let Some(path) = config.error_page(status) else {
+        return plain_status(status);
+    };
+    match fs::read(&path) {
+        Ok(contents) => Response::new(status)
+            .header("Content-Type", "text/html; charset=utf-8")
+            .body(contents),
+        Err(err) => {
+            eprintln!("read error page {} failed: {}", path.display(), err);
+            plain_status(status)
+        }
+    }
+}
+
+fn plain_status(status: u16) -> Response {
+    Response::new(status)
+        .header("Content-Type", "text/plain; charset=utf-8")
+        .body(format!("{} {}", status, http::reason_phrase(status)))
+}
+
+// 线程都在忙时最多排队的连接数，再多的连接直接回复 503
+const QUEUE_CAPACITY: usize = 16;
+// 线程数多于 `ServerConfig::workers` 时，多出来的线程空闲 WORKER_KEEP_ALIVE 后退出
+const WORKER_KEEP_ALIVE: Duration = Duration::from_secs(30);
+// 接受连接时没有空闲线程，持久连接空闲这么久就关闭，把线程让给别的连接
+const BUSY_IDLE_TIMEOUT: Duration = Duration::from_secs(1);
+// 慢路由：第一个请求是它们的连接按低优先级排队，线程池忙时先处理别的连接
+const SLOW_ROUTES: [&str; 1] = ["/sleep"];
+
+// 使用线程池是处理 tcp 连接
+// 每个持久连接在关闭前一直占用一个线程，线程池忙时要尽快让出来
+fn handle_tcp_listener_use_thread_pool(listener: TcpListener, pool: ThreadPool, router: Arc<Router>){
+    // 交给线程池的连接数，包括正在处理和排队的
+    let in_flight = Arc::new(AtomicUsize::new(0));
+    let threads = pool.metrics().max_size;
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            // 单个连接出错（比如对方已经断开）不影响继续监听
+            Err(err) => {
+                eprintln!("accept failed: {}", err);
+                continue;
+            }
+        };
+        // 留一个句柄，任务被拒绝时用它回复 503
+        let rejected = stream.try_clone();
+        let router = Arc::clone(&router);
+        let metrics = pool.metrics();
+        let mut keep_alive = KeepAlive::default();
+        if metrics.idle == 0 || metrics.queued > 0 {
+            keep_alive.idle_timeout = BUSY_IDLE_TIMEOUT;
+        }
+        let guard = InFlight::new(&in_flight);
+
+        match pool.try_execute_with_priority(connection_priority(&stream), move || {
+            let service = PoolAware {
+                router: &router,
+                in_flight: &guard.0,
+                threads,
+            };
+            if let Err(err) = adapter::blocking::serve_connection(stream, &service, keep_alive) {
+                eprintln!("handle connection failed: {}", err);
+            }
+        }) {
+            Ok(()) => {}
+            Err(ExecuteError::QueueFull) => {
+                eprintln!("thread pool is busy, rejecting connection: {:?}", pool.metrics());
+                if let Ok(mut stream) = rejected {
+                    if let Err(err) = write_service_unavailable(&mut stream) {
+                        eprintln!("write 503 failed: {}", err);
+                    }
+                }
+            }
+            Err(ExecuteError::Closed) => break,
+        }
+    }
+
+    println!("Shutting down.");
+}
+
+// 按已经收到的请求行决定连接的优先级，不等待：请求行还没到时按普通优先级
+fn connection_priority(stream: &TcpStream) -> Priority {
+    let mut buf = [0; 256];
+    let peeked = stream.set_nonblocking(true).and_then(|()| stream.peek(&mut buf));
+    if let Err(err) = stream.set_nonblocking(false) {
+        eprintln!("set_nonblocking failed: {}", err);
+    }
+    let Ok(n) = peeked else {
+        return Priority::Normal;
+    };
+    // 请求行 `GET /sleep?x=1 HTTP/1.1`，请求目标后面还有空格才算收完整
+    let mut parts = buf[..n].split(|&b| b == b' ');
+    let target = match (parts.next(), parts.next(), parts.next()) {
+        (Some(_), Some(target), Some(_)) => target,
+        _ => return Priority::Normal,
+    };
+    let path = target.split(|&b| b == b'?').next().unwrap_or(target);
+    if SLOW_ROUTES.iter().any(|route| route.as_bytes() == path) {
+        Priority::Low
+    } else {
+        Priority::Normal
+    }
+}
+
+// 连接结束（或任务被拒绝没有执行）时减掉 `in_flight`
+struct InFlight(Arc<AtomicUsize>);
+
+impl InFlight {
+    fn new(in_flight: &Arc<AtomicUsize>) -> InFlight {
+        in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlight(Arc::clone(in_flight))
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// 有连接在排队等线程时，回复带 `Connection: close`，发完这个回复就让出线程
+struct PoolAware<'a> {
+    router: &'a Router,
+    in_flight: &'a AtomicUsize,
+    threads: usize,
+}
+
+impl Service for PoolAware<'_> {
+    fn call(&self, request: &http::Request) -> Reply {
+        let mut reply = self.router.call(request);
+        if self.in_flight.load(Ordering::SeqCst) > self.threads {
+            reply.response.set_header("Connection", "close");
+        }
+        reply
+    }
+}
+
+fn write_service_unavailable(stream: &mut TcpStream) -> io::Result<()> {
+    let response = Response::new(503)
+        .header("Retry-After", 1)
+        .header("Connection", "close")
+        .body("503 Service Unavailable");
+    stream.write_all(&response.into_bytes())?;
+    stream.flush()
+}
+
+// 使用 stream流 是处理 tcp 连接
+fn handle_tcp_listener(listener: TcpListener, router: &Router){
+    for stream in listener.incoming()  {
+        let stream = stream.unwrap();
+        if let Err(err) = handle_connection(stream, router) {
+            eprintln!("handle connection failed: {}", err);
+        }
+    }
+}
+
+// 持久连接：同一个连接上按顺序处理多个（包括流水线发来的）请求，直到客户端关闭、空闲超时或达到最大请求数
+fn handle_connection(stream: TcpStream, router: &Router) -> io::Result<()> {
+    adapter::blocking::serve_connection(stream, router, KeepAlive::default()).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::{mpsc, Mutex};
+
+    use super::*;
+
+    #[test]
+    fn full_queue_gets_503() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let pool = ThreadPool::builder()
+            .size(1)
+            .queue_capacity(1)
+            .overflow_policy(OverflowPolicy::Reject)
+            .build()
+            .unwrap();
+        let (started_sender, started) = mpsc::channel();
+        let (release, released) = mpsc::channel::<()>();
+        let (started_sender, released) = (Mutex::new(started_sender), Mutex::new(released));
+        let router = Router::new().get("/block", move |_: &Context| {
+            started_sender.lock().unwrap().send(()).unwrap();
+            let _ = released.lock().unwrap().recv();
+            Response::new(200).body("done")
+        });
+        // 监听的线程不会退出，测试结束时随进程结束
+        thread::spawn(move || handle_tcp_listener_use_thread_pool(listener, pool, Arc::new(router)));
+
+        // 第一个连接占住唯一的线程，第二个连接在队列中等待
+        let mut busy = TcpStream::connect(addr).unwrap();
+        busy.write_all(b"GET /block HTTP/1.1\r\nHost: a\r\nConnection: close\r\n\r\n").unwrap();
+        started.recv_timeout(Duration::from_secs(5)).unwrap();
+        let mut queued = TcpStream::connect(addr).unwrap();
+
+        // 队列满了，第三个连接收到 503 后被关闭，而不是直接断开
+        let mut rejected = TcpStream::connect(addr).unwrap();
+        rejected.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let mut response = String::new();
+        rejected.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"), "{}", response);
+        assert!(response.contains("Retry-After: 1\r\n"), "{}", response);
+        assert!(response.contains("Connection: close\r\n"), "{}", response);
+        assert!(response.ends_with("503 Service Unavailable"), "{}", response);
+
+        // 前面的连接照常处理
+        drop(release);
+        let mut response = String::new();
+        busy.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n") && response.ends_with("done"), "{}", response);
+        queued.write_all(b"GET /nothing HTTP/1.1\r\nHost: a\r\nConnection: close\r\n\r\n").unwrap();
+        let mut response = String::new();
+        queued.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"), "{}", response);
+    }
+
+    // 客户端发送 `data` 后，服务端接受的连接上已经能读到数据
+    fn accepted(data: &[u8]) -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        if !data.is_empty() {
+            client.write_all(data).unwrap();
+            server.peek(&mut [0]).unwrap();
+        }
+        (client, server)
+    }
+
+    #[test]
+    fn missing_page_sends_500_error_page() {
+        let doc_root = std::env::temp_dir().join(format!("rust_web_routes_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&doc_root);
+        fs::create_dir_all(&doc_root).unwrap();
+        fs::write(doc_root.join("500.html"), "<h1>oops</h1>").unwrap();
+        let mut config = ServerConfig {
+            doc_root: doc_root.clone(),
+            index_file: "missing.html".into(),
+            ..ServerConfig::default()
+        };
+        config.error_pages.insert(500, "500.html".into());
+        let router = routes(Arc::new(config));
+
+        let request = http::RequestParser::new()
+            .parse(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n")
+            .unwrap()
+            .unwrap()
+            .0;
+        let response = router.handle(&request);
+        assert_eq!(response.status(), 500);
+        assert_eq!(response.body_bytes(), b"<h1>oops</h1>");
+        // 404 错误页读不到时发送纯文本，状态码不变
+        let request = http::RequestParser::new()
+            .parse(b"GET /nothing HTTP/1.1\r\nHost: a\r\n\r\n")
+            .unwrap()
+            .unwrap()
+            .0;
+        assert_eq!(router.handle(&request).body_bytes(), b"404 Not Found");
+        fs::remove_dir_all(&doc_root).unwrap();
+    }
+
+    #[test]
+    fn slow_routes_are_low_priority() {
+        let (_client, server) = accepted(b"GET /sleep HTTP/1.1\r\n");
+        assert_eq!(connection_priority(&server), Priority::Low);
+        // 看过之后连接仍然是阻塞的，请求也没有被读走
+        let mut buf = [0; 4];
+        (&server).read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"GET ");
+
+        let (_client, server) = accepted(b"GET /sleep?x=1 HTTP/1.1\r\n");
+        assert_eq!(connection_priority(&server), Priority::Low);
+    }
+
+    #[test]
+    fn other_connections_are_normal_priority() {
+        for data in [&b"GET / HTTP/1.1\r\n"[..], b"GET /sleepy HTTP/1.1\r\n", b"GET /sleep", b""] {
+            let (_client, server) = accepted(data);
+            assert_eq!(connection_priority(&server), Priority::Normal, "{:?}", data);
+        }
+    }
+}
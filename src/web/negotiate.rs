@@ -0,0 +1,68 @@
+/// 解析 `Accept`、`Accept-Encoding` 这类带 q 值的列表，返回每一项（小写，不含参数）和它的 q 值。
+/// 没写 q 的项是 1，q 不合法的项忽略。
+pub(super) fn parse_list(header: &str) -> Vec<(String, f32)> {
+    header
+        .split(',')
+        .filter_map(|item| {
+            let mut params = item.split(';');
+            let value = params.next()?.trim().to_ascii_lowercase();
+            if value.is_empty() {
+                return None;
+            }
+            let mut quality = 1.0;
+            for param in params {
+                let Some((name, q)) = param.split_once('=') else {
+                    continue;
+                };
+                if name.trim().eq_ignore_ascii_case("q") {
+                    quality = q.trim().parse().ok().filter(|q| (0.0..=1.0).contains(q))?;
+                }
+            }
+            Some((value, quality))
+        })
+        .collect()
+}
+
+/// 列表中 `value` 的 q 值，没有列出时是 None
+pub(super) fn quality(list: &[(String, f32)], value: &str) -> Option<f32> {
+    list.iter()
+        .filter(|(item, _)| item.eq_ignore_ascii_case(value))
+        .map(|(_, quality)| *quality)
+        .reduce(f32::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            parse_list("text/HTML, application/json;q=0.9 ,*/*; q=0.1"),
+            [
+                (String::from("text/html"), 1.0),
+                (String::from("application/json"), 0.9),
+                (String::from("*/*"), 0.1),
+            ]
+        );
+        // 其他参数忽略，Q 不区分大小写
+        assert_eq!(parse_list("text/html;level=1;Q=0.5"), [(String::from("text/html"), 0.5)]);
+        assert_eq!(parse_list("gzip;q=0, identity;q=0"), [(String::from("gzip"), 0.0), (String::from("identity"), 0.0)]);
+        assert!(parse_list("").is_empty());
+        assert!(parse_list(" , ;q=1").is_empty());
+    }
+
+    #[test]
+    fn invalid_quality_is_ignored() {
+        assert_eq!(parse_list("gzip;q=2, br;q=abc, deflate;q=-1, *;q=0.3"), [(String::from("*"), 0.3)]);
+    }
+
+    #[test]
+    fn quality_of() {
+        let list = parse_list("gzip;q=0.2, br, GZIP;q=0.7");
+        // 同一项出现多次取最大的
+        assert_eq!(quality(&list, "gzip"), Some(0.7));
+        assert_eq!(quality(&list, "BR"), Some(1.0));
+        assert_eq!(quality(&list, "identity"), None);
+    }
+}
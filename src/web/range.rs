@@ -0,0 +1,168 @@
+/// `Range` 请求头中的一段，闭区间
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct ByteRange {
+    pub(super) start: u64,
+    pub(super) end: u64,
+}
+
+impl ByteRange {
+    pub(super) fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+}
+
+pub(super) enum ParsedRange {
+    /// 格式不对、不是 `bytes` 单位或段数太多，忽略 `Range`，发送整个文件
+    Ignore,
+    /// 没有一段落在文件范围内：416
+    Unsatisfiable,
+    /// 落在文件范围内的段，已经截断到文件末尾，按请求中的顺序
+    Ranges(Vec<ByteRange>),
+}
+
+// 最多接受的段数，更多时忽略 `Range`，防止用大量小段放大响应
+const MAX_RANGES: usize = 16;
+
+/// 解析 `Range: bytes=0-99,200-,-50`，`len` 是文件长度
+pub(super) fn parse(header: &str, len: u64) -> ParsedRange {
+    let Some(specs) = header.trim().strip_prefix("bytes=") else {
+        return ParsedRange::Ignore;
+    };
+
+    let mut ranges = Vec::new();
+    let mut count = 0;
+    for spec in specs.split(',').map(str::trim).filter(|spec| !spec.is_empty()) {
+        count += 1;
+        if count > MAX_RANGES {
+            return ParsedRange::Ignore;
+        }
+        let Some((first, last)) = spec.split_once('-') else {
+            return ParsedRange::Ignore;
+        };
+        if first.is_empty() {
+            // `-n`：最后 n 个字节
+            let Some(suffix) = number(last) else {
+                return ParsedRange::Ignore;
+            };
+            if suffix > 0 && len > 0 {
+                ranges.push(ByteRange {
+                    start: len.saturating_sub(suffix),
+                    end: len - 1,
+                });
+            }
+            continue;
+        }
+
+        let Some(start) = number(first) else {
+            return ParsedRange::Ignore;
+        };
+        let end = match last {
+            "" => u64::MAX,
+            last => match number(last) {
+                Some(end) if end >= start => end,
+                _ => return ParsedRange::Ignore,
+            },
+        };
+        if start < len {
+            ranges.push(ByteRange {
+                start,
+                end: end.min(len - 1),
+            });
+        }
+    }
+
+    if count == 0 {
+        ParsedRange::Ignore
+    } else if ranges.is_empty() {
+        ParsedRange::Unsatisfiable
+    } else {
+        ParsedRange::Ranges(ranges)
+    }
+}
+
+// 只接受十进制数字，`parse` 会接受的 `+1` 之类不算
+fn number(src: &str) -> Option<u64> {
+    if src.is_empty() || !src.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    src.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges(header: &str, len: u64) -> Vec<(u64, u64)> {
+        match parse(header, len) {
+            ParsedRange::Ranges(ranges) => ranges.iter().map(|range| (range.start, range.end)).collect(),
+            ParsedRange::Ignore => panic!("{:?} ignored", header),
+            ParsedRange::Unsatisfiable => panic!("{:?} unsatisfiable", header),
+        }
+    }
+
+    #[test]
+    fn single_ranges() {
+        assert_eq!(ranges("bytes=0-99", 1000), [(0, 99)]);
+        assert_eq!(ranges(" bytes=5-5 ", 10), [(5, 5)]);
+        // 结束位置超出文件时截断
+        assert_eq!(ranges("bytes=5-100", 10), [(5, 9)]);
+        assert_eq!(ByteRange { start: 5, end: 9 }.len(), 5);
+    }
+
+    #[test]
+    fn open_ended_and_suffix() {
+        assert_eq!(ranges("bytes=7-", 10), [(7, 9)]);
+        assert_eq!(ranges("bytes=-3", 10), [(7, 9)]);
+        // 后缀比文件长时是整个文件
+        assert_eq!(ranges("bytes=-100", 10), [(0, 9)]);
+    }
+
+    #[test]
+    fn multiple_ranges_keep_order() {
+        assert_eq!(ranges("bytes=8-9, 0-1,,-2", 10), [(8, 9), (0, 1), (8, 9)]);
+        // 不在文件范围内的段去掉，只要还有一段就不是 416
+        assert_eq!(ranges("bytes=20-30,0-0", 10), [(0, 0)]);
+    }
+
+    #[test]
+    fn unsatisfiable() {
+        for header in ["bytes=10-", "bytes=10-20", "bytes=-0", "bytes=20-,30-40"] {
+            assert!(matches!(parse(header, 10), ParsedRange::Unsatisfiable), "{}", header);
+        }
+        // 空文件没有可以满足的段
+        assert!(matches!(parse("bytes=-5", 0), ParsedRange::Unsatisfiable));
+        assert!(matches!(parse("bytes=0-", 0), ParsedRange::Unsatisfiable));
+    }
+
+    #[test]
+    fn ignored() {
+        let invalid = [
+            "",
+            "bytes=",
+            "bytes=,",
+            "items=0-1",
+            "bytes 0-1",
+            "bytes=1",
+            "bytes=5-4",
+            "bytes=a-b",
+            "bytes=+1-2",
+            "bytes=-",
+            "bytes=--1",
+            "bytes=0-1,x",
+            "bytes=99999999999999999999-",
+        ];
+        for header in invalid {
+            assert!(matches!(parse(header, 10), ParsedRange::Ignore), "{:?}", header);
+        }
+    }
+
+    #[test]
+    fn too_many_ranges() {
+        let header = |count: usize| format!("bytes={}", vec!["0-0"; count].join(","));
+        assert_eq!(ranges(&header(MAX_RANGES), 10).len(), MAX_RANGES);
+        assert!(matches!(parse(&header(MAX_RANGES + 1), 10), ParsedRange::Ignore));
+        // 不在文件范围内的段也算数
+        let header = format!("bytes=0-0,{}", vec!["50-60"; MAX_RANGES].join(","));
+        assert!(matches!(parse(&header, 10), ParsedRange::Ignore));
+    }
+}
@@ -0,0 +1,344 @@
+use std::sync::Arc;
+
+use crate::http::{Method, Request, Response};
+
+/// 请求处理函数
+pub type Handler = Arc<dyn Fn(&Context) -> Response + Send + Sync>;
+
+/// 传给处理函数的请求上下文：请求本身，以及从路径中提取的参数
+pub struct Context<'a> {
+    request: &'a Request,
+    params: Vec<(String, String)>,
+}
+
+impl<'a> Context<'a> {
+    pub fn request(&self) -> &'a Request {
+        self.request
+    }
+
+    /// 路径参数，`/users/:id` 中的 `id`，或 `/static/*path` 中的 `path`
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn params(&self) -> &[(String, String)] {
+        &self.params
+    }
+
+    /// 查询参数，同名参数取第一个
+    pub fn query(&self, name: &str) -> Option<&'a str> {
+        self.request.query_param(name)
+    }
+}
+
+/// 路由：按方法和路径模式把请求分发给处理函数。
+///
+/// 路径模式按 `/` 分段，每段可以是：
+/// - 字面量，如 `/users`
+/// - 参数 `:name`，匹配任意一段，如 `/users/:id`
+/// - 通配符 `*name`，只能是最后一段，匹配剩下的零或多段，如 `/static/*path`
+///
+/// 多个模式都能匹配时，从前往后逐段比较，字面量优先于参数，参数优先于通配符。
+/// 路径能匹配但方法不对时回复 405，没有 `HEAD` 路由时使用 `GET` 路由；都不匹配时交给 fallback，默认回复 404。
+pub struct Router {
+    routes: Vec<Route>,
+    fallback: Handler,
+}
+
+struct Route {
+    method: Method,
+    pattern: Pattern,
+    handler: Handler,
+}
+
+impl Default for Router {
+    fn default() -> Self {
+        Router::new()
+    }
+}
+
+impl Router {
+    pub fn new() -> Router {
+        Router {
+            routes: Vec::new(),
+            fallback: Arc::new(|_: &Context| {
+                Response::new(404)
+                    .header("Content-Type", "text/plain; charset=utf-8")
+                    .body("404 Not Found")
+            }),
+        }
+    }
+
+    /// 注册路由，模式不合法（比如通配符不是最后一段）时 panic
+    pub fn route<F>(mut self, method: Method, pattern: &str, handler: F) -> Router
+    where
+        F: Fn(&Context) -> Response + Send + Sync + 'static,
+    {
+        self.routes.push(Route {
+            method,
+            pattern: Pattern::parse(pattern),
+            handler: Arc::new(handler),
+        });
+        self
+    }
+
+    pub fn get<F>(self, pattern: &str, handler: F) -> Router
+    where
+        F: Fn(&Context) -> Response + Send + Sync + 'static,
+    {
+        self.route(Method::Get, pattern, handler)
+    }
+
+    pub fn post<F>(self, pattern: &str, handler: F) -> Router
+    where
+        F: Fn(&Context) -> Response + Send + Sync + 'static,
+    {
+        self.route(Method::Post, pattern, handler)
+    }
+
+    pub fn put<F>(self, pattern: &str, handler: F) -> Router
+    where
+        F: Fn(&Context) -> Response + Send + Sync + 'static,
+    {
+        self.route(Method::Put, pattern, handler)
+    }
+
+    pub fn delete<F>(self, pattern: &str, handler: F) -> Router
+    where
+        F: Fn(&Context) -> Response + Send + Sync + 'static,
+    {
+        self.route(Method::Delete, pattern, handler)
+    }
+
+    /// 没有路由匹配时的处理函数
+    pub fn fallback<F>(mut self, handler: F) -> Router
+    where
+        F: Fn(&Context) -> Response + Send + Sync + 'static,
+    {
+        self.fallback = Arc::new(handler);
+        self
+    }
+
+    /// 分发请求。`HEAD` 请求的响应仍然带 body，由发送的一方只发送响应头
+    pub fn handle(&self, request: &Request) -> Response {
+        let segments: Vec<&str> = split(request.path()).collect();
+
+        // 路径能匹配的路由，按优先级排序
+        let mut matched: Vec<(&Route, Vec<(String, String)>)> = self
+            .routes
+            .iter()
+            .filter_map(|route| route.pattern.matches(&segments).map(|params| (route, params)))
+            .collect();
+        matched.sort_by_key(|(route, _)| route.pattern.rank());
+
+        let method = request.method();
+        let found = matched
+            .iter()
+            .position(|(route, _)| &route.method == method)
+            .or_else(|| {
+                if *method != Method::Head {
+                    return None;
+                }
+                matched.iter().position(|(route, _)| route.method == Method::Get)
+            });
+
+        match found {
+            Some(index) => {
+                let (route, params) = matched.swap_remove(index);
+                (route.handler)(&Context { request, params })
+            }
+            None if matched.is_empty() => (self.fallback)(&Context {
+                request,
+                params: Vec::new(),
+            }),
+            None => method_not_allowed(&matched),
+        }
+    }
+}
+
+fn method_not_allowed(matched: &[(&Route, Vec<(String, String)>)]) -> Response {
+    let mut allow: Vec<&str> = Vec::new();
+    for (route, _) in matched {
+        let names: &[&str] = match route.method {
+            Method::Get => &["GET", "HEAD"],
+            _ => &[route.method.as_str()],
+        };
+        for name in names {
+            if !allow.contains(name) {
+                allow.push(name);
+            }
+        }
+    }
+    Response::new(405)
+        .header("Allow", allow.join(", "))
+        .header("Content-Type", "text/plain; charset=utf-8")
+        .body("405 Method Not Allowed")
+}
+
+fn split(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|segment| !segment.is_empty())
+}
+
+enum Segment {
+    Literal(String),
+    Param(String),
+    Wildcard(String),
+}
+
+struct Pattern {
+    segments: Vec<Segment>,
+}
+
+impl Pattern {
+    fn parse(pattern: &str) -> Pattern {
+        let parts: Vec<&str> = split(pattern).collect();
+        let segments = parts
+            .iter()
+            .enumerate()
+            .map(|(i, part)| {
+                if let Some(name) = part.strip_prefix(':') {
+                    assert!(!name.is_empty(), "empty parameter name in route {:?}", pattern);
+                    Segment::Param(name.to_string())
+                } else if let Some(name) = part.strip_prefix('*') {
+                    assert!(i + 1 == parts.len(), "wildcard must be the last segment in route {:?}", pattern);
+                    Segment::Wildcard(name.to_string())
+                } else {
+                    Segment::Literal(part.to_string())
+                }
+            })
+            .collect();
+        Pattern { segments }
+    }
+
+    /// 匹配成功时返回提取出的参数
+    fn matches(&self, path: &[&str]) -> Option<Vec<(String, String)>> {
+        let mut params = Vec::new();
+        for (i, segment) in self.segments.iter().enumerate() {
+            match segment {
+                Segment::Literal(literal) => {
+                    if path.get(i) != Some(&literal.as_str()) {
+                        return None;
+                    }
+                }
+                Segment::Param(name) => params.push((name.clone(), path.get(i)?.to_string())),
+                Segment::Wildcard(name) => {
+                    params.push((name.clone(), path[i.min(path.len())..].join("/")));
+                    return Some(params);
+                }
+            }
+        }
+        if path.len() != self.segments.len() {
+            return None;
+        }
+        Some(params)
+    }
+
+    // 用来排序：逐段比较段的种类，越小越优先；字面量内容不参与比较
+    fn rank(&self) -> Vec<u8> {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(_) => 0,
+                Segment::Param(_) => 1,
+                Segment::Wildcard(_) => 2,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::RequestParser;
+
+    fn request(method: &str, target: &str) -> Request {
+        let raw = format!("{} {} HTTP/1.1\r\nHost: a\r\n\r\n", method, target);
+        RequestParser::new().parse(raw.as_bytes()).unwrap().unwrap().0
+    }
+
+    // 回复 `路由名 参数...`，用来看是哪个路由处理的
+    fn reply(name: &'static str) -> impl Fn(&Context) -> Response + Send + Sync + 'static {
+        move |context: &Context| {
+            let params: Vec<String> = context.params().iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+            Response::new(200).body(format!("{} {}", name, params.join(" ")).trim_end().to_string())
+        }
+    }
+
+    fn body(router: &Router, method: &str, target: &str) -> (u16, String) {
+        let response = router.handle(&request(method, target));
+        (response.status(), String::from_utf8(response.body_bytes().to_vec()).unwrap())
+    }
+
+    fn router() -> Router {
+        Router::new()
+            .get("/", reply("index"))
+            .get("/users", reply("users"))
+            .post("/users", reply("create"))
+            .get("/users/:id", reply("user"))
+            .get("/users/me", reply("me"))
+            .delete("/users/:id", reply("delete"))
+            .get("/users/:id/posts/:post", reply("post"))
+            .get("/static/*path", reply("static"))
+    }
+
+    #[test]
+    fn matches_routes() {
+        let router = router();
+        assert_eq!(body(&router, "GET", "/"), (200, "index".to_string()));
+        assert_eq!(body(&router, "GET", "/users"), (200, "users".to_string()));
+        // 多余的 `/` 不影响匹配
+        assert_eq!(body(&router, "GET", "/users/"), (200, "users".to_string()));
+        assert_eq!(body(&router, "POST", "/users"), (200, "create".to_string()));
+        // 字面量优先于参数，和注册的顺序无关
+        assert_eq!(body(&router, "GET", "/users/me"), (200, "me".to_string()));
+    }
+
+    #[test]
+    fn path_params() {
+        let router = router();
+        assert_eq!(body(&router, "GET", "/users/42"), (200, "user id=42".to_string()));
+        assert_eq!(body(&router, "GET", "/users/42/posts/7"), (200, "post id=42 post=7".to_string()));
+        assert_eq!(body(&router, "GET", "/static/css/site.css"), (200, "static path=css/site.css".to_string()));
+        // 通配符可以匹配零段
+        assert_eq!(body(&router, "GET", "/static"), (200, "static path=".to_string()));
+
+        let router = Router::new().get("/search/:term", |context: &Context| {
+            Response::new(200).body(format!("{:?} {:?}", context.param("term"), context.query("page")))
+        });
+        assert_eq!(body(&router, "GET", "/search/rust?page=2"), (200, "Some(\"rust\") Some(\"2\")".to_string()));
+    }
+
+    #[test]
+    fn method_mismatch_is_405() {
+        let router = router();
+        let response = router.handle(&request("PUT", "/users/42"));
+        assert_eq!(response.status(), 405);
+        assert_eq!(response.headers().get("Allow"), Some("GET, HEAD, DELETE"));
+        let response = router.handle(&request("DELETE", "/users"));
+        assert_eq!(response.status(), 405);
+        assert_eq!(response.headers().get("Allow"), Some("GET, HEAD, POST"));
+        // 没有 HEAD 路由时用 GET 路由
+        assert_eq!(body(&router, "HEAD", "/users/42"), (200, "user id=42".to_string()));
+    }
+
+    #[test]
+    fn unmatched_path_goes_to_fallback() {
+        let router = router();
+        assert_eq!(body(&router, "GET", "/nothing"), (404, "404 Not Found".to_string()));
+        assert_eq!(body(&router, "GET", "/users/42/posts"), (404, "404 Not Found".to_string()));
+
+        let router = router.fallback(|context: &Context| Response::new(404).body(format!("no {}", context.request().path())));
+        assert_eq!(body(&router, "POST", "/nothing"), (404, "no /nothing".to_string()));
+        // 路径能匹配时方法不对不交给 fallback
+        assert_eq!(body(&router, "PUT", "/users").0, 405);
+    }
+
+    #[test]
+    #[should_panic(expected = "wildcard must be the last segment")]
+    fn wildcard_must_be_last() {
+        Router::new().get("/static/*path/more", reply("bad"));
+    }
+}
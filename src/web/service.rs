@@ -0,0 +1,423 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::http::{self, KeepAlive, Method, ParseError, Request, RequestParser, Response};
+
+use super::compression::Encoding;
+use super::listing::escape_html;
+use super::router::Router;
+use super::static_files::{BodyPart, FileResponse, Resolved, StaticError, StaticFiles};
+
+/// 决定请求的响应，不做网络 IO。同一个实现可以交给 `adapter` 中任何一种运行时的连接处理
+pub trait Service: Send + Sync {
+    fn call(&self, request: &Request) -> Reply;
+}
+
+impl Service for Router {
+    fn call(&self, request: &Request) -> Reply {
+        Reply::new(self.handle(request))
+    }
+}
+
+/// 服务对一个请求的回复
+#[derive(Debug)]
+pub struct Reply {
+    /// 响应头，没有 `file` 时也包括 body
+    pub response: Response,
+    /// 要从文件发送的 body
+    pub file: Option<FileBody>,
+    /// 发送之前先等待，用来模拟慢请求
+    pub delay: Option<Duration>,
+    /// 发送之后交给连接的调用者，连接随后关闭
+    pub signal: Option<Signal>,
+}
+
+impl Reply {
+    pub fn new(response: Response) -> Reply {
+        Reply {
+            response,
+            file: None,
+            delay: None,
+            signal: None,
+        }
+    }
+}
+
+impl From<Response> for Reply {
+    fn from(response: Response) -> Self {
+        Reply::new(response)
+    }
+}
+
+/// 从文件发送的 body，见 `StaticFiles::serve`
+#[derive(Debug)]
+pub struct FileBody {
+    /// 要打开的文件
+    pub path: PathBuf,
+    pub parts: Vec<BodyPart>,
+    pub compress: Option<Encoding>,
+}
+
+/// 回复发送之后由连接的调用者执行的命令。
+/// 退出程序会打断还没发完的回复，所以不能在 `Service::call` 中直接执行
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    /// 退出程序，见 `AdminService` 的 `POST /quit`
+    Quit,
+}
+
+// `?sleep` 等待的时间
+const SLEEP_DELAY: Duration = Duration::from_secs(4);
+
+/// 静态文件服务，加上演示用的查询参数 `?sleep`：等 4 秒再回复。
+///
+/// 停止、退出等控制命令不在这里，见 `AdminService`。
+pub struct FileService {
+    static_files: StaticFiles,
+    welcome: Option<String>,
+    log_requests: bool,
+}
+
+impl FileService {
+    pub fn new(static_files: StaticFiles) -> FileService {
+        FileService {
+            static_files,
+            welcome: None,
+            log_requests: false,
+        }
+    }
+
+    /// 根路径 `/` 回复的页面，不设置时和其他目录一样处理
+    pub fn welcome(mut self, html: &str) -> FileService {
+        self.welcome = Some(html.to_string());
+        self
+    }
+
+    /// 每个请求在标准输出打印一行方法和路径，默认关闭
+    pub fn log_requests(mut self, on: bool) -> FileService {
+        self.log_requests = on;
+        self
+    }
+
+    pub fn static_files(&self) -> &StaticFiles {
+        &self.static_files
+    }
+
+    fn respond(&self, request: &Request) -> Reply {
+        let path = request.path();
+        if let (Some(welcome), "/") = (&self.welcome, path) {
+            return Response::new(200)
+                .header("Content-Type", "text/html; charset=utf-8")
+                .body(welcome.as_str())
+                .into();
+        }
+
+        let result = match self.static_files.resolve_entry(path) {
+            Ok(Resolved::File(file_path)) => self.file(request, &file_path),
+            Ok(Resolved::Directory(dir)) => self.static_files.listing_response(request, &dir).map(Reply::new),
+            Err(err) => Err(err),
+        };
+        result.unwrap_or_else(|err| {
+            eprintln!("{}: {}", path, err);
+            let status = err.status_code();
+            Response::new(status)
+                .header("Content-Type", "text/html; charset=utf-8")
+                .body(format!("<html><body>{} {}</body></html>", http::reason_phrase(status), escape_html(path)))
+                .into()
+        })
+    }
+
+    fn file(&self, request: &Request, file_path: &Path) -> Result<Reply, StaticError> {
+        // 客户端接受时换成预压缩的文件
+        let variant = self.static_files.select(request, file_path);
+        let metadata = fs::metadata(&variant.path)?;
+        let FileResponse { response, body, compress } = self.static_files.serve(request, &variant, &metadata);
+        // 304 等没有 body 的响应不用打开文件
+        let file = (!body.is_empty()).then_some(FileBody {
+            path: variant.path,
+            parts: body,
+            compress,
+        });
+        Ok(Reply {
+            file,
+            ..Reply::new(response)
+        })
+    }
+}
+
+impl Service for FileService {
+    fn call(&self, request: &Request) -> Reply {
+        if self.log_requests {
+            println!("method: {} , path: {}", request.method(), request.path());
+        }
+        let mut reply = self.respond(request);
+        if request.query() == Some("sleep") {
+            reply.delay = Some(SLEEP_DELAY);
+        }
+        reply
+    }
+}
+
+/// `Connection` 交给调用者发送的回复
+#[derive(Debug)]
+pub struct Outgoing {
+    pub reply: Reply,
+    /// `HEAD` 请求只发送响应头
+    pub head_only: bool,
+    /// 发送后是否保持连接，false 时发送完就关闭
+    pub keep_alive: bool,
+}
+
+/// 一个连接上的 HTTP/1.1 处理，不做 IO。
+///
+/// 调用者把读到的字节交给 `receive`，再反复用 `next_reply` 取出回复依次发送（包括流水线发来的请求），
+/// 直到回复的 `keep_alive` 是 false；读到连接结束时调用 `eof`。
+pub struct Connection {
+    parser: RequestParser,
+    buffer: Vec<u8>,
+    served: usize,
+    keep_alive: KeepAlive,
+    closing: bool,
+    // 缓冲中不完整的请求开始的时间
+    request_started: Option<Instant>,
+}
+
+impl Connection {
+    pub fn new(keep_alive: KeepAlive) -> Connection {
+        Connection {
+            parser: RequestParser::new(),
+            buffer: Vec::new(),
+            served: 0,
+            keep_alive,
+            closing: false,
+            request_started: None,
+        }
+    }
+
+    /// 等待下一个请求的最长时间，超时后直接关闭连接
+    pub fn idle_timeout(&self) -> Duration {
+        self.keep_alive.idle_timeout
+    }
+
+    /// 下一次读最多等待的时间：没有收到一半的请求时是空闲超时，否则是 `request_timeout` 剩下的时间。
+    /// 返回 None 表示请求已经超时，这时调用 `timeout`
+    pub fn read_timeout(&self) -> Option<Duration> {
+        match self.request_started {
+            None => Some(self.keep_alive.idle_timeout),
+            Some(started) => self
+                .keep_alive
+                .request_timeout
+                .checked_sub(started.elapsed())
+                .filter(|remaining| !remaining.is_zero()),
+        }
+    }
+
+    pub fn receive(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+        if self.request_started.is_none() && !self.is_idle() {
+            self.request_started = Some(Instant::now());
+        }
+    }
+
+    /// 停止服务时调用：之后的回复都带 `Connection: close`，发完就关闭连接
+    pub fn close(&mut self) {
+        self.closing = true;
+    }
+
+    /// 缓冲中没有收到一半的请求，这时关闭连接不会打断请求
+    pub fn is_idle(&self) -> bool {
+        self.buffer.iter().all(|b| matches!(b, b'\r' | b'\n'))
+    }
+
+    /// 缓冲中有完整的请求时交给 `service` 处理并返回回复，需要更多数据时返回 None。
+    /// 请求格式错误时返回 400/414/431 等错误响应，发送后关闭连接
+    pub fn next_reply<S: Service + ?Sized>(&mut self, service: &S) -> Option<Outgoing> {
+        match self.next_request()? {
+            Ok(request) => {
+                let reply = service.call(&request);
+                Some(self.respond(&request, reply))
+            }
+            Err(outgoing) => Some(outgoing),
+        }
+    }
+
+    /// `next_reply` 的前一半：取出缓冲中的完整请求，需要更多数据时返回 None，请求格式错误时返回错误响应。
+    /// 调用者自己调用 `Service::call`（比如放到可以阻塞的线程上），再用 `respond` 得到要发送的回复
+    pub fn next_request(&mut self) -> Option<Result<Request, Outgoing>> {
+        match self.parser.parse(&self.buffer) {
+            Ok(Some((request, consumed))) => {
+                self.buffer.drain(..consumed);
+                // 流水线中的下一个请求从现在开始计时
+                self.request_started = (!self.is_idle()).then(Instant::now);
+                self.served += 1;
+                Some(Ok(request))
+            }
+            Ok(None) => None,
+            Err(err) => Some(Err(Outgoing::error(err))),
+        }
+    }
+
+    /// `next_reply` 的后一半：按连接的状态和 keep-alive 设置补上响应头
+    pub fn respond(&mut self, request: &Request, mut reply: Reply) -> Outgoing {
+        // 控制命令的响应发完就关闭连接，停止服务时也一样
+        if reply.signal.is_some() || self.closing {
+            reply.response.set_header("Connection", "close");
+        }
+        let keep_alive = self.keep_alive.apply(request, self.served, &mut reply.response);
+        Outgoing {
+            reply,
+            head_only: *request.method() == Method::Head,
+            keep_alive,
+        }
+    }
+
+    /// 读超时。缓冲中有不完整的请求时返回 408 的回复，发送后关闭连接
+    pub fn timeout(&mut self) -> Option<Outgoing> {
+        if self.is_idle() {
+            return None;
+        }
+        Some(Outgoing::error(ParseError::RequestTimeout))
+    }
+
+    /// 对方关闭了连接。缓冲中还有不完整的请求时返回 400 的回复
+    pub fn eof(&mut self) -> Option<Outgoing> {
+        if self.is_idle() {
+            return None;
+        }
+        Some(Outgoing::error(ParseError::UnexpectedEof))
+    }
+}
+
+impl Outgoing {
+    fn error(err: ParseError) -> Outgoing {
+        Outgoing {
+            reply: Response::from(err).into(),
+            head_only: false,
+            keep_alive: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    // 回复请求的路径
+    struct Echo;
+
+    impl Service for Echo {
+        fn call(&self, request: &Request) -> Reply {
+            Response::new(200).body(request.path()).into()
+        }
+    }
+
+    fn replies(connection: &mut Connection) -> Vec<Outgoing> {
+        std::iter::from_fn(|| connection.next_reply(&Echo)).collect()
+    }
+
+    fn header<'a>(outgoing: &'a Outgoing, name: &str) -> Option<&'a str> {
+        outgoing.reply.response.headers().get(name)
+    }
+
+    #[test]
+    fn keep_alive() {
+        let mut connection = Connection::new(KeepAlive::default());
+        connection.receive(b"GET /a HTTP/1.1\r\nHost: x\r\n\r\n");
+        let outgoing = replies(&mut connection);
+        assert_eq!(outgoing.len(), 1);
+        assert!(outgoing[0].keep_alive);
+        assert_eq!(header(&outgoing[0], "Connection"), Some("keep-alive"));
+        assert_eq!(header(&outgoing[0], "Keep-Alive"), Some("timeout=5, max=99"));
+        assert!(connection.is_idle());
+
+        // 客户端要求关闭；HTTP/1.0 默认关闭
+        for request in ["GET /b HTTP/1.1\r\nConnection: close\r\n\r\n", "GET /c HTTP/1.0\r\n\r\n"] {
+            let mut connection = Connection::new(KeepAlive::default());
+            connection.receive(request.as_bytes());
+            let outgoing = replies(&mut connection);
+            assert!(!outgoing[0].keep_alive, "{}", request);
+            assert_eq!(header(&outgoing[0], "Connection"), Some("close"));
+        }
+    }
+
+    #[test]
+    fn max_requests() {
+        let keep_alive = KeepAlive {
+            max_requests: 2,
+            ..KeepAlive::default()
+        };
+        let mut connection = Connection::new(keep_alive);
+        connection.receive(b"GET /1 HTTP/1.1\r\n\r\n");
+        let first = replies(&mut connection);
+        assert!(first[0].keep_alive);
+        assert_eq!(header(&first[0], "Keep-Alive"), Some("timeout=5, max=1"));
+        connection.receive(b"GET /2 HTTP/1.1\r\n\r\n");
+        let second = replies(&mut connection);
+        assert!(!second[0].keep_alive);
+        assert_eq!(header(&second[0], "Connection"), Some("close"));
+    }
+
+    #[test]
+    fn pipelined_requests_in_one_read() {
+        let mut connection = Connection::new(KeepAlive::default());
+        connection.receive(b"GET /1 HTTP/1.1\r\n\r\nHEAD /2 HTTP/1.1\r\n\r\nGET /3 HTTP/1.1\r\n\r\nGET /4");
+        let outgoing = replies(&mut connection);
+        let bodies: Vec<_> = outgoing.iter().map(|o| o.reply.response.body_bytes()).collect();
+        assert_eq!(bodies, [b"/1".as_slice(), b"/2", b"/3"]);
+        assert_eq!(outgoing.iter().map(|o| o.head_only).collect::<Vec<_>>(), [false, true, false]);
+        assert!(outgoing.iter().all(|o| o.keep_alive));
+        // 剩下半个请求，开始计时
+        assert!(!connection.is_idle());
+        connection.receive(b" HTTP/1.1\r\n\r\n");
+        assert_eq!(replies(&mut connection)[0].reply.response.body_bytes(), b"/4");
+    }
+
+    #[test]
+    fn closing_connection() {
+        let mut connection = Connection::new(KeepAlive::default());
+        connection.close();
+        connection.receive(b"GET / HTTP/1.1\r\n\r\n");
+        assert!(!replies(&mut connection)[0].keep_alive);
+    }
+
+    #[test]
+    fn bad_request_closes_connection() {
+        let mut connection = Connection::new(KeepAlive::default());
+        connection.receive(b"GET / HTTP/9.9\r\n\r\nGET / HTTP/1.1\r\n\r\n");
+        let outgoing = connection.next_reply(&Echo).unwrap();
+        assert_eq!(outgoing.reply.response.status(), 505);
+        assert!(!outgoing.keep_alive);
+    }
+
+    #[test]
+    fn request_timeout() {
+        let keep_alive = KeepAlive {
+            request_timeout: Duration::from_millis(20),
+            ..KeepAlive::default()
+        };
+        let mut connection = Connection::new(keep_alive);
+        // 空闲时是空闲超时，没有要回复的
+        assert_eq!(connection.read_timeout(), Some(keep_alive.idle_timeout));
+        assert!(connection.timeout().is_none());
+
+        connection.receive(b"GET / HT");
+        assert!(connection.read_timeout().unwrap() <= keep_alive.request_timeout);
+        thread::sleep(Duration::from_millis(30));
+        assert_eq!(connection.read_timeout(), None);
+        let outgoing = connection.timeout().unwrap();
+        assert_eq!(outgoing.reply.response.status(), 408);
+        assert!(!outgoing.keep_alive);
+    }
+
+    #[test]
+    fn eof_in_the_middle_of_a_request() {
+        let mut connection = Connection::new(KeepAlive::default());
+        assert!(connection.eof().is_none());
+        connection.receive(b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nab");
+        assert!(connection.next_reply(&Echo).is_none());
+        assert_eq!(connection.eof().unwrap().reply.response.status(), 400);
+    }
+}
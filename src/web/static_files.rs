@@ -0,0 +1,902 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File, Metadata};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::http::{self, Method, Request, Response, Version};
+
+use super::compression::{self, Encoding};
+use super::listing;
+use super::mime::MimeTypes;
+use super::range::{self, ParsedRange};
+
+/// 静态文件服务：把请求路径安全地映射到根目录下的文件。
+///
+/// - 路径按 `/` 分段，忽略空段和 `.`，含 `..`、反斜杠或 NUL 的路径回复 403
+/// - 以 `.` 开头的文件和目录（如 `.git`、`.env`）默认回复 403，通过符号链接访问也一样
+/// - 解析符号链接后不在根目录下的文件回复 403
+/// - 文件不存在回复 404
+/// - 目录使用其中的 `index_file`，没有时回复 403，开启了 `listing` 时回复目录列表
+///
+/// 只负责解析、打开文件和生成响应头，发送由调用者完成，所以同步和异步的服务都可以用。
+#[derive(Debug, Clone)]
+pub struct StaticFiles {
+    root: PathBuf,
+    index_file: Option<String>,
+    allow_dotfiles: bool,
+    listing: bool,
+    mime_types: MimeTypes,
+    compression: bool,
+    precompressed: bool,
+    compress_min_size: u64,
+}
+
+impl StaticFiles {
+    pub fn new(root: impl Into<PathBuf>) -> StaticFiles {
+        StaticFiles {
+            root: root.into(),
+            index_file: Some("index.html".to_string()),
+            allow_dotfiles: false,
+            listing: false,
+            mime_types: MimeTypes::new(),
+            compression: false,
+            precompressed: false,
+            compress_min_size: 1024,
+        }
+    }
+
+    /// 请求目录时使用的文件，默认是 `index.html`，None 表示不使用
+    pub fn index_file(mut self, index_file: Option<&str>) -> StaticFiles {
+        self.index_file = index_file.map(str::to_string);
+        self
+    }
+
+    /// 是否允许访问以 `.` 开头的文件和目录，默认不允许
+    pub fn allow_dotfiles(mut self, allow: bool) -> StaticFiles {
+        self.allow_dotfiles = allow;
+        self
+    }
+
+    /// 目录中没有 `index_file` 时是否列出目录内容，默认不列出
+    pub fn listing(mut self, listing: bool) -> StaticFiles {
+        self.listing = listing;
+        self
+    }
+
+    /// 设置扩展名对应的 `Content-Type`，覆盖内置的类型
+    pub fn mime_type(mut self, extension: &str, mime_type: &str) -> StaticFiles {
+        self.mime_types = self.mime_types.insert(extension, mime_type);
+        self
+    }
+
+    /// 是否按 `Accept-Encoding` 实时压缩文本类的文件，默认不压缩
+    pub fn compression(mut self, compression: bool) -> StaticFiles {
+        self.compression = compression;
+        self
+    }
+
+    /// 是否发送预压缩的文件：请求 `app.js` 且客户端接受时，发送同目录下的 `app.js.br` 或 `app.js.gz`。默认不发送
+    pub fn precompressed(mut self, precompressed: bool) -> StaticFiles {
+        self.precompressed = precompressed;
+        self
+    }
+
+    /// 小于这个大小的文件不实时压缩，默认 1024 字节
+    pub fn compress_min_size(mut self, size: u64) -> StaticFiles {
+        self.compress_min_size = size;
+        self
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// 解析请求行中原始的请求目标（可以带查询串，路径还没有百分号解码）
+    pub fn resolve_target(&self, target: &str) -> Result<PathBuf, StaticError> {
+        let path = target.split(['?', '#']).next().unwrap_or_default();
+        let path = http::percent_decode(path, false).map_err(|_| StaticError::Forbidden)?;
+        self.resolve(&path)
+    }
+
+    /// 解析已经百分号解码的请求路径（如 `Request::path`），返回根目录下规范化后的文件路径
+    pub fn resolve(&self, path: &str) -> Result<PathBuf, StaticError> {
+        match self.resolve_entry(path)? {
+            Resolved::File(file) => Ok(file),
+            Resolved::Directory(_) => Err(StaticError::Forbidden),
+        }
+    }
+
+    /// 和 `resolve` 一样，只是开启了 `listing` 时，没有 `index_file` 的目录返回 `Resolved::Directory`
+    pub fn resolve_entry(&self, path: &str) -> Result<Resolved, StaticError> {
+        if path.contains(['\\', '\0']) {
+            return Err(StaticError::Forbidden);
+        }
+
+        let mut relative = PathBuf::new();
+        for segment in path.split('/') {
+            if segment.is_empty() || segment == "." {
+                continue;
+            }
+            if segment == ".." || (segment.starts_with('.') && !self.allow_dotfiles) {
+                return Err(StaticError::Forbidden);
+            }
+            // 防止 Windows 上的 `C:` 之类被当成根目录或前缀
+            let mut components = Path::new(segment).components();
+            match (components.next(), components.next()) {
+                (Some(Component::Normal(_)), None) => relative.push(segment),
+                _ => return Err(StaticError::Forbidden),
+            }
+        }
+
+        let root = fs::canonicalize(&self.root)?;
+        let mut file = fs::canonicalize(root.join(&relative))?;
+        if !self.allowed(&root, &file) {
+            return Err(StaticError::Forbidden);
+        }
+        if fs::metadata(&file)?.is_dir() {
+            let index = match &self.index_file {
+                Some(index_file) => match fs::canonicalize(file.join(index_file)) {
+                    Ok(index) => Some(index),
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+                    Err(err) => return Err(err.into()),
+                },
+                None => None,
+            };
+            file = match index {
+                Some(index) if self.allowed(&root, &index) => index,
+                Some(_) => return Err(StaticError::Forbidden),
+                None if self.listing => return Ok(Resolved::Directory(file)),
+                None => return Err(StaticError::Forbidden),
+            };
+        }
+        if !fs::metadata(&file)?.is_file() {
+            return Err(StaticError::Forbidden);
+        }
+        Ok(Resolved::File(file))
+    }
+
+    // 解析符号链接后的路径 `path` 是否可以访问：要在根目录 `root` 下，不允许点文件时其中每一段都不能以 `.` 开头。
+    // 请求路径中的点文件已经检查过，这里防止 `cfg -> .env` 这样的链接
+    fn allowed(&self, root: &Path, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(root) else {
+            return false;
+        };
+        self.allow_dotfiles
+            || relative
+                .components()
+                .all(|component| !component.as_os_str().to_string_lossy().starts_with('.'))
+    }
+
+    /// 目录 `dir`（`resolve_entry` 返回的）的列表，`Accept` 优先 `application/json` 时是 JSON，否则是 HTML。
+    /// 按查询参数 `sort=name|size|modified` 和 `order=asc|desc` 排序；请求路径不以 `/` 结尾时回复 301
+    pub fn listing_response(&self, request: &Request, dir: &Path) -> Result<Response, StaticError> {
+        Ok(listing::render(request, dir, self.allow_dotfiles)?)
+    }
+
+    /// 解析并打开文件，同时返回文件信息（长度、修改时间等）
+    pub fn open(&self, path: &str) -> Result<(File, Metadata), StaticError> {
+        let file = File::open(self.resolve(path)?)?;
+        let metadata = file.metadata()?;
+        Ok((file, metadata))
+    }
+
+    /// 文件对应的 `Content-Type`
+    pub fn content_type(&self, path: &Path) -> &str {
+        self.mime_types.get(path)
+    }
+
+    /// 选择要发送的文件：开启了 `precompressed` 且客户端接受时是预压缩的文件，否则是 `path` 本身。
+    /// 调用者打开返回的 `Variant::path`，再用它调用 `serve`
+    pub fn select(&self, request: &Request, path: &Path) -> Variant {
+        let identity = Variant {
+            path: path.to_path_buf(),
+            original: path.to_path_buf(),
+            encoding: None,
+        };
+        if !self.precompressed || !matches!(request.method(), Method::Get | Method::Head) {
+            return identity;
+        }
+
+        let Ok(root) = fs::canonicalize(&self.root) else {
+            return identity;
+        };
+        let siblings: Vec<(Encoding, PathBuf)> = Encoding::ALL
+            .into_iter()
+            .filter_map(|encoding| {
+                let mut name = path.file_name()?.to_os_string();
+                name.push(".");
+                name.push(encoding.extension());
+                // 预压缩的文件同样不能是指向根目录之外或点文件的符号链接
+                let sibling = fs::canonicalize(path.with_file_name(name)).ok()?;
+                let is_file = fs::metadata(&sibling).ok()?.is_file();
+                (is_file && self.allowed(&root, &sibling)).then_some((encoding, sibling))
+            })
+            .collect();
+        let available: Vec<Encoding> = siblings.iter().map(|(encoding, _)| *encoding).collect();
+        match compression::negotiate_encoding(request.header("Accept-Encoding"), &available) {
+            Some(encoding) => Variant {
+                path: siblings.into_iter().find(|(e, _)| *e == encoding).map(|(_, path)| path).unwrap_or_default(),
+                original: path.to_path_buf(),
+                encoding: Some(encoding),
+            },
+            None => identity,
+        }
+    }
+
+    /// 文件的响应，处理 `GET`/`HEAD` 的条件请求和 `GET` 的 `Range`：
+    ///
+    /// - `If-None-Match` 匹配 `ETag`，或没有 `If-None-Match` 时文件在 `If-Modified-Since` 之后没有修改：304
+    /// - `Range` 中有落在文件范围内的段：206，多段时是 `multipart/byteranges`；都不在范围内：416
+    /// - 带 `If-Range` 且不匹配当前的 `ETag` 或修改时间时忽略 `Range`
+    ///
+    /// 其他情况是带整个文件的 200，开启了 `compression` 时可能需要实时压缩，见 `FileResponse::compress`。
+    /// 响应头总是带 `ETag`、`Last-Modified`（平台支持时）和 `Accept-Ranges`，压缩相关的设置开启时还带 `Vary`。
+    ///
+    /// `metadata` 是 `variant.path` 的文件信息。
+    pub fn serve(&self, request: &Request, variant: &Variant, metadata: &Metadata) -> FileResponse {
+        let len = metadata.len();
+        let modified = metadata.modified().ok().map(truncate_to_secs);
+        let content_type = self.content_type(&variant.original);
+
+        let method = request.method();
+        let conditional = matches!(method, Method::Get | Method::Head);
+        // 有 Range 时发送原始数据的片段，不实时压缩
+        let compress = match variant.encoding {
+            None if self.compression
+                && conditional
+                && request.version() == Version::Http11
+                && !request.headers().contains("Range")
+                && len >= self.compress_min_size
+                && compression::is_compressible(content_type) =>
+            {
+                compression::negotiate_encoding(request.header("Accept-Encoding"), &Encoding::ALL)
+            }
+            _ => None,
+        };
+        // 每种编码的内容不同，ETag 也要不同；实时压缩的结果不保证每次逐字节相同，用弱 ETag
+        let etag = match (variant.encoding, compress) {
+            (Some(encoding), _) => etag(metadata, Some(encoding), false),
+            (None, Some(encoding)) => etag(metadata, Some(encoding), true),
+            (None, None) => etag(metadata, None, false),
+        };
+        let file_head = |status: u16| {
+            let mut response = file_head(status, &etag, modified);
+            if self.compression || self.precompressed {
+                response.set_header("Vary", "Accept-Encoding");
+            }
+            if let Some(encoding) = variant.encoding.or(compress) {
+                response.set_header("Content-Encoding", encoding.as_str());
+            }
+            response
+        };
+
+        if conditional && not_modified(request, &etag, modified) {
+            // 304 没有 body，Content-Length 是 200 时的长度（实时压缩时不知道长度，不带）
+            let mut response = file_head(304);
+            if compress.is_none() {
+                response.set_header("Content-Length", len);
+            }
+            return FileResponse { response, body: Vec::new(), compress: None };
+        }
+        if let Some(encoding) = compress {
+            let response = file_head(200)
+                .header("Content-Type", content_type)
+                .header("Transfer-Encoding", "chunked");
+            return FileResponse {
+                response,
+                body: vec![BodyPart::File { offset: 0, len }],
+                compress: Some(encoding),
+            };
+        }
+
+        let range = match request.header("Range") {
+            Some(range) if *method == Method::Get && if_range_matches(request, &etag, modified) => range::parse(range, len),
+            _ => ParsedRange::Ignore,
+        };
+        let (response, body) = match range {
+            ParsedRange::Ignore => {
+                let response = file_head(200).header("Content-Type", content_type);
+                (response, vec![BodyPart::File { offset: 0, len }])
+            }
+            ParsedRange::Unsatisfiable => {
+                let response = file_head(416)
+                    .header("Content-Type", "text/plain; charset=utf-8")
+                    .header("Content-Range", format!("bytes */{}", len));
+                let body = format!("416 {}", http::reason_phrase(416));
+                (response, vec![BodyPart::Bytes(body.into_bytes())])
+            }
+            ParsedRange::Ranges(ranges) if ranges.len() == 1 => {
+                let range = ranges[0];
+                let response = file_head(206)
+                    .header("Content-Type", content_type)
+                    .header("Content-Range", format!("bytes {}-{}/{}", range.start, range.end, len));
+                (response, vec![BodyPart::File { offset: range.start, len: range.len() }])
+            }
+            ParsedRange::Ranges(ranges) => {
+                let boundary = format!("{:016x}", rand::random::<u64>());
+                let response = file_head(206)
+                    .header("Content-Type", format!("multipart/byteranges; boundary={}", boundary));
+                let mut body = Vec::with_capacity(ranges.len() * 2 + 1);
+                for range in ranges {
+                    let part_head = format!(
+                        "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+                        boundary, content_type, range.start, range.end, len
+                    );
+                    body.push(BodyPart::Bytes(part_head.into_bytes()));
+                    body.push(BodyPart::File { offset: range.start, len: range.len() });
+                }
+                body.push(BodyPart::Bytes(format!("\r\n--{}--\r\n", boundary).into_bytes()));
+                (response, body)
+            }
+        };
+        let content_length: u64 = body.iter().map(BodyPart::len).sum();
+        FileResponse {
+            response: response.header("Content-Length", content_length),
+            body,
+            compress: None,
+        }
+    }
+}
+
+/// `StaticFiles::resolve_entry` 的结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolved {
+    File(PathBuf),
+    /// 没有 `index_file` 的目录，只在开启了 `listing` 时出现
+    Directory(PathBuf),
+}
+
+/// `StaticFiles::select` 选中的文件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variant {
+    /// 要打开的文件
+    pub path: PathBuf,
+    /// 请求的文件，用来确定 `Content-Type`
+    pub original: PathBuf,
+    /// `path` 是预压缩的文件时是它的压缩方式
+    pub encoding: Option<Encoding>,
+}
+
+impl Variant {
+    /// 不使用预压缩文件，直接发送 `path`
+    pub fn identity(path: impl Into<PathBuf>) -> Variant {
+        let path = path.into();
+        Variant {
+            original: path.clone(),
+            path,
+            encoding: None,
+        }
+    }
+}
+
+/// `StaticFiles::serve` 的结果：响应头和按顺序发送的 body。
+/// 先发送 `response.head_bytes()`，不是 `HEAD` 请求时再依次发送 `body` 的各部分。
+#[derive(Debug)]
+pub struct FileResponse {
+    pub response: Response,
+    pub body: Vec<BodyPart>,
+    /// None 时响应头已经带了 body 的 `Content-Length`，`body` 原样发送；
+    /// Some 时响应是 `Transfer-Encoding: chunked`，`body` 要用这种方式的 `Compressor` 压缩后发送
+    pub compress: Option<Encoding>,
+}
+
+/// 响应 body 的一部分
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BodyPart {
+    Bytes(Vec<u8>),
+    /// 文件中从 `offset` 开始的 `len` 个字节
+    File { offset: u64, len: u64 },
+}
+
+impl BodyPart {
+    pub fn len(&self) -> u64 {
+        match self {
+            BodyPart::Bytes(bytes) => bytes.len() as u64,
+            BodyPart::File { len, .. } => *len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// 各种状态都带的响应头
+fn file_head(status: u16, etag: &str, modified: Option<SystemTime>) -> Response {
+    let mut response = Response::new(status)
+        .header("ETag", etag)
+        .header("Accept-Ranges", "bytes");
+    if let Some(modified) = modified {
+        response.set_header("Last-Modified", httpdate::fmt_http_date(modified));
+    }
+    response
+}
+
+// 由长度和修改时间（纳秒）生成，文件被改写时会变化；压缩过的内容加上压缩方式
+fn etag(metadata: &Metadata, encoding: Option<Encoding>, weak: bool) -> String {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    let mut etag = format!("\"{:x}-{:x}", metadata.len(), modified.as_nanos());
+    if let Some(encoding) = encoding {
+        etag.push('-');
+        etag.push_str(encoding.as_str());
+    }
+    etag.push('"');
+    if weak {
+        etag.insert_str(0, "W/");
+    }
+    etag
+}
+
+// HTTP 日期只精确到秒
+fn truncate_to_secs(time: SystemTime) -> SystemTime {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => UNIX_EPOCH + Duration::from_secs(since.as_secs()),
+        Err(_) => time,
+    }
+}
+
+// `If-None-Match` 优先，有它时忽略 `If-Modified-Since`
+fn not_modified(request: &Request, etag: &str, modified: Option<SystemTime>) -> bool {
+    if let Some(if_none_match) = request.header("If-None-Match") {
+        return etag_list_matches(if_none_match, etag);
+    }
+    let since = request
+        .header("If-Modified-Since")
+        .and_then(|date| httpdate::parse_http_date(date.trim()).ok());
+    match (since, modified) {
+        (Some(since), Some(modified)) => modified <= since,
+        _ => false,
+    }
+}
+
+// `If-None-Match` 用弱比较：忽略 `W/` 前缀
+fn etag_list_matches(list: &str, etag: &str) -> bool {
+    let etag = etag.trim_start_matches("W/");
+    let list = list.trim();
+    list == "*"
+        || list
+            .split(',')
+            .map(|tag| tag.trim().trim_start_matches("W/"))
+            .any(|tag| tag == etag)
+}
+
+// `If-Range` 用强比较：弱 ETag 永远不匹配，日期必须和修改时间完全相同
+fn if_range_matches(request: &Request, etag: &str, modified: Option<SystemTime>) -> bool {
+    let Some(if_range) = request.header("If-Range").map(str::trim) else {
+        return true;
+    };
+    if if_range.starts_with('"') || if_range.starts_with("W/") {
+        return if_range == etag;
+    }
+    match (httpdate::parse_http_date(if_range).ok(), modified) {
+        (Some(date), Some(modified)) => date == modified,
+        _ => false,
+    }
+}
+
+/// 静态文件请求失败
+#[derive(Debug)]
+pub enum StaticError {
+    /// 路径不合法或不允许访问：403
+    Forbidden,
+    /// 文件不存在：404
+    NotFound,
+    /// 其他 IO 错误：500
+    Io(io::Error),
+}
+
+impl StaticError {
+    pub fn status_code(&self) -> u16 {
+        match self {
+            StaticError::Forbidden => 403,
+            StaticError::NotFound => 404,
+            StaticError::Io(_) => 500,
+        }
+    }
+}
+
+impl From<io::Error> for StaticError {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            // 路径中间的某一段是文件而不是目录，也按文件不存在处理
+            io::ErrorKind::NotFound | io::ErrorKind::NotADirectory => StaticError::NotFound,
+            io::ErrorKind::PermissionDenied => StaticError::Forbidden,
+            _ => StaticError::Io(err),
+        }
+    }
+}
+
+impl fmt::Display for StaticError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StaticError::Forbidden => write!(f, "forbidden"),
+            StaticError::NotFound => write!(f, "not found"),
+            StaticError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for StaticError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            StaticError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<StaticError> for Response {
+    /// 错误响应，不暴露具体的 IO 错误
+    fn from(err: StaticError) -> Self {
+        let status = err.status_code();
+        Response::new(status)
+            .header("Content-Type", "text/plain; charset=utf-8")
+            .body(format!("{} {}", status, http::reason_phrase(status)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    #[cfg(unix)]
+    use std::os::unix::fs::symlink;
+    use std::path::{Path, PathBuf};
+
+    use super::*;
+    use crate::http::RequestParser;
+
+    // 每个测试用自己的目录
+    fn temp_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("rust_web_static_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    // 根目录放在 `outside` 下面，旁边是不能访问的 `secret.txt`
+    fn root_with_outside_secret(name: &str) -> (PathBuf, PathBuf) {
+        let outside = temp_root(name);
+        let root = outside.join("root");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("sub/a.txt"), "a").unwrap();
+        fs::write(outside.join("secret.txt"), "secret").unwrap();
+        (outside, root)
+    }
+
+    fn assert_rejected(result: Result<PathBuf, StaticError>, what: &str) {
+        match result {
+            Err(StaticError::Forbidden | StaticError::NotFound) => {}
+            other => panic!("{}: {:?}", what, other),
+        }
+    }
+
+    #[test]
+    fn traversal_is_rejected() {
+        let (outside, root) = root_with_outside_secret("traversal");
+        let files = StaticFiles::new(&root);
+
+        assert_eq!(files.resolve("/sub/./a.txt").unwrap(), fs::canonicalize(root.join("sub/a.txt")).unwrap());
+        for path in ["/../secret.txt", "/sub/../../secret.txt", "/sub/..", "/..\\secret.txt", "/sub\\..\\..\\secret.txt"] {
+            assert!(matches!(files.resolve(path), Err(StaticError::Forbidden)), "{}", path);
+        }
+        // 解码后是 `..` 的请求目标
+        for target in ["/%2e%2e/secret.txt", "/%2E%2E/secret.txt", "/sub%2F..%2F..%2Fsecret.txt", "/sub/%2e%2e%2f%2e%2e%2fsecret.txt"] {
+            assert!(matches!(files.resolve_target(target), Err(StaticError::Forbidden)), "{}", target);
+        }
+        // 没有解码的 `%2e%2e` 只是普通的文件名
+        assert_rejected(files.resolve("/%2e%2e/secret.txt"), "undecoded");
+        assert!(matches!(files.resolve("/sub/a.txt\0"), Err(StaticError::Forbidden)));
+        assert!(matches!(files.resolve_target("/sub/a.txt%00"), Err(StaticError::Forbidden)));
+        assert!(matches!(files.resolve_target("/%zz"), Err(StaticError::Forbidden)));
+        fs::remove_dir_all(&outside).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn symlink_outside_root_is_forbidden() {
+        let (outside, root) = root_with_outside_secret("outsidelink");
+        symlink(outside.join("secret.txt"), root.join("secret")).unwrap();
+        symlink(&outside, root.join("up")).unwrap();
+        let files = StaticFiles::new(&root);
+
+        for path in ["/secret", "/up/secret.txt"] {
+            let result = files.resolve(path);
+            assert!(matches!(result, Err(StaticError::Forbidden)), "{}: {:?}", path, result);
+        }
+        // 绕一圈又回到根目录下的可以访问
+        assert_eq!(files.resolve("/up/root/sub/a.txt").unwrap(), fs::canonicalize(root.join("sub/a.txt")).unwrap());
+        // 允许点文件也不能出根目录
+        assert!(matches!(files.clone().allow_dotfiles(true).resolve("/secret"), Err(StaticError::Forbidden)));
+        fs::remove_dir_all(&outside).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn symlink_to_dotfile_is_forbidden() {
+        let root = temp_root("dotlink");
+        fs::write(root.join(".env"), "SECRET=1").unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+        symlink(root.join(".env"), root.join("cfg")).unwrap();
+        symlink(root.join("a.txt"), root.join("ok")).unwrap();
+        let files = StaticFiles::new(&root);
+
+        assert!(matches!(files.resolve("/.env"), Err(StaticError::Forbidden)));
+        assert!(matches!(files.resolve("/cfg"), Err(StaticError::Forbidden)));
+        assert_eq!(files.resolve("/ok").unwrap(), fs::canonicalize(root.join("a.txt")).unwrap());
+        assert!(files.clone().allow_dotfiles(true).resolve("/cfg").is_ok());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn symlink_into_dot_directory_is_forbidden() {
+        let root = temp_root("dotdir");
+        fs::create_dir(root.join(".git")).unwrap();
+        fs::write(root.join(".git/config"), "[core]").unwrap();
+        fs::create_dir(root.join("site")).unwrap();
+        symlink(root.join(".git/config"), root.join("site/index.html")).unwrap();
+        symlink(root.join(".git"), root.join("repo")).unwrap();
+        let files = StaticFiles::new(&root);
+
+        // 目录的 index 文件是指向点文件的链接
+        assert!(matches!(files.resolve("/site/"), Err(StaticError::Forbidden)));
+        assert!(matches!(files.resolve("/repo/config"), Err(StaticError::Forbidden)));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn precompressed_sibling_linking_to_dotfile_is_skipped() {
+        let root = temp_root("dotsibling");
+        fs::write(root.join("app.js"), "let a = 1;").unwrap();
+        fs::write(root.join(".secret.gz"), "not gzip").unwrap();
+        symlink(root.join(".secret.gz"), root.join("app.js.gz")).unwrap();
+        let files = StaticFiles::new(&root).precompressed(true);
+        let request = crate::http::RequestParser::new()
+            .parse(b"GET /app.js HTTP/1.1\r\nHost: a\r\nAccept-Encoding: gzip\r\n\r\n")
+            .unwrap()
+            .unwrap()
+            .0;
+
+        let path = files.resolve(request.path()).unwrap();
+        let variant = files.select(&request, &path);
+        assert_eq!(variant.encoding, None);
+        assert_eq!(variant.path, path);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    fn request(head: &str) -> Request {
+        let raw = format!("GET /digits.txt HTTP/1.1\r\nHost: a\r\n{}\r\n", head);
+        RequestParser::new().parse(raw.as_bytes()).unwrap().unwrap().0
+    }
+
+    // 按 `serve` 的结果拼出完整的 body
+    fn serve(files: &StaticFiles, request: &Request) -> (Response, Vec<u8>) {
+        let path = files.resolve(request.path()).unwrap();
+        let variant = files.select(request, &path);
+        let metadata = fs::metadata(&variant.path).unwrap();
+        let FileResponse { response, body, compress } = files.serve(request, &variant, &metadata);
+        assert_eq!(compress, None);
+        let content = fs::read(&variant.path).unwrap();
+        let mut bytes = Vec::new();
+        for part in body {
+            match part {
+                BodyPart::Bytes(part) => bytes.extend_from_slice(&part),
+                BodyPart::File { offset, len } => {
+                    bytes.extend_from_slice(&content[offset as usize..(offset + len) as usize])
+                }
+            }
+        }
+        // 304 的 `Content-Length` 是 200 时的长度
+        let content_length = response.headers().get("Content-Length").unwrap();
+        let expected = if response.status() == 304 { content.len() } else { bytes.len() };
+        assert_eq!(content_length, expected.to_string());
+        (response, bytes)
+    }
+
+    fn digits(name: &str) -> (PathBuf, StaticFiles) {
+        let root = temp_root(name);
+        fs::write(root.join("digits.txt"), "0123456789").unwrap();
+        let files = StaticFiles::new(&root);
+        (root, files)
+    }
+
+    fn header<'a>(response: &'a Response, name: &str) -> Option<&'a str> {
+        response.headers().get(name)
+    }
+
+    #[test]
+    fn content_headers() {
+        let (root, files) = digits("contentheaders");
+        let modified = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        fs::File::options()
+            .write(true)
+            .open(root.join("digits.txt"))
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        let (response, bytes) = serve(&files, &request(""));
+        assert_eq!(response.status(), 200);
+        assert_eq!(header(&response, "Content-Type"), Some("text/plain; charset=utf-8"));
+        assert_eq!(header(&response, "Content-Length"), Some("10"));
+        // IMF-fixdate，总是 GMT
+        assert_eq!(header(&response, "Last-Modified"), Some("Sun, 09 Sep 2001 01:46:40 GMT"));
+        assert_eq!(bytes, b"0123456789");
+
+        let files = files.mime_type("txt", "text/x-digits");
+        let (response, _) = serve(&files, &request(""));
+        assert_eq!(header(&response, "Content-Type"), Some("text/x-digits"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn single_ranges() {
+        let (root, files) = digits("ranges");
+        let cases = [
+            ("bytes=2-4", "bytes 2-4/10", "234"),
+            ("bytes=7-", "bytes 7-9/10", "789"),
+            ("bytes=-2", "bytes 8-9/10", "89"),
+            ("bytes=5-100", "bytes 5-9/10", "56789"),
+        ];
+        for (range, content_range, body) in cases {
+            let (response, bytes) = serve(&files, &request(&format!("Range: {}\r\n", range)));
+            assert_eq!(response.status(), 206, "{}", range);
+            assert_eq!(header(&response, "Content-Range"), Some(content_range));
+            assert_eq!(bytes, body.as_bytes());
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn unsatisfiable_range() {
+        let (root, files) = digits("range416");
+        let (response, bytes) = serve(&files, &request("Range: bytes=10-20\r\n"));
+        assert_eq!(response.status(), 416);
+        assert_eq!(header(&response, "Content-Range"), Some("bytes */10"));
+        assert_eq!(bytes, b"416 Range Not Satisfiable");
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn ignored_ranges_send_whole_file() {
+        let (root, files) = digits("rangeignored");
+        let too_many = format!("Range: bytes={}\r\n", vec!["0-0"; 17].join(","));
+        for head in ["Range: bytes=5-1\r\n", "Range: lines=1-2\r\n", too_many.as_str()] {
+            let (response, bytes) = serve(&files, &request(head));
+            assert_eq!(response.status(), 200, "{}", head);
+            assert_eq!(header(&response, "Content-Range"), None);
+            assert_eq!(bytes, b"0123456789");
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn if_range() {
+        let (root, files) = digits("ifrange");
+        let (response, _) = serve(&files, &request(""));
+        let etag = header(&response, "ETag").unwrap().to_string();
+        let modified = header(&response, "Last-Modified").unwrap().to_string();
+        assert_eq!(header(&response, "Accept-Ranges"), Some("bytes"));
+
+        for if_range in [etag.as_str(), modified.as_str()] {
+            let (response, bytes) = serve(&files, &request(&format!("Range: bytes=0-1\r\nIf-Range: {}\r\n", if_range)));
+            assert_eq!((response.status(), bytes.as_slice()), (206, b"01".as_slice()), "{}", if_range);
+        }
+        let weak = format!("W/{}", etag);
+        let mismatches = ["\"other\"", weak.as_str(), "Thu, 01 Jan 1970 00:00:00 GMT", "not a date"];
+        for if_range in mismatches {
+            let (response, bytes) = serve(&files, &request(&format!("Range: bytes=0-1\r\nIf-Range: {}\r\n", if_range)));
+            assert_eq!((response.status(), bytes.as_slice()), (200, b"0123456789".as_slice()), "{}", if_range);
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn multipart_ranges() {
+        let (root, files) = digits("multipart");
+        let (response, bytes) = serve(&files, &request("Range: bytes=8-,0-1\r\n"));
+        assert_eq!(response.status(), 206);
+        assert_eq!(header(&response, "Content-Range"), None);
+        let boundary = header(&response, "Content-Type")
+            .and_then(|value| value.strip_prefix("multipart/byteranges; boundary="))
+            .unwrap();
+        let content_type = files.content_type(Path::new("digits.txt"));
+        let expected = format!(
+            "\r\n--{b}\r\nContent-Type: {t}\r\nContent-Range: bytes 8-9/10\r\n\r\n89\
+             \r\n--{b}\r\nContent-Type: {t}\r\nContent-Range: bytes 0-1/10\r\n\r\n01\
+             \r\n--{b}--\r\n",
+            b = boundary,
+            t = content_type
+        );
+        assert_eq!(String::from_utf8(bytes).unwrap(), expected);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn if_none_match() {
+        let (root, files) = digits("ifnonematch");
+        let (response, _) = serve(&files, &request(""));
+        let etag = header(&response, "ETag").unwrap().to_string();
+
+        let weak = format!("W/{}", etag);
+        let list = format!("\"other\", {}", etag);
+        for if_none_match in [etag.as_str(), weak.as_str(), list.as_str(), "*", " * "] {
+            let (response, bytes) = serve(&files, &request(&format!("If-None-Match: {}\r\n", if_none_match)));
+            assert_eq!(response.status(), 304, "{}", if_none_match);
+            assert!(bytes.is_empty());
+            assert!(response.body_bytes().is_empty());
+            assert_eq!(header(&response, "ETag"), Some(etag.as_str()));
+        }
+        for if_none_match in ["\"other\"", "\"other\", W/\"another\""] {
+            let (response, bytes) = serve(&files, &request(&format!("If-None-Match: {}\r\n", if_none_match)));
+            assert_eq!((response.status(), bytes.as_slice()), (200, b"0123456789".as_slice()), "{}", if_none_match);
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn if_modified_since() {
+        let (root, files) = digits("ifmodifiedsince");
+        let (response, _) = serve(&files, &request(""));
+        let modified = header(&response, "Last-Modified").unwrap().to_string();
+        let modified_time = httpdate::parse_http_date(&modified).unwrap();
+        let older = httpdate::fmt_http_date(modified_time - Duration::from_secs(1));
+        let newer = httpdate::fmt_http_date(modified_time + Duration::from_secs(60));
+
+        for (since, status) in [(&modified, 304), (&newer, 304), (&older, 200), (&"garbage".to_string(), 200)] {
+            let (response, bytes) = serve(&files, &request(&format!("If-Modified-Since: {}\r\n", since)));
+            assert_eq!(response.status(), status, "{}", since);
+            assert_eq!(bytes.is_empty(), status == 304);
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn if_none_match_takes_precedence() {
+        let (root, files) = digits("precedence");
+        let (response, _) = serve(&files, &request(""));
+        let etag = header(&response, "ETag").unwrap().to_string();
+        let modified = header(&response, "Last-Modified").unwrap().to_string();
+        let epoch = httpdate::fmt_http_date(UNIX_EPOCH);
+
+        // 日期说没修改，但 ETag 不匹配
+        let head = format!("If-None-Match: \"other\"\r\nIf-Modified-Since: {}\r\n", modified);
+        assert_eq!(serve(&files, &request(&head)).0.status(), 200);
+        // 日期说修改过，但 ETag 匹配
+        let head = format!("If-None-Match: {}\r\nIf-Modified-Since: {}\r\n", etag, epoch);
+        assert_eq!(serve(&files, &request(&head)).0.status(), 304);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn not_modified_for_compressed_variant_has_no_content_length() {
+        let root = temp_root("compressed304");
+        fs::write(root.join("page.html"), "<p>hello</p>\n".repeat(200)).unwrap();
+        let files = StaticFiles::new(&root).compression(true);
+        let raw = |extra: &str| format!("GET /page.html HTTP/1.1\r\nAccept-Encoding: gzip\r\n{}\r\n", extra);
+        let parse = |raw: String| RequestParser::new().parse(raw.as_bytes()).unwrap().unwrap().0;
+        let path = files.resolve("/page.html").unwrap();
+        let metadata = fs::metadata(&path).unwrap();
+
+        let request = parse(raw(""));
+        let first = files.serve(&request, &files.select(&request, &path), &metadata);
+        assert_eq!(first.compress, Some(Encoding::Gzip));
+        let etag = first.response.headers().get("ETag").unwrap().to_string();
+        assert!(etag.starts_with("W/"));
+
+        let request = parse(raw(&format!("If-None-Match: {}\r\n", etag)));
+        let FileResponse { response, body, compress } = files.serve(&request, &files.select(&request, &path), &metadata);
+        assert_eq!((response.status(), compress), (304, None));
+        assert!(body.is_empty());
+        assert_eq!(response.headers().get("Content-Encoding"), Some("gzip"));
+        let head = String::from_utf8(response.head_bytes()).unwrap();
+        assert!(!head.contains("Content-Length"), "{}", head);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
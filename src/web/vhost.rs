@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::http::Request;
+
+use super::service::{Reply, Service};
+
+/// 按 `Host` 头选择服务的虚拟主机。
+///
+/// 主机名不区分大小写，忽略端口和末尾的 `.`；没有 `Host` 头或没有匹配的主机名时交给默认服务。
+#[derive(Clone)]
+pub struct VirtualHosts {
+    default: Arc<dyn Service>,
+    hosts: HashMap<String, Arc<dyn Service>>,
+}
+
+impl VirtualHosts {
+    pub fn new(default: Arc<dyn Service>) -> VirtualHosts {
+        VirtualHosts {
+            default,
+            hosts: HashMap::new(),
+        }
+    }
+
+    /// 添加主机名对应的服务，同名的会被替换
+    pub fn host(mut self, name: &str, service: Arc<dyn Service>) -> VirtualHosts {
+        self.hosts.insert(normalize_host(name), service);
+        self
+    }
+
+    /// 请求交给哪个服务处理
+    pub fn select(&self, request: &Request) -> &Arc<dyn Service> {
+        request
+            .header("Host")
+            .and_then(|host| self.hosts.get(&normalize_host(host)))
+            .unwrap_or(&self.default)
+    }
+}
+
+impl Service for VirtualHosts {
+    fn call(&self, request: &Request) -> Reply {
+        self.select(request).call(request)
+    }
+}
+
+/// `Host` 头中的主机名：去掉端口和末尾的 `.`，转成小写。IPv6 地址保留方括号，如 `[::1]`
+pub fn normalize_host(host: &str) -> String {
+    let host = host.trim();
+    let name = match host.strip_prefix('[') {
+        Some(rest) => match rest.split_once(']') {
+            Some((addr, _)) => return format!("[{}]", addr.to_ascii_lowercase()),
+            None => host,
+        },
+        None => host.split(':').next().unwrap_or_default(),
+    };
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{RequestParser, Response};
+
+    // 回复自己名字的服务
+    struct Named(&'static str);
+
+    impl Service for Named {
+        fn call(&self, _: &Request) -> Reply {
+            Response::new(200).body(self.0).into()
+        }
+    }
+
+    fn hosts() -> VirtualHosts {
+        VirtualHosts::new(Arc::new(Named("default")))
+            .host("Example.COM", Arc::new(Named("example")))
+            .host("localhost", Arc::new(Named("local")))
+            .host("[::1]", Arc::new(Named("ipv6")))
+    }
+
+    fn served_by(hosts: &VirtualHosts, head: &str) -> String {
+        let raw = format!("GET / {}\r\n\r\n", head);
+        let request = RequestParser::new().parse(raw.as_bytes()).unwrap().unwrap().0;
+        let reply = hosts.call(&request);
+        String::from_utf8(reply.response.body_bytes().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn normalize() {
+        assert_eq!(normalize_host("example.com"), "example.com");
+        assert_eq!(normalize_host("example.com:8080"), "example.com");
+        assert_eq!(normalize_host("example.com."), "example.com");
+        assert_eq!(normalize_host("Example.COM.:443"), "example.com");
+        assert_eq!(normalize_host(" localhost "), "localhost");
+        assert_eq!(normalize_host("[::1]:8080"), "[::1]");
+        assert_eq!(normalize_host("[::1]"), "[::1]");
+        assert_eq!(normalize_host("[2001:DB8::1]"), "[2001:db8::1]");
+        assert_eq!(normalize_host("127.0.0.1:7878"), "127.0.0.1");
+        assert_eq!(normalize_host(""), "");
+    }
+
+    #[test]
+    fn select_by_host() {
+        let hosts = hosts();
+        assert_eq!(served_by(&hosts, "HTTP/1.1\r\nHost: example.com"), "example");
+        assert_eq!(served_by(&hosts, "HTTP/1.1\r\nHost: EXAMPLE.com:8080"), "example");
+        assert_eq!(served_by(&hosts, "HTTP/1.1\r\nHost: example.com."), "example");
+        assert_eq!(served_by(&hosts, "HTTP/1.1\r\nHost: localhost:7878"), "local");
+        assert_eq!(served_by(&hosts, "HTTP/1.1\r\nHost: [::1]:8080"), "ipv6");
+    }
+
+    #[test]
+    fn unknown_or_missing_host_goes_to_default() {
+        let hosts = hosts();
+        assert_eq!(served_by(&hosts, "HTTP/1.1\r\nHost: other.example"), "default");
+        assert_eq!(served_by(&hosts, "HTTP/1.1\r\nHost: www.example.com"), "default");
+        assert_eq!(served_by(&hosts, "HTTP/1.0"), "default");
+    }
+
+    #[test]
+    fn host_replaces_same_name() {
+        let hosts = hosts().host("EXAMPLE.com.", Arc::new(Named("replaced")));
+        assert_eq!(served_by(&hosts, "HTTP/1.1\r\nHost: example.com"), "replaced");
+    }
+}